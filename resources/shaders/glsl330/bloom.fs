@@ -13,14 +13,14 @@ out vec4 finalColor;
 
 // NOTE: Add your custom variables here
 
-const vec2 size = vec2(800, 450);   // Framebuffer size
+uniform vec2 textureSize;           // Framebuffer size, passed from code
 const float samples = 5.0;          // Pixels per axis; higher = bigger glow, worse performance
-const float quality = 2.5;          // Defines size factor: Lower = smaller glow, better quality
+uniform float quality = 2.5;          // Defines size factor: Lower = smaller glow, better quality
 
 void main()
 {
     vec4 sum = vec4(0);
-    vec2 sizeFactor = vec2(1)/size*quality;
+    vec2 sizeFactor = vec2(1)/textureSize*quality;
 
     // Texel color fetching from texture sampler
     vec4 source = texture(texture0, fragTexCoord);
@@ -1,10 +1,10 @@
 #version 330
 
+// Screen space fog for the post-processing chain, thickens with the distance read back from the scene depth
+
 // Input vertex attributes (from vertex shader)
 in vec2 fragTexCoord;
 in vec4 fragColor;
-in vec3 fragPosition;
-in vec3 fragNormal;
 
 // Input uniform values
 uniform sampler2D texture0;
@@ -13,83 +13,26 @@ uniform vec4 colDiffuse;
 // Output fragment color
 out vec4 finalColor;
 
-// NOTE: Add your custom variables here
-
-#define     MAX_LIGHTS              4
-#define     LIGHT_DIRECTIONAL       0
-#define     LIGHT_POINT             1
-
-struct MaterialProperty {
-    vec3 color;
-    int useSampler;
-    sampler2D sampler;
-};
-
-struct Light {
-    int enabled;
-    int type;
-    vec3 position;
-    vec3 target;
-    vec4 color;
-};
+// Scene depth and the clip planes it was rendered with, passed from code
+uniform sampler2D depthTexture;
+uniform float nearPlane;
+uniform float farPlane;
 
-// Input lighting values
-uniform Light lights[MAX_LIGHTS];
-uniform vec4 ambient;
-uniform vec3 viewPos;
-uniform float fogDensity;
+uniform float fogDensity = 0.02;
+uniform vec4 fogColor = vec4(0.5, 0.5, 0.5, 1.0);
 
 void main()
 {
     // Texel color fetching from texture sampler
-    vec4 texelColor = texture(texture0, fragTexCoord);
-    vec3 lightDot = vec3(0.0);
-    vec3 normal = normalize(fragNormal);
-    vec3 viewD = normalize(viewPos - fragPosition);
-    vec3 specular = vec3(0.0);
-
-    // NOTE: Implement here your fragment shader code
-
-    for (int i = 0; i < MAX_LIGHTS; i++)
-    {
-        if (lights[i].enabled == 1)
-        {
-            vec3 light = vec3(0.0);
-
-            if (lights[i].type == LIGHT_DIRECTIONAL) light = -normalize(lights[i].target - lights[i].position);
-            if (lights[i].type == LIGHT_POINT) light = normalize(lights[i].position - fragPosition);
+    vec4 texelColor = texture(texture0, fragTexCoord)*colDiffuse*fragColor;
 
-            float NdotL = max(dot(normal, light), 0.0);
-            lightDot += lights[i].color.rgb*NdotL;
+    // Undo the perspective projection to get the distance from the camera
+    float depth = texture(depthTexture, fragTexCoord).r*2.0 - 1.0;
+    float dist = (2.0*nearPlane*farPlane)/(farPlane + nearPlane - depth*(farPlane - nearPlane));
 
-            float specCo = 0.0;
-            if (NdotL > 0.0) specCo = pow(max(0.0, dot(viewD, reflect(-(light), normal))), 16.0); // Shine: 16.0
-            specular += specCo;
-        }
-    }
-
-    finalColor = (texelColor*((colDiffuse + vec4(specular,1))*vec4(lightDot, 1.0)));
-    finalColor += texelColor*(ambient/10.0);
-
-    // Gamma correction
-    finalColor = pow(finalColor, vec4(1.0/2.2));
-
-    // Fog calculation
-    float dist = length(viewPos - fragPosition);
-
-    // these could be parameters...
-    const vec4 fogColor = vec4(0.5, 0.5, 0.5, 1.0);
-    //const float fogDensity = 0.16;
-
-    // Exponential fog
+    // Exponential squared fog
     float fogFactor = 1.0/exp((dist*fogDensity)*(dist*fogDensity));
-
-    // Linear fog (less nice)
-    //const float fogStart = 2.0;
-    //const float fogEnd = 10.0;
-    //float fogFactor = (fogEnd - dist)/(fogEnd - fogStart);
-
     fogFactor = clamp(fogFactor, 0.0, 1.0);
 
-    finalColor = mix(fogColor, finalColor, fogFactor);
+    finalColor = mix(fogColor, texelColor, fogFactor);
 }
@@ -15,9 +15,10 @@ out vec4 finalColor;
 
 // NOTE: Add your custom variables here
 
-#define     MAX_LIGHTS              4
+#define     MAX_LIGHTS              8
 #define     LIGHT_DIRECTIONAL       0
 #define     LIGHT_POINT             1
+#define     LIGHT_SPOT              2
 
 struct Light {
     int enabled;
@@ -25,6 +26,8 @@ struct Light {
     vec3 position;
     vec3 target;
     vec4 color;
+    float radius;   // Point and spot lights fade out at this distance
+    float cutoff;   // Cosine of the spot cone's half angle
 };
 
 // Input lighting values
@@ -50,23 +53,33 @@ void main()
         if (lights[i].enabled == 1)
         {
             vec3 light = vec3(0.0);
+            float attenuation = 1.0;
 
             if (lights[i].type == LIGHT_DIRECTIONAL)
             {
                 light = -normalize(lights[i].target - lights[i].position);
             }
 
-            if (lights[i].type == LIGHT_POINT)
+            if ((lights[i].type == LIGHT_POINT) || (lights[i].type == LIGHT_SPOT))
             {
                 light = normalize(lights[i].position - fragPosition);
+                float falloff = clamp(1.0 - length(lights[i].position - fragPosition)/lights[i].radius, 0.0, 1.0);
+                attenuation = falloff*falloff;
+            }
+
+            if (lights[i].type == LIGHT_SPOT)
+            {
+                // Soft edge over the outer tenth of the cone
+                float theta = dot(-light, normalize(lights[i].target - lights[i].position));
+                attenuation *= clamp((theta - lights[i].cutoff)/max((1.0 - lights[i].cutoff)*0.1, 0.0001), 0.0, 1.0);
             }
 
             float NdotL = max(dot(normal, light), 0.0);
-            lightDot += lights[i].color.rgb*NdotL;
+            lightDot += lights[i].color.rgb*NdotL*attenuation;
 
             float specCo = 0.0;
             if (NdotL > 0.0) specCo = pow(max(0.0, dot(viewD, reflect(-(light), normal))), 16.0); // 16 refers to shine
-            specular += specCo;
+            specular += specCo*attenuation;
         }
     }
 
@@ -14,16 +14,15 @@ out vec4 finalColor;
 // NOTE: Add your custom variables here
 
 // NOTE: Render size values must be passed from code
-const float renderWidth = 800;
-const float renderHeight = 450;
+uniform vec2 textureSize;
 
 uniform float pixelWidth = 5.0;
 uniform float pixelHeight = 5.0;
 
 void main()
 {
-    float dx = pixelWidth*(1.0/renderWidth);
-    float dy = pixelHeight*(1.0/renderHeight);
+    float dx = pixelWidth*(1.0/textureSize.x);
+    float dy = pixelHeight*(1.0/textureSize.y);
 
     vec2 coord = vec2(dx*floor(fragTexCoord.x/dx), dy*floor(fragTexCoord.y/dy));
 
@@ -1,12 +1,12 @@
 #version 330
 
 // This shader is based on the basic lighting shader
-// This only supports one light, which is directional, and it (of course) supports shadows
+// One directional light casts shadows, up to MAX_LIGHTS more light the scene without shadows
 
 // Input vertex attributes (from vertex shader)
 in vec3 fragPosition;
 in vec2 fragTexCoord;
-//in vec4 fragColor;
+in vec4 fragColor;
 in vec3 fragNormal;
 
 // Input uniform values
@@ -28,6 +28,24 @@ uniform sampler2D shadowMap;
 
 uniform int shadowMapResolution;
 
+// Lights besides the shadow caster, same layout as in lighting.fs
+#define     MAX_LIGHTS              8
+#define     LIGHT_DIRECTIONAL       0
+#define     LIGHT_POINT             1
+#define     LIGHT_SPOT              2
+
+struct Light {
+    int enabled;
+    int type;
+    vec3 position;
+    vec3 target;
+    vec4 color;
+    float radius;   // Point and spot lights fade out at this distance
+    float cutoff;   // Cosine of the spot cone's half angle
+};
+
+uniform Light lights[MAX_LIGHTS];
+
 void main()
 {
     // Texel color fetching from texture sampler
@@ -37,6 +55,8 @@ void main()
     vec3 viewD = normalize(viewPos - fragPosition);
     vec3 specular = vec3(0.0);
 
+    vec4 tint = colDiffuse * fragColor;
+
     vec3 l = -lightDir;
 
     float NdotL = max(dot(normal, l), 0.0);
@@ -46,7 +66,7 @@ void main()
     if (NdotL > 0.0) specCo = pow(max(0.0, dot(viewD, reflect(-(l), normal))), 16.0); // 16 refers to shine
     specular += specCo;
 
-    finalColor = (texelColor*((colDiffuse + vec4(specular, 1.0))*vec4(lightDot, 1.0)));
+    finalColor = (texelColor*((tint + vec4(specular, 1.0))*vec4(lightDot, 1.0)));
 
     // Shadow calculations
     vec4 fragPosLightSpace = lightVP * vec4(fragPosition, 1);
@@ -67,18 +87,56 @@ void main()
     // we test the surrounding points as well.
     // This blurs shadow edges, hiding aliasing artifacts.
     vec2 texelSize = vec2(1.0/float(shadowMapResolution));
-    for (int x = -1; x <= 1; x++)
+    // Fragments outside the light's view are never shadowed
+    if (curDepth <= 1.0)
+    {
+        for (int x = -1; x <= 1; x++)
+        {
+            for (int y = -1; y <= 1; y++)
+            {
+                float sampleDepth = texture(shadowMap, sampleCoords + texelSize*vec2(x, y)).r;
+                if (curDepth - bias > sampleDepth) shadowCounter++;
+            }
+        }
+    }
+    // Darken only the color so see-through blocks keep their alpha in shadow
+    finalColor.rgb = mix(finalColor.rgb, vec3(0.0), float(shadowCounter)/float(numSamples));
+
+    // Unshadowed lights
+    vec3 extraLight = vec3(0.0);
+    for (int i = 0; i < MAX_LIGHTS; i++)
     {
-        for (int y = -1; y <= 1; y++)
+        if (lights[i].enabled == 1)
         {
-            float sampleDepth = texture(shadowMap, sampleCoords + texelSize*vec2(x, y)).r;
-            if (curDepth - bias > sampleDepth) shadowCounter++;
+            vec3 light = vec3(0.0);
+            float attenuation = 1.0;
+
+            if (lights[i].type == LIGHT_DIRECTIONAL)
+            {
+                light = -normalize(lights[i].target - lights[i].position);
+            }
+
+            if ((lights[i].type == LIGHT_POINT) || (lights[i].type == LIGHT_SPOT))
+            {
+                light = normalize(lights[i].position - fragPosition);
+                float falloff = clamp(1.0 - length(lights[i].position - fragPosition)/lights[i].radius, 0.0, 1.0);
+                attenuation = falloff*falloff;
+            }
+
+            if (lights[i].type == LIGHT_SPOT)
+            {
+                // Soft edge over the outer tenth of the cone
+                float theta = dot(-light, normalize(lights[i].target - lights[i].position));
+                attenuation *= clamp((theta - lights[i].cutoff)/max((1.0 - lights[i].cutoff)*0.1, 0.0001), 0.0, 1.0);
+            }
+
+            extraLight += lights[i].color.rgb*max(dot(normal, light), 0.0)*attenuation;
         }
     }
-    finalColor = mix(finalColor, vec4(0, 0, 0, 1), float(shadowCounter)/float(numSamples));
+    finalColor.rgb += texelColor.rgb*tint.rgb*extraLight;
 
     // Add ambient lighting whether in shadow or not
-    finalColor += texelColor*(ambient/10.0)*colDiffuse;
+    finalColor += texelColor*(ambient/10.0)*tint;
 
     // Gamma correction
     finalColor = pow(finalColor, vec4(1.0/2.2));
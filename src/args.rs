@@ -0,0 +1,119 @@
+use crate::state::{ArenaPreset, ArenaSettings, AudioSettings, BotDifficulty, GameState};
+use crate::map::load_map;
+use crate::generation::{apply_generated_arena, parse_arena_preset};
+use crate::logic::parse_camera_mode;
+use crate::bots::{add_bot, parse_bot_difficulty};
+
+// Value following `name` on the command line, exits if the value is missing
+pub fn arg_value(args: &[String], name: &str) -> Option<String> {
+    let index = args.iter().position(|arg| arg == name)?;
+    match args.get(index + 1) {
+        Some(value) => Some(value.clone()),
+        None => {
+            eprintln!("{} requires a value", name);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Parsed value following `name`, exits if it is missing or invalid
+pub fn parse_arg<T: std::str::FromStr>(args: &[String], name: &str) -> Option<T> {
+    let value = arg_value(args, name)?;
+    match value.parse() {
+        Ok(parsed) => Some(parsed),
+        Err(_) => {
+            eprintln!("Invalid value '{}' for {}", value, name);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Apply `--seed`, `--preset` and `--map` to the state, exits on invalid arguments
+pub fn apply_arena_args(state: &mut GameState, args: &[String]) {
+    // Optional generated arena passed as `--seed <n>` and/or `--preset <flat|hills|maze|pillars>`
+    let seed = parse_arg(args, "--seed");
+    let preset = arg_value(args, "--preset");
+    if seed.is_some() || preset.is_some() {
+        let seed = seed.unwrap_or_else(rand::random);
+        let preset = match preset {
+            Some(name) => parse_arena_preset(&name).unwrap_or_else(|| {
+                eprintln!("Unknown preset '{}' (expected flat, hills, maze or pillars)", name);
+                std::process::exit(1);
+            }),
+            None => ArenaPreset::Flat,
+        };
+        println!("Generating arena with seed {}", seed);
+        if let Err(error) = apply_generated_arena(state, ArenaSettings { seed, preset }) {
+            eprintln!("Failed to generate arena with seed {}: {}", seed, error);
+            std::process::exit(1);
+        }
+    }
+
+    // Optional arena passed as `--map <path>`
+    if let Some(path) = arg_value(args, "--map") {
+        if let Err(error) = load_map(state, &path) {
+            eprintln!("Failed to load map {}: {}", path, error);
+            std::process::exit(1);
+        }
+        state.arena = None;
+        state.editor.map_path = path;
+    }
+}
+
+// Apply `--camera <shared|split|dynamic>`, exits on an unknown mode
+pub fn apply_camera_args(state: &mut GameState, args: &[String]) {
+    if let Some(name) = arg_value(args, "--camera") {
+        state.camera_state.mode = parse_camera_mode(&name).unwrap_or_else(|| {
+            eprintln!("Unknown camera mode '{}' (expected shared, split or dynamic)", name);
+            std::process::exit(1);
+        });
+    }
+}
+
+// Apply `--bot-difficulty <easy|normal|hard>`, `--fill-bots <lobby size>` and `--bots <n>`, exits on invalid arguments
+pub fn apply_bot_args(state: &mut GameState, args: &[String]) {
+    if let Some(name) = arg_value(args, "--bot-difficulty") {
+        state.bot_settings.difficulty = parse_bot_difficulty(&name).unwrap_or_else(|| {
+            eprintln!("Unknown bot difficulty '{}' (expected easy, normal or hard)", name);
+            std::process::exit(1);
+        });
+    }
+
+    // Keep the lobby topped up with bots
+    if let Some(lobby_size) = parse_arg::<usize>(args, "--fill-bots") {
+        state.bot_settings.fill_lobby = true;
+        state.bot_settings.lobby_size = lobby_size;
+    }
+
+    // Fixed number of bots, they join on the first tick
+    let count: usize = parse_arg(args, "--bots").unwrap_or(0);
+    let difficulty: BotDifficulty = state.bot_settings.difficulty;
+    for _ in 0..count {
+        if add_bot(state, difficulty).is_none() {
+            eprintln!("Only room for {} bots", state.bots.len());
+            break;
+        }
+    }
+}
+
+// Apply `--volume`, `--effects-volume` and `--music-volume` (0 to 1) and `--mute`, exits on invalid volumes
+pub fn apply_audio_args(settings: &mut AudioSettings, args: &[String]) {
+    let volume = |name: &str| parse_arg::<f32>(args, name).inspect(|volume| {
+        if !(0.0..=1.0).contains(volume) {
+            eprintln!("{} must be between 0 and 1", name);
+            std::process::exit(1);
+        }
+    });
+    if let Some(master_volume) = volume("--volume") {
+        settings.master_volume = master_volume;
+    }
+    if let Some(effects_volume) = volume("--effects-volume") {
+        settings.effects_volume = effects_volume;
+    }
+    if let Some(music_volume) = volume("--music-volume") {
+        settings.music_volume = music_volume;
+    }
+    if args.iter().any(|arg| arg == "--mute") {
+        settings.muted = true;
+    }
+}
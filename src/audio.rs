@@ -0,0 +1,371 @@
+use raylib::prelude::*;
+use std::collections::HashSet;
+use std::ffi::CString;
+use std::fs;
+use crate::state::{AudioConfig, AudioState, CameraState, GameEvent, GameState, MatchPhase, Playlist, SoundDefinition, SoundEffect};
+use crate::config::{AUDIO_PATH, SOUND_FALLOFF_DISTANCE};
+use crate::format::{FileFormat, FormatError, read_lines};
+
+pub const AUDIO_FORMAT: FileFormat = FileFormat {
+    kind: "audio file",
+    header: "voxel_battle_audio",
+    version: 1,
+};
+
+// Path of a file in the sound and music directory
+pub fn audio_path(file: &str) -> String {
+    format!("resources/audio/{}", file)
+}
+
+pub fn load_audio_config(path: &str) -> Result<AudioConfig, FormatError> {
+    parse_audio_config(&fs::read_to_string(path)?)
+}
+
+// One entry per line: "sound <effect> <file> [volume=f]" or "playlist <lobby|match> <file>...".
+// A later line for the same effect or playlist replaces the earlier one.
+pub fn parse_audio_config(text: &str) -> Result<AudioConfig, FormatError> {
+    let (_, lines) = read_lines(&AUDIO_FORMAT, text)?;
+
+    let mut config = AudioConfig::default();
+    for (line, content) in lines {
+        let mut fields = content.split_whitespace();
+        match fields.next().unwrap_or("") {
+            "sound" => {
+                let name = fields.next().ok_or_else(|| FormatError::Parse { line, message: "missing sound effect".to_string() })?;
+                let effect = parse_sound_effect(name).ok_or_else(|| FormatError::Parse {
+                    line,
+                    message: format!("unknown sound effect '{}'", name),
+                })?;
+                let path = fields.next().ok_or_else(|| FormatError::Parse { line, message: format!("missing file for {}", name) })?;
+
+                let mut sound = SoundDefinition { effect, path: path.to_string(), volume: 1.0 };
+                for field in fields {
+                    match field.split_once('=') {
+                        Some(("volume", value)) => match value.parse::<f32>() {
+                            Ok(volume) if (0.0..=1.0).contains(&volume) => sound.volume = volume,
+                            _ => return Err(FormatError::Parse { line, message: format!("invalid volume '{}'", value) }),
+                        },
+                        _ => return Err(FormatError::Parse { line, message: format!("unknown field '{}'", field) }),
+                    }
+                }
+                config.sounds.retain(|existing| existing.effect != effect);
+                config.sounds.push(sound);
+            }
+            "playlist" => {
+                let name = fields.next().ok_or_else(|| FormatError::Parse { line, message: "missing playlist name".to_string() })?;
+                let playlist = parse_playlist(name).ok_or_else(|| FormatError::Parse {
+                    line,
+                    message: format!("unknown playlist '{}'", name),
+                })?;
+                config.playlists.insert(playlist, fields.map(str::to_string).collect());
+            }
+            keyword => return Err(FormatError::Parse { line, message: format!("unknown entry '{}'", keyword) }),
+        }
+    }
+    Ok(config)
+}
+
+pub fn sound_effect_name(effect: SoundEffect) -> &'static str {
+    match effect {
+        SoundEffect::Join => "join",
+        SoundEffect::Dash => "dash",
+        SoundEffect::Bump => "bump",
+        SoundEffect::Break => "break",
+        SoundEffect::Place => "place",
+        SoundEffect::Remove => "remove",
+        SoundEffect::Elimination => "elimination",
+        SoundEffect::RoundStart => "round_start",
+    }
+}
+
+pub fn parse_sound_effect(name: &str) -> Option<SoundEffect> {
+    [
+        SoundEffect::Join,
+        SoundEffect::Dash,
+        SoundEffect::Bump,
+        SoundEffect::Break,
+        SoundEffect::Place,
+        SoundEffect::Remove,
+        SoundEffect::Elimination,
+        SoundEffect::RoundStart,
+    ].into_iter().find(|effect| sound_effect_name(*effect) == name)
+}
+
+pub fn playlist_name(playlist: Playlist) -> &'static str {
+    match playlist {
+        Playlist::Lobby => "lobby",
+        Playlist::Match => "match",
+    }
+}
+
+pub fn parse_playlist(name: &str) -> Option<Playlist> {
+    [Playlist::Lobby, Playlist::Match].into_iter().find(|playlist| playlist_name(*playlist) == name)
+}
+
+// Sound of an event and where in the world it happened, None for sounds heard the same everywhere
+pub fn event_sound(event: &GameEvent, voxel_size: f32) -> (SoundEffect, Option<Vector3>) {
+    let voxel = |(x, y, z): (i32, i32, i32)| Some(Vector3::new(x as f32, y as f32, z as f32) * voxel_size);
+    match *event {
+        GameEvent::VoxelBroken { position, .. } => (SoundEffect::Break, voxel(position)),
+        GameEvent::PlayerJoined { position, .. } => (SoundEffect::Join, Some(position)),
+        GameEvent::DashStarted { position, .. } => (SoundEffect::Dash, Some(position)),
+        GameEvent::WallBumped { position, .. } => (SoundEffect::Bump, Some(position)),
+        GameEvent::VoxelPlaced { position, .. } => (SoundEffect::Place, voxel(position)),
+        GameEvent::VoxelRemoved { position, .. } => (SoundEffect::Remove, voxel(position)),
+        GameEvent::PlayerEliminated { position, .. } => (SoundEffect::Elimination, Some(position)),
+        GameEvent::RoundStarted { .. } => (SoundEffect::RoundStart, None),
+    }
+}
+
+// Camera the sound is heard through, the split-screen pane looking closest to it
+fn listener(camera_state: &CameraState, position: Vector3) -> Camera3D {
+    if !camera_state.split {
+        return camera_state.camera;
+    }
+    camera_state.player_cameras.iter()
+        .map(|pane| pane.camera)
+        .min_by(|first, second| first.target.distance_to(position).total_cmp(&second.target.distance_to(position)))
+        .unwrap_or(camera_state.camera)
+}
+
+// Volume factor and stereo pan of a sound at `position`. Sounds fade with the distance from what the camera
+// looks at and move to the side of the screen they are on. raylib's pan is 0.5 in the middle and 1 on the left.
+pub fn spatialize(camera_state: &CameraState, position: Vector3) -> (f32, f32) {
+    let camera = listener(camera_state, position);
+    let attenuation = 1.0 / (1.0 + camera.target.distance_to(position) / SOUND_FALLOFF_DISTANCE);
+
+    let forward = camera.target - camera.position;
+    let right = forward.cross(camera.up).normalized();
+    let offset = position - camera.position;
+    let length = offset.length();
+    let side = if length > 0.001 { offset.dot(right) / length } else { 0.0 };
+    (attenuation, (0.5 - side * 0.5).clamp(0.0, 1.0))
+}
+
+// Open the audio device and load the sounds and playlists of the audio file. Without a device or file the
+// game runs silently, sounds that fail to load are skipped.
+pub fn init_audio(audio: &mut AudioState) {
+    unsafe {
+        ffi::InitAudioDevice();
+    }
+    audio.device_ready = unsafe { ffi::IsAudioDeviceReady() };
+    if !audio.device_ready {
+        eprintln!("No audio device, playing without sound");
+        return;
+    }
+
+    let config = match load_audio_config(AUDIO_PATH) {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("Failed to load audio from {}, playing without sound: {}", AUDIO_PATH, error);
+            return;
+        }
+    };
+
+    for definition in &config.sounds {
+        let path = audio_path(&definition.path);
+        let c_path = CString::new(path.as_str()).unwrap();
+        let sound = unsafe { ffi::LoadSound(c_path.as_ptr()) };
+        if unsafe { ffi::IsSoundValid(sound) } {
+            audio.sounds.insert(definition.effect, (sound, definition.volume));
+        } else {
+            eprintln!("Failed to load sound {}, {} stays silent", path, sound_effect_name(definition.effect));
+        }
+    }
+    audio.playlists = config.playlists.into_iter()
+        .map(|(playlist, tracks)| (playlist, tracks.iter().map(|track| audio_path(track)).collect()))
+        .collect();
+}
+
+pub fn close_audio(audio: &mut AudioState) {
+    if !audio.device_ready {
+        return;
+    }
+    stop_music(audio);
+    for (sound, _) in audio.sounds.drain().map(|(_, sound)| sound) {
+        unsafe {
+            ffi::UnloadSound(sound);
+        }
+    }
+    unsafe {
+        ffi::CloseAudioDevice();
+    }
+    audio.device_ready = false;
+}
+
+pub fn toggle_mute(audio: &mut AudioState) {
+    audio.settings.muted = !audio.settings.muted;
+    println!("Sound {}", if audio.settings.muted { "muted" } else { "on" });
+}
+
+// Play the sounds of the events raised by the last update, to be called after each `update`.
+// An effect plays once per tick however many events ask for it, e.g. for a whole brush stroke.
+pub fn play_event_sounds(audio: &AudioState, state: &GameState) {
+    if !audio.device_ready || audio.settings.muted {
+        return;
+    }
+    let settings = audio.settings;
+    let mut played = HashSet::new();
+    for event in &state.events {
+        let (effect, position) = event_sound(event, state.world.voxel_size);
+        let Some((sound, volume)) = audio.sounds.get(&effect) else {
+            continue;
+        };
+        if !played.insert(effect) {
+            continue;
+        }
+
+        let (attenuation, pan) = position.map_or((1.0, 0.5), |position| spatialize(&state.camera_state, position));
+        unsafe {
+            ffi::SetSoundVolume(*sound, settings.master_volume * settings.effects_volume * volume * attenuation);
+            ffi::SetSoundPan(*sound, pan);
+            ffi::PlaySound(*sound);
+        }
+    }
+}
+
+// Keep the playlist of the current match phase streaming, to be called every frame. Tracks play one after
+// another and the playlist starts over after the last one.
+pub fn update_music(audio: &mut AudioState, state: &GameState) {
+    if !audio.device_ready {
+        return;
+    }
+
+    let playlist = match state.match_state.phase {
+        MatchPhase::Lobby => Playlist::Lobby,
+        _ => Playlist::Match,
+    };
+    if audio.playlist != Some(playlist) {
+        stop_music(audio);
+        audio.playlist = Some(playlist);
+        audio.track = 0;
+        start_track(audio);
+    }
+
+    let Some(music) = audio.music else {
+        return;
+    };
+    if !unsafe { ffi::IsMusicStreamPlaying(music) } {
+        stop_music(audio);
+        audio.track += 1;
+        start_track(audio);
+        return;
+    }
+
+    let settings = audio.settings;
+    let volume = if settings.muted { 0.0 } else { settings.master_volume * settings.music_volume };
+    unsafe {
+        ffi::SetMusicVolume(music, volume);
+        ffi::UpdateMusicStream(music);
+    }
+}
+
+// Start the track of the current playlist at `audio.track`, wrapping around. Tracks that fail to load are
+// skipped, the music stops if none of them loads.
+fn start_track(audio: &mut AudioState) {
+    let Some(tracks) = audio.playlist.and_then(|playlist| audio.playlists.get(&playlist)) else {
+        return;
+    };
+    for attempt in 0..tracks.len() {
+        let index = (audio.track + attempt) % tracks.len();
+        let c_path = CString::new(tracks[index].as_str()).unwrap();
+        let mut music = unsafe { ffi::LoadMusicStream(c_path.as_ptr()) };
+        if !unsafe { ffi::IsMusicValid(music) } {
+            eprintln!("Failed to load music {}", tracks[index]);
+            continue;
+        }
+
+        // Tracks end so the next one can start
+        music.looping = false;
+        unsafe {
+            ffi::PlayMusicStream(music);
+        }
+        audio.music = Some(music);
+        audio.track = index;
+        return;
+    }
+}
+
+fn stop_music(audio: &mut AudioState) {
+    if let Some(music) = audio.music.take() {
+        unsafe {
+            ffi::StopMusicStream(music);
+            ffi::UnloadMusicStream(music);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::VoxelType;
+
+    // Camera above and behind the origin looking along -z, so +x is the right side of the screen
+    fn camera_state() -> CameraState {
+        CameraState {
+            camera: Camera3D::perspective(Vector3::new(0.0, 10.0, 10.0), Vector3::zero(), Vector3::new(0.0, 1.0, 0.0), 60.0),
+            ..CameraState::default()
+        }
+    }
+
+    fn assert_near(value: f32, expected: f32) {
+        assert!((value - expected).abs() < 0.001, "{} is not {}", value, expected);
+    }
+
+    #[test]
+    fn events_map_to_their_sounds() {
+        let position = Vector3::new(1.0, 2.0, 3.0);
+        let cases = [
+            (GameEvent::PlayerJoined { player_id: 0, position }, SoundEffect::Join),
+            (GameEvent::DashStarted { player_id: 0, position }, SoundEffect::Dash),
+            (GameEvent::WallBumped { player_id: 0, position, speed: 5.0 }, SoundEffect::Bump),
+            (GameEvent::PlayerEliminated { player_id: 0, position }, SoundEffect::Elimination),
+        ];
+        for (event, effect) in cases {
+            assert_eq!(event_sound(&event, 2.0), (effect, Some(position)));
+        }
+    }
+
+    #[test]
+    fn voxel_events_play_at_the_voxel_center() {
+        let expected = Some(Vector3::new(2.0, 4.0, 6.0));
+        let voxel_type = VoxelType::WALL;
+        let broken = GameEvent::VoxelBroken { position: (1, 2, 3), voxel_type, player_id: 0 };
+        assert_eq!(event_sound(&broken, 2.0), (SoundEffect::Break, expected));
+        assert_eq!(event_sound(&GameEvent::VoxelPlaced { position: (1, 2, 3), voxel_type }, 2.0), (SoundEffect::Place, expected));
+        assert_eq!(event_sound(&GameEvent::VoxelRemoved { position: (1, 2, 3), voxel_type }, 2.0), (SoundEffect::Remove, expected));
+    }
+
+    #[test]
+    fn round_start_is_not_positioned() {
+        assert_eq!(event_sound(&GameEvent::RoundStarted { round: 1 }, 1.0), (SoundEffect::RoundStart, None));
+    }
+
+    #[test]
+    fn sound_at_camera_target_is_full_volume_and_centered() {
+        let (volume, pan) = spatialize(&camera_state(), Vector3::zero());
+        assert_near(volume, 1.0);
+        assert_near(pan, 0.5);
+    }
+
+    #[test]
+    fn volume_halves_at_falloff_distance() {
+        let camera_state = camera_state();
+        let (near, _) = spatialize(&camera_state, Vector3::new(0.0, 0.0, -SOUND_FALLOFF_DISTANCE / 2.0));
+        let (falloff, _) = spatialize(&camera_state, Vector3::new(0.0, 0.0, -SOUND_FALLOFF_DISTANCE));
+        let (far, _) = spatialize(&camera_state, Vector3::new(0.0, 0.0, -SOUND_FALLOFF_DISTANCE * 3.0));
+        assert_near(falloff, 0.5);
+        assert!(near > falloff && falloff > far);
+    }
+
+    #[test]
+    fn pan_follows_screen_side() {
+        let camera_state = camera_state();
+        let (right_volume, right) = spatialize(&camera_state, Vector3::new(5.0, 0.0, 0.0));
+        let (left_volume, left) = spatialize(&camera_state, Vector3::new(-5.0, 0.0, 0.0));
+        assert!(right < 0.5, "pan {} should lean right", right);
+        assert!(left > 0.5, "pan {} should lean left", left);
+        assert_near(right + left, 1.0);
+        assert_near(right_volume, left_volume);
+    }
+}
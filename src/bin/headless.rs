@@ -0,0 +1,131 @@
+// Runs the simulation without a window: `headless [--ticks n] [--players n] [--bots n] [--bot-difficulty name] [--seed n]
+// [--preset name] [--map path] [--record path]`
+use std::collections::HashMap;
+use std::time::Instant;
+use raylib::prelude::*;
+use voxel_battle::state::{GameEvent, GameState, InputFrame, PlayerInputFrame};
+use voxel_battle::logic::{init, update, iter_voxels};
+use voxel_battle::args::{apply_arena_args, apply_bot_args, arg_value, parse_arg};
+use voxel_battle::bots::update_bots;
+use voxel_battle::replay::{record_tick, save_replay, start_recording};
+use voxel_battle::generation::arena_preset_name;
+use voxel_battle::config::{TICK_RATE, HEADLESS_DEFAULT_TICKS, PLAYER_COLORS};
+
+#[derive(Default)]
+struct PlayerStats {
+    distance: f32,
+    dashes: u32,
+    voxels_broken: u32,
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let ticks: u32 = parse_arg(&args, "--ticks").unwrap_or(HEADLESS_DEFAULT_TICKS);
+    let player_count: i32 = parse_arg(&args, "--players").unwrap_or(2);
+    if !(0..=5).contains(&player_count) {
+        eprintln!("--players must be between 0 and 5");
+        std::process::exit(1);
+    }
+
+    let mut state = GameState::default();
+    init(&mut state);
+    apply_arena_args(&mut state, &args);
+    apply_bot_args(&mut state, &args);
+    if player_count as usize + state.bots.len() > PLAYER_COLORS.len() {
+        eprintln!("At most {} players and bots together", PLAYER_COLORS.len());
+        std::process::exit(1);
+    }
+
+    // The run can be watched afterwards with `--replay <path>`
+    let record_path = arg_value(&args, "--record");
+    let mut replay = record_path.as_ref().map(|_| start_recording(&mut state));
+
+    let delta = 1.0 / TICK_RATE as f32;
+    let mut stats: HashMap<i32, PlayerStats> = HashMap::new();
+    let started = Instant::now();
+
+    for tick in 0..ticks {
+        let mut input = scripted_input(tick, player_count);
+        if let Some(replay) = replay.as_mut() {
+            record_tick(replay, &input);
+        }
+        update_bots(&mut state, &mut input, delta);
+
+        let previous: HashMap<i32, Vector3> = state.players.iter()
+            .map(|(id, player)| (*id, player.position))
+            .collect();
+
+        update(&mut state, &input, delta);
+
+        for (id, player) in &state.players {
+            if let Some(position) = previous.get(id) {
+                stats.entry(*id).or_default().distance += (player.position - *position).length();
+            }
+        }
+        for event in &state.events {
+            match event {
+                GameEvent::DashStarted { player_id, .. } => stats.entry(*player_id).or_default().dashes += 1,
+                GameEvent::VoxelBroken { player_id, .. } => stats.entry(*player_id).or_default().voxels_broken += 1,
+                _ => {}
+            }
+        }
+    }
+
+    let elapsed = started.elapsed().as_secs_f32();
+    print_summary(&state, &stats, ticks, elapsed);
+
+    if let (Some(path), Some(replay)) = (record_path, replay) {
+        if let Err(error) = save_replay(&replay, &path) {
+            eprintln!("Failed to save replay {}: {}", path, error);
+            std::process::exit(1);
+        }
+        println!("Saved replay to {}", path);
+    }
+}
+
+// Deterministic inputs: the keyboard slot joins on the first tick, gamepad slots join by moving,
+// everyone readies up once joined, walks in a slowly turning direction and dashes periodically
+fn scripted_input(tick: u32, player_count: i32) -> InputFrame {
+    let mut frame = InputFrame::default();
+    let time = tick as f32 / TICK_RATE as f32;
+
+    for id in 0..player_count {
+        let angle = time * 0.7 + id as f32 * 1.3;
+        frame.players.insert(id, PlayerInputFrame {
+            movement: Vector2::new(angle.cos(), angle.sin()),
+            dash_pressed: (tick + id as u32 * 17).is_multiple_of(90),
+            join_pressed: (id == 0 && tick == 0) || tick == 2,
+        });
+    }
+    frame
+}
+
+fn print_summary(state: &GameState, stats: &HashMap<i32, PlayerStats>, ticks: u32, elapsed: f32) {
+    let simulated = ticks as f32 / TICK_RATE as f32;
+    println!("Simulated {} ticks ({:.1}s) in {:.3}s ({:.0} ticks/s)",
+        ticks, simulated, elapsed, ticks as f32 / elapsed.max(f32::EPSILON));
+
+    let arena = match state.arena {
+        Some(settings) => format!("{} arena, seed {}", arena_preset_name(settings.preset), settings.seed),
+        None => "hand-built arena".to_string(),
+    };
+    println!("World {}x{}x{}, {} voxels, {} spawn points, {}",
+        state.world.width, state.world.height, state.world.depth,
+        iter_voxels(&state.world).count(), state.world.spawn_points.len(), arena);
+
+    let match_state = &state.match_state;
+    println!("Match {:?}, round {}, winner {}", match_state.phase, match_state.round,
+        match_state.match_winner.map_or("none".to_string(), |id| format!("player {}", id)));
+
+    let mut ids: Vec<&i32> = state.players.keys().collect();
+    ids.sort();
+    for id in ids {
+        let player = &state.players[id];
+        let player_stats = stats.get(id).map_or((0.0, 0, 0), |s| (s.distance, s.dashes, s.voxels_broken));
+        let name = if state.bots.contains_key(id) { "Bot" } else { "Player" };
+        println!("{} {}: position ({:.2}, {:.2}, {:.2}), traveled {:.1}, dashes {}, voxels broken {}, health {:.0}, rounds won {}{}",
+            name, id, player.position.x, player.position.y, player.position.z, player_stats.0, player_stats.1, player_stats.2,
+            player.health, match_state.round_wins.get(id).copied().unwrap_or(0),
+            if player.is_alive { "" } else { " (eliminated)" });
+    }
+}
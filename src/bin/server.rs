@@ -0,0 +1,41 @@
+// Authoritative dedicated server: `server [--bind address] [--seed n] [--preset name] [--map path] [--bots n] [--fill-bots size]
+// [--bot-difficulty name]`
+use std::time::{Duration, Instant};
+use voxel_battle::state::{GameState, ServerState};
+use voxel_battle::logic::init;
+use voxel_battle::args::{apply_arena_args, apply_bot_args, arg_value};
+use voxel_battle::network::{open_server_socket, server_receive, server_tick};
+use voxel_battle::config::{DEFAULT_SERVER_PORT, TICK_RATE, MAX_FRAME_TIME};
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let bind = arg_value(&args, "--bind").unwrap_or_else(|| format!("0.0.0.0:{}", DEFAULT_SERVER_PORT));
+
+    let mut state = GameState::default();
+    init(&mut state);
+    apply_arena_args(&mut state, &args);
+    apply_bot_args(&mut state, &args);
+
+    let socket = open_server_socket(&bind).unwrap_or_else(|error| {
+        eprintln!("Failed to listen on {}: {}", bind, error);
+        std::process::exit(1);
+    });
+    println!("Server listening on {}", bind);
+
+    let mut server = ServerState::default();
+    let fixed_delta = 1.0 / TICK_RATE as f32;
+    let mut accumulator = 0.0;
+    let mut last_update = Instant::now();
+    loop {
+        accumulator += last_update.elapsed().as_secs_f32().min(MAX_FRAME_TIME);
+        last_update = Instant::now();
+
+        server_receive(&mut server, &mut state, &socket);
+        while accumulator >= fixed_delta {
+            server_tick(&mut server, &mut state, &socket, fixed_delta);
+            accumulator -= fixed_delta;
+        }
+
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
@@ -0,0 +1,222 @@
+use raylib::prelude::*;
+use std::fmt;
+use std::fs;
+use crate::state::{BlockProperties, BlockRegistry, VoxelType};
+use crate::format::{FileFormat, FormatError, parse_field, read_lines};
+use crate::config::DEFAULT_BLOCK_HIT_POINTS;
+
+pub const BLOCKS_FORMAT: FileFormat = FileFormat {
+    kind: "block file",
+    header: "voxel_battle_blocks",
+    version: 1,
+};
+
+#[derive(Debug)]
+pub enum BlockError {
+    Format(FormatError),
+    MissingTerrain, // Ground and wall are required by new worlds and generated arenas
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockError::Format(error) => write!(f, "{}", error),
+            BlockError::MissingTerrain => write!(
+                f, "blocks {} and {} must be defined, they are used for ground and walls",
+                VoxelType::GROUND.0, VoxelType::WALL.0
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BlockError {}
+
+impl From<FormatError> for BlockError {
+    fn from(error: FormatError) -> Self {
+        BlockError::Format(error)
+    }
+}
+
+impl From<std::io::Error> for BlockError {
+    fn from(error: std::io::Error) -> Self {
+        BlockError::Format(FormatError::Io(error))
+    }
+}
+
+pub fn load_blocks(path: &str) -> Result<BlockRegistry, BlockError> {
+    parse_blocks(&fs::read_to_string(path)?)
+}
+
+// One block per line: "block <id> <name> [solid] [walkable] [destructible] [color=r,g,b,a] [hit_points=f] [friction=f] [bounce=f] [damage=f]".
+// IDs start at 1 and increase by one, 0 is reserved for empty space.
+pub fn parse_blocks(text: &str) -> Result<BlockRegistry, BlockError> {
+    let (_, lines) = read_lines(&BLOCKS_FORMAT, text)?;
+
+    let mut registry = BlockRegistry { blocks: vec![BlockRegistry::default().blocks[0].clone()] };
+    for (line, content) in lines {
+        let mut fields = content.split_whitespace();
+        let keyword = fields.next().unwrap_or("");
+        if keyword != "block" {
+            return Err(FormatError::Parse { line, message: format!("unknown entry '{}'", keyword) }.into());
+        }
+
+        let id: u8 = parse_field(fields.next(), line, "block id")?;
+        if id as usize != registry.blocks.len() {
+            return Err(FormatError::Parse {
+                line,
+                message: format!("expected block id {}, ids must start at 1 and increase by one", registry.blocks.len()),
+            }.into());
+        }
+        let name = fields.next().ok_or_else(|| FormatError::Parse { line, message: "missing block name".to_string() })?;
+        if find_block(&registry, name).is_some() {
+            return Err(FormatError::Parse { line, message: format!("block '{}' is defined twice", name) }.into());
+        }
+
+        let mut block = BlockProperties {
+            id: VoxelType(id),
+            name: name.to_string(),
+            color: Color::WHITE,
+            solid: false,
+            walkable: false,
+            destructible: false,
+            hit_points: DEFAULT_BLOCK_HIT_POINTS,
+            friction: 1.0,
+            bounce: 0.0,
+            damage: 0.0,
+        };
+        for field in fields {
+            match field.split_once('=') {
+                None => match field {
+                    "solid" => block.solid = true,
+                    "walkable" => block.walkable = true,
+                    "destructible" => block.destructible = true,
+                    _ => return Err(FormatError::Parse { line, message: format!("unknown flag '{}'", field) }.into()),
+                },
+                Some(("color", value)) => block.color = parse_color(value, line)?,
+                Some(("hit_points", value)) => block.hit_points = parse_non_negative(value, line, "hit_points")?,
+                Some(("friction", value)) => block.friction = parse_non_negative(value, line, "friction")?,
+                Some(("bounce", value)) => block.bounce = parse_non_negative(value, line, "bounce")?,
+                Some(("damage", value)) => block.damage = parse_non_negative(value, line, "damage")?,
+                Some((key, _)) => return Err(FormatError::Parse { line, message: format!("unknown property '{}'", key) }.into()),
+            }
+        }
+        registry.blocks.push(block);
+    }
+
+    if registry.blocks.len() <= VoxelType::WALL.0 as usize {
+        return Err(BlockError::MissingTerrain);
+    }
+    Ok(registry)
+}
+
+// Properties of a block, unknown IDs behave like empty space
+pub fn block_properties(registry: &BlockRegistry, voxel_type: VoxelType) -> &BlockProperties {
+    registry.blocks.get(voxel_type.0 as usize).unwrap_or(&registry.blocks[0])
+}
+
+pub fn find_block(registry: &BlockRegistry, name: &str) -> Option<VoxelType> {
+    registry.blocks.iter().find(|block| block.name == name).map(|block| block.id)
+}
+
+fn parse_non_negative(value: &str, line: usize, name: &str) -> Result<f32, FormatError> {
+    let number: f32 = parse_field(Some(value), line, name)?;
+    if !number.is_finite() || number < 0.0 {
+        return Err(FormatError::Parse { line, message: format!("{} must not be negative", name) });
+    }
+    Ok(number)
+}
+
+fn parse_color(value: &str, line: usize) -> Result<Color, FormatError> {
+    let mut channels = value.split(',');
+    let color = Color {
+        r: parse_field(channels.next(), line, "color")?,
+        g: parse_field(channels.next(), line, "color")?,
+        b: parse_field(channels.next(), line, "color")?,
+        a: parse_field(channels.next(), line, "color")?,
+    };
+    if channels.next().is_some() {
+        return Err(FormatError::Parse { line, message: "color must be r,g,b,a".to_string() });
+    }
+    Ok(color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TERRAIN: &str = "voxel_battle_blocks 1\nblock 1 ground solid walkable\nblock 2 wall solid destructible\n";
+
+    fn parse_error(extra: &str) -> (usize, String) {
+        match parse_blocks(&format!("{}{}", TERRAIN, extra)) {
+            Err(BlockError::Format(FormatError::Parse { line, message })) => (line, message),
+            result => panic!("expected a parse error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn valid_blocks_load_with_their_properties() {
+        let registry = parse_blocks(&format!(
+            "{}block 3 slime color=10,200,30,128 solid walkable destructible hit_points=5 friction=0.5 bounce=1.25 damage=2\n",
+            TERRAIN
+        )).unwrap();
+        assert_eq!(registry.blocks.len(), 4);
+        assert_eq!(registry.blocks[0], BlockRegistry::default().blocks[0]);
+        assert_eq!(registry.blocks[3], BlockProperties {
+            id: VoxelType(3),
+            name: "slime".to_string(),
+            color: Color { r: 10, g: 200, b: 30, a: 128 },
+            solid: true,
+            walkable: true,
+            destructible: true,
+            hit_points: 5.0,
+            friction: 0.5,
+            bounce: 1.25,
+            damage: 2.0,
+        });
+        // Left out properties keep their defaults
+        let ground = block_properties(&registry, VoxelType::GROUND);
+        assert_eq!(
+            (ground.color, ground.hit_points, ground.friction, ground.bounce, ground.damage),
+            (Color::WHITE, DEFAULT_BLOCK_HIT_POINTS, 1.0, 0.0, 0.0)
+        );
+        assert!(ground.solid && ground.walkable && !ground.destructible);
+        assert_eq!(block_properties(&registry, VoxelType(9)).name, registry.blocks[0].name);
+    }
+
+    #[test]
+    fn shipped_blocks_are_found_by_name() {
+        let registry = parse_blocks(include_str!("../resources/blocks.txt")).unwrap();
+        assert_eq!(find_block(&registry, "ground"), Some(VoxelType::GROUND));
+        assert_eq!(find_block(&registry, "wall"), Some(VoxelType::WALL));
+        for block in &registry.blocks {
+            assert_eq!(find_block(&registry, &block.name), Some(block.id));
+            assert_eq!(block_properties(&registry, block.id), block);
+        }
+        assert_eq!(find_block(&registry, "marble"), None);
+    }
+
+    #[test]
+    fn ground_and_wall_are_required() {
+        assert!(matches!(parse_blocks("voxel_battle_blocks 1\n"), Err(BlockError::MissingTerrain)));
+        assert!(matches!(parse_blocks("voxel_battle_blocks 1\nblock 1 ground solid walkable\n"), Err(BlockError::MissingTerrain)));
+        assert!(matches!(parse_blocks("voxel_battle_blocks 2\n"), Err(BlockError::Format(FormatError::UnsupportedVersion { .. }))));
+    }
+
+    #[test]
+    fn invalid_blocks_are_rejected() {
+        assert_eq!(parse_error("block 3 wall solid"), (4, "block 'wall' is defined twice".to_string()));
+        assert_eq!(parse_error("block 4 ice"), (4, "expected block id 3, ids must start at 1 and increase by one".to_string()));
+        assert_eq!(parse_error("block 2 ice"), (4, "expected block id 3, ids must start at 1 and increase by one".to_string()));
+        assert_eq!(parse_error("block 3"), (4, "missing block name".to_string()));
+        assert_eq!(parse_error("block ice 3"), (4, "invalid block id 'ice'".to_string()));
+        assert_eq!(parse_error("block 3 ice shiny"), (4, "unknown flag 'shiny'".to_string()));
+        assert_eq!(parse_error("block 3 ice grip=2"), (4, "unknown property 'grip'".to_string()));
+        assert_eq!(parse_error("block 3 ice friction=-1"), (4, "friction must not be negative".to_string()));
+        assert_eq!(parse_error("block 3 ice damage=inf"), (4, "damage must not be negative".to_string()));
+        assert_eq!(parse_error("block 3 ice bounce=high"), (4, "invalid bounce 'high'".to_string()));
+        assert_eq!(parse_error("block 3 ice color=1,2,3"), (4, "missing color".to_string()));
+        assert_eq!(parse_error("block 3 ice color=1,2,3,4,5"), (4, "color must be r,g,b,a".to_string()));
+        assert_eq!(parse_error("block 3 ice color=1,2,3,256"), (4, "invalid color '256'".to_string()));
+        assert_eq!(parse_error("tile 3 ice"), (4, "unknown entry 'tile'".to_string()));
+    }
+}
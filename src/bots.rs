@@ -0,0 +1,307 @@
+use raylib::prelude::*;
+use crate::state::{BotDifficulty, BotState, GameState, InputFrame, MatchPhase, PlayerInput, PlayerInputFrame, PlayerState};
+use crate::logic::{get_voxel, remove_player};
+use crate::navigation::{find_path, node_at, node_position, update_navigation};
+use crate::blocks::block_properties;
+use crate::config::{PLAYER_COLORS, FIRST_BOT_ID, BOT_MAX_DROP, BOT_DASH_DISTANCE};
+
+// Directions tried when the way to the target is blocked, in degrees from it
+const DETOUR_ANGLES: [f32; 7] = [0.0, 45.0, -45.0, 90.0, -90.0, 135.0, -135.0];
+const PROBE_STEP: f32 = 0.5; // Distance between the points checked along a path
+
+// How a difficulty plays
+struct BotProfile {
+    reaction_time: f32, // Seconds between decisions
+    dash_range: f32, // Distance to the target at which the bot dashes
+    lookahead: f32, // How far ahead walls and holes are avoided
+}
+
+fn bot_profile(difficulty: BotDifficulty) -> BotProfile {
+    match difficulty {
+        BotDifficulty::Easy => BotProfile { reaction_time: 0.5, dash_range: 2.5, lookahead: 1.0 },
+        BotDifficulty::Normal => BotProfile { reaction_time: 0.25, dash_range: 4.0, lookahead: 2.0 },
+        BotDifficulty::Hard => BotProfile { reaction_time: 0.1, dash_range: 5.0, lookahead: 3.0 },
+    }
+}
+
+pub fn bot_difficulty_name(difficulty: BotDifficulty) -> &'static str {
+    match difficulty {
+        BotDifficulty::Easy => "easy",
+        BotDifficulty::Normal => "normal",
+        BotDifficulty::Hard => "hard",
+    }
+}
+
+pub fn parse_bot_difficulty(name: &str) -> Option<BotDifficulty> {
+    [BotDifficulty::Easy, BotDifficulty::Normal, BotDifficulty::Hard].into_iter().find(|difficulty| bot_difficulty_name(*difficulty) == name)
+}
+
+// Register a bot, its player joins through the input the bot produces like a gamepad player does.
+// Returns the bot's player ID, None when every player slot is taken.
+pub fn add_bot(state: &mut GameState, difficulty: BotDifficulty) -> Option<i32> {
+    // Bots that haven't joined yet hold on to their slot
+    let waiting = state.bots.keys().filter(|id| !state.players.contains_key(id)).count();
+    if state.players.len() + waiting >= PLAYER_COLORS.len() {
+        return None;
+    }
+
+    let id = (FIRST_BOT_ID..).find(|id| !state.player_inputs.contains_key(id))?;
+    restore_bot(state, id, difficulty);
+    Some(id)
+}
+
+// Register a bot under a known player ID, like the ones a replay starts with
+pub fn restore_bot(state: &mut GameState, id: i32, difficulty: BotDifficulty) {
+    state.player_inputs.insert(id, PlayerInput {
+        movement: Vector2::zero(),
+        movement_speed: 5.0, // Same speed as human players
+    });
+    state.bots.insert(id, BotState { difficulty, think_timer: 0.0, input: PlayerInputFrame::default() });
+}
+
+pub fn remove_bot(state: &mut GameState, id: i32) {
+    state.bots.remove(&id);
+    remove_player(state, id);
+}
+
+// Handle the lobby's bot keys and add the input of every bot to the frame next to the human devices,
+// to be called before each `update`
+pub fn update_bots(state: &mut GameState, frame: &mut InputFrame, delta: f32) {
+    update_navigation(&mut state.navigation, &state.world, &state.blocks);
+
+    if state.match_state.phase == MatchPhase::Lobby {
+        if frame.toggle_bot_fill {
+            state.bot_settings.fill_lobby = !state.bot_settings.fill_lobby;
+            // Switching filling off sends every bot home
+            if !state.bot_settings.fill_lobby {
+                let ids: Vec<i32> = state.bots.keys().copied().collect();
+                for id in ids {
+                    remove_bot(state, id);
+                }
+            }
+        }
+        if frame.cycle_bot_difficulty {
+            let difficulty = match state.bot_settings.difficulty {
+                BotDifficulty::Easy => BotDifficulty::Normal,
+                BotDifficulty::Normal => BotDifficulty::Hard,
+                BotDifficulty::Hard => BotDifficulty::Easy,
+            };
+            state.bot_settings.difficulty = difficulty;
+            for bot in state.bots.values_mut() {
+                bot.difficulty = difficulty;
+            }
+        }
+        if state.bot_settings.fill_lobby {
+            fill_lobby(state);
+        }
+    }
+
+    // Sorted so the result doesn't depend on hash map order
+    let mut ids: Vec<i32> = state.bots.keys().copied().collect();
+    ids.sort();
+    for id in ids {
+        let mut bot = state.bots[&id];
+        bot.think_timer -= delta;
+        if bot.think_timer <= 0.0 {
+            bot.think_timer += bot_profile(bot.difficulty).reaction_time;
+            bot.input = decide_input(state, id, &bot);
+        } else {
+            // Between decisions the bot keeps moving but doesn't press anything again
+            bot.input.dash_pressed = false;
+            bot.input.join_pressed = false;
+        }
+        frame.players.insert(id, bot.input);
+        state.bots.insert(id, bot);
+    }
+}
+
+// Add or remove bots so humans and bots together reach the lobby size, humans take the place of bots
+fn fill_lobby(state: &mut GameState) {
+    let humans = state.players.keys().filter(|id| !state.bots.contains_key(id)).count();
+    let wanted = state.bot_settings.lobby_size.saturating_sub(humans);
+
+    let mut ids: Vec<i32> = state.bots.keys().copied().collect();
+    ids.sort();
+    for id in ids.iter().skip(wanted) {
+        remove_bot(state, *id);
+    }
+    let difficulty = state.bot_settings.difficulty;
+    for _ in ids.len()..wanted {
+        if add_bot(state, difficulty).is_none() {
+            break;
+        }
+    }
+}
+
+// Join and ready up in the lobby, chase the nearest opponent during a round and dash once close
+fn decide_input(state: &GameState, id: i32, bot: &BotState) -> PlayerInputFrame {
+    let player = state.players.get(&id);
+    if state.match_state.phase == MatchPhase::Lobby {
+        // The first press joins, the next one readies up
+        return PlayerInputFrame {
+            join_pressed: player.is_none_or(|player| !player.is_ready),
+            ..PlayerInputFrame::default()
+        };
+    }
+
+    let Some(player) = player.filter(|player| player.is_alive) else {
+        return PlayerInputFrame::default();
+    };
+    if state.match_state.phase != MatchPhase::Playing {
+        return PlayerInputFrame::default();
+    }
+
+    // Nearest living opponent, ties go to the lowest ID
+    let distance_to = |other: &PlayerState| horizontal(other.position - player.position).length();
+    let target = state.players.iter()
+        .filter(|(other_id, other)| **other_id != id && other.is_alive)
+        .min_by(|(first_id, first), (second_id, second)| {
+            distance_to(first).total_cmp(&distance_to(second)).then(first_id.cmp(second_id))
+        });
+    let Some((_, target)) = target else {
+        return PlayerInputFrame::default();
+    };
+
+    let profile = bot_profile(bot.difficulty);
+    let offset = horizontal(target.position - player.position);
+    let distance = offset.length();
+    if distance < 0.001 {
+        return PlayerInputFrame::default();
+    }
+    // Follow the walkable path around walls and gaps, straight at the target when there is none
+    let direct = offset / distance;
+    let wanted = path_direction(state, player, target).unwrap_or(direct);
+    let movement = steer(state, player, wanted, profile.lookahead);
+
+    // Only dash straight at the target and only when the dash can't end in a hole
+    let aimed = movement.dot(direct) > 0.9;
+    let dash_pressed = aimed && distance < profile.dash_range && player.dash_cooldown <= 0.0 && !player.is_dashing
+        && is_path_safe(state, player, movement, BOT_DASH_DISTANCE);
+
+    PlayerInputFrame { movement, dash_pressed, join_pressed: false }
+}
+
+// Direction to the next node of the shortest path to the target, None when either player is off the
+// navigation grid, no path was found or both stand on the same node
+fn path_direction(state: &GameState, player: &PlayerState, target: &PlayerState) -> Option<Vector2> {
+    let nav = &state.navigation;
+    let start = node_at(nav, &state.world, player.position)?;
+    let goal = node_at(nav, &state.world, target.position)?;
+    let path = find_path(nav, start, goal)?;
+    // Nodes the bot has all but reached are skipped so it doesn't turn back to their centers
+    path.iter().skip(1)
+        .map(|node| horizontal(node_position(nav, &state.world, *node) - player.position))
+        .find(|offset| offset.length() > state.world.voxel_size * 0.5)
+        .map(|offset| offset.normalized())
+}
+
+// First direction close to `wanted` with neither a wall nor a hole ahead, turns around as a last resort
+fn steer(state: &GameState, player: &PlayerState, wanted: Vector2, lookahead: f32) -> Vector2 {
+    let candidates = DETOUR_ANGLES.iter().map(|angle| wanted.rotated(angle.to_radians())).chain([-wanted]);
+    for direction in candidates {
+        if is_path_safe(state, player, direction, lookahead) {
+            return direction;
+        }
+    }
+    Vector2::zero()
+}
+
+// Whether walking `distance` along `direction` keeps the player on safe ground and out of walls
+fn is_path_safe(state: &GameState, player: &PlayerState, direction: Vector2, distance: f32) -> bool {
+    let steps = (distance / PROBE_STEP).ceil().max(1.0) as i32;
+    (1..=steps).all(|step| {
+        let travel = direction * (step as f32 * PROBE_STEP).min(distance);
+        let position = player.position + Vector3::new(travel.x, 0.0, travel.y);
+        !is_blocked(state, player, position) && has_safe_ground(state, player, position)
+    })
+}
+
+// A solid block at body height that can't be stepped onto
+fn is_blocked(state: &GameState, player: &PlayerState, position: Vector3) -> bool {
+    let (x, y, z) = cell_at(state, position);
+    // Rows from the player's center up, a player one voxel tall only occupies the center row
+    let rows = (player.size.y / state.world.voxel_size).ceil().max(1.0) as i32;
+    (0..rows).any(|row| {
+        let block = block_properties(&state.blocks, get_voxel(&state.world, x, y + row, z));
+        block.solid && !block.walkable
+    })
+}
+
+// A solid block not far below the feet that doesn't hurt to stand on
+fn has_safe_ground(state: &GameState, player: &PlayerState, position: Vector3) -> bool {
+    let feet = position - Vector3::new(0.0, player.size.y / 2.0 + state.world.voxel_size / 2.0, 0.0);
+    let (x, y, z) = cell_at(state, feet);
+    (y - BOT_MAX_DROP..=y).rev()
+        .map(|row| block_properties(&state.blocks, get_voxel(&state.world, x, row, z)))
+        .find(|block| block.solid)
+        .is_some_and(|block| block.damage <= 0.0)
+}
+
+// Voxel cell containing a world position, voxels are centered on their coordinates
+fn cell_at(state: &GameState, position: Vector3) -> (i32, i32, i32) {
+    let cell = position / state.world.voxel_size;
+    (cell.x.round() as i32, cell.y.round() as i32, cell.z.round() as i32)
+}
+
+// Ground plane part of a vector, y of the result is z of the world like the movement input
+fn horizontal(vector: Vector3) -> Vector2 {
+    Vector2::new(vector.x, vector.z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::config::{BOT_LOBBY_SIZE, TICK_RATE};
+    use crate::generation::apply_generated_arena;
+    use crate::logic::{init, seed_simulation, update};
+    use crate::state::{ArenaPreset, ArenaSettings, GameEvent};
+
+    const SOAK_TICKS: usize = 60 * TICK_RATE as usize;
+    const WALKING_SPEED: f32 = 5.0 * 1.5; // Top speed of steering alone, anything faster comes from a dash or a hit
+
+    #[test]
+    fn bots_play_matches_without_walking_off_the_arena() {
+        for seed in 1..=4 {
+            let mut state = GameState::default();
+            init(&mut state);
+            seed_simulation(&mut state, seed as u64);
+            apply_generated_arena(&mut state, ArenaSettings { seed, preset: ArenaPreset::Flat }).unwrap();
+
+            let delta = 1.0 / TICK_RATE as f32;
+            let mut rounds_started = 0;
+            let mut matches_won = 0;
+            let mut last_pushed: HashMap<i32, usize> = HashMap::new();
+            for tick in 0..SOAK_TICKS {
+                let mut frame = InputFrame { toggle_bot_fill: tick == 0, ..InputFrame::default() };
+                update_bots(&mut state, &mut frame, delta);
+                let health: HashMap<i32, f32> = state.players.iter().map(|(id, player)| (*id, player.health)).collect();
+                let phase = state.match_state.phase;
+                update(&mut state, &frame, delta);
+
+                rounds_started += state.events.iter().filter(|event| matches!(event, GameEvent::RoundStarted { .. })).count();
+                if phase != MatchPhase::MatchOver && state.match_state.phase == MatchPhase::MatchOver {
+                    matches_won += 1;
+                }
+                for (id, player) in &state.players {
+                    let speed = Vector2::new(player.velocity.x, player.velocity.z).length();
+                    if player.is_dashing || speed > WALKING_SPEED + 0.01 || health.get(id).is_some_and(|before| player.health < *before) {
+                        last_pushed.insert(*id, tick);
+                    }
+                    // The flat preset's only holes are past its edges, bots only go there when dashing or knocked off
+                    if player.is_alive && player.position.y < state.world.voxel_size / 2.0 {
+                        assert!(
+                            last_pushed.get(id).is_some_and(|hit| tick - hit < TICK_RATE as usize),
+                            "seed {}: bot {} walked off the arena at tick {}", seed, id, tick
+                        );
+                    }
+                }
+            }
+
+            assert_eq!(state.players.len(), BOT_LOBBY_SIZE);
+            assert!(state.players.keys().all(|id| state.bots.contains_key(id)));
+            assert!(rounds_started >= 3, "seed {}: only {} rounds started", seed, rounds_started);
+            assert!(matches_won >= 1, "seed {}: no match was won", seed);
+        }
+    }
+}
@@ -6,9 +6,79 @@ pub const SCREEN_HEIGHT: i32 = 600;
 pub const GLSL_VERSION: i32 = 330;
 
 pub const VOXEL_SIZE: f32 = 1.0;
+pub const CHUNK_SIZE: i32 = 16; // Voxels along each side of a render chunk
+pub const DEFAULT_MAP_PATH: &str = "maps/arena.vbmap";
+pub const BLOCKS_PATH: &str = "resources/blocks.txt"; // Block registry, built-in ground and wall are used when missing
+pub const POST_PROCESS_PATH: &str = "resources/postprocess.txt"; // Post-processing chain, the scene is drawn as is when missing
+pub const AUDIO_PATH: &str = "resources/audio.txt"; // Sound effects and music playlists, the game is silent when missing
+pub const CONTROLS_PATH: &str = "resources/controls.txt"; // Key and gamepad bindings, the built-in ones are used when missing
+pub const MAX_WORLD_DIMENSION: i32 = 256; // Upper bound accepted by the map loader
+pub const MAX_HILL_HEIGHT: i32 = 3; // Tallest generated terrain above the base floor
+pub const SPAWN_AREA_RADIUS: i32 = 1; // Generated spawn areas are cleared (2r+1)x(2r+1) patches
+pub const EDITOR_STATUS_DURATION: f32 = 3.0; // Seconds an editor status message stays visible
+pub const EDITOR_HISTORY_LIMIT: usize = 100; // Undoable strokes kept by the editor
+pub const EDITOR_FLOOD_FILL_LIMIT: usize = 65536; // Most voxels a single flood fill may change
+pub const TICK_RATE: u32 = 60; // Fixed simulation steps per second
+pub const MAX_FRAME_TIME: f32 = 0.25; // Longest frame fed to the simulation, avoids a spiral of catch-up ticks after a hitch
+pub const HEADLESS_DEFAULT_TICKS: u32 = 3600; // One minute of simulated time
+pub const REPLAY_CHECKPOINT_INTERVAL: usize = 600; // Ticks between saved states of a replay, seeking back re-simulates at most this many
+pub const REPLAY_SEEK_TICKS: usize = 300; // Ticks skipped by one press of a seek key
+pub const REPLAY_MIN_SPEED: f32 = 0.25;
+pub const REPLAY_MAX_SPEED: f32 = 8.0;
+pub const REPLAY_MAX_TICKS: usize = 60 * 60 * 60 * 4; // Four hours, longer replay files are rejected as corrupt
+pub const REPLAY_CAMERA_SPEED: f32 = 15.0; // Units per second the free replay camera moves
+pub const PLAYER_MAX_HEALTH: f32 = 100.0;
+pub const DASH_DAMAGE_PER_SPEED: f32 = 2.0; // Damage per unit of impact speed of a dash hit
+pub const DASH_KNOCKBACK: f32 = 1.0; // Knockback velocity per unit of impact speed
+pub const HIT_INVULNERABILITY: f32 = 0.5; // Seconds a player can't be hit again after a dash hit
+pub const BLOCK_DAMAGE_PER_SPEED: f32 = 2.0; // Damage per unit of impact speed when a dash hits a destructible voxel
+pub const DEFAULT_BLOCK_HIT_POINTS: f32 = 50.0; // Hit points of destructible blocks that don't set their own
+pub const GRAVITY: f32 = 30.0; // Downward acceleration in units per second squared
+pub const BOUNCE_MIN_SPEED: f32 = 0.4; // Slower bounces settle so players can stand on bouncy blocks
+pub const MAX_FALL_SPEED: f32 = 20.0; // Kept below one voxel per tick so falling players can't pass through the floor
+pub const FALL_ELIMINATION_DEPTH: f32 = 3.0; // Players this far below y=0 are out of the arena
+pub const MIN_PLAYERS_TO_START: usize = 2; // Ready players needed to leave the lobby
+pub const ROUNDS_TO_WIN: u32 = 3;
+pub const COUNTDOWN_DURATION: f32 = 3.0; // Seconds before a round starts
+pub const ROUND_OVER_DURATION: f32 = 3.0; // Seconds the round winner is shown
+pub const MATCH_OVER_DURATION: f32 = 5.0; // Seconds the match winner is shown before returning to the lobby
+pub const DEFAULT_SERVER_PORT: u16 = 7777;
+pub const CONNECTION_TIMEOUT: f32 = 5.0; // Seconds without packets before a peer is dropped
+pub const CONNECT_RETRY_INTERVAL: f32 = 0.5; // Seconds between connection attempts of a client
+pub const INPUT_REDUNDANCY: usize = 4; // Recent inputs resent in every packet to survive packet loss
+pub const MAX_BUFFERED_INPUTS: usize = 8; // Inputs queued on the server per client before old ones are dropped
+pub const NAV_MAX_STEP: i32 = 1; // Voxels a path climbs or drops in one move, same both ways so paths can be walked back
+pub const NAV_SEARCH_LIMIT: usize = 20000; // Most nodes a single path search expands before giving up
+pub const SECOND_KEYBOARD_ID: i32 = 5; // Input ID of the second player sharing the keyboard, after the four gamepads
+pub const FIRST_BOT_ID: i32 = 6; // Input IDs 0-5 belong to the keyboard players and gamepads
+pub const GAMEPAD_DEADZONE: f32 = 0.1; // Stick movement ignored around the center, the controls file can set it per gamepad
+pub const BOT_LOBBY_SIZE: usize = 4; // Players a lobby filled with bots reaches, leaving a slot for someone joining late
+pub const BOT_MAX_DROP: i32 = 1; // Deepest drop in voxels a bot walks off, anything deeper counts as a hole
+pub const BOT_DASH_DISTANCE: f32 = 4.0; // Roughly how far a dash carries, checked for holes before dashing
+pub const MAX_SPLIT_PANES: usize = 4; // Players beyond this are only seen on the shared camera
+pub const SPLIT_DISTANCE: f32 = 20.0; // Player spread at which the dynamic camera splits the screen
+pub const MERGE_DISTANCE: f32 = 14.0; // Spread at which it merges again, lower than SPLIT_DISTANCE so it doesn't flicker
+pub const PLAYER_CAMERA_HEIGHT: f32 = 15.0; // Height of a split-screen camera above its player
+pub const PLAYER_CAMERA_DISTANCE: f32 = 12.0; // Distance of a split-screen camera behind its player
 pub const DEBUG_COLOR: Color = Color { r: 0, g: 255, b: 0, a: 128 }; // Semi-transparent green
 pub const GRID_COLOR: Color = Color { r: 0, g: 255, b: 0, a: 128 }; // Very transparent green for grid
 pub const LIGHT_COLOR: Color = Color { r: 255, g: 255, b: 255, a: 255 }; // White light source
+pub const MAX_LIGHTS: usize = 8; // Must match MAX_LIGHTS in the lighting shaders
+pub const DEFAULT_LIGHT_RADIUS: f32 = 10.0; // Reach of new point and spot lights
+pub const DEFAULT_SPOT_CONE_ANGLE: f32 = 30.0; // Degrees from the center of a spot light to its edge
+pub const EDITOR_LIGHT_HEIGHT: i32 = 3; // Voxels above the hovered cell new lights are placed at
+pub const PLAYER_GLOW_INTENSITY: f32 = 0.6; // Brightness of the light following each player, 0 disables it
+pub const PLAYER_GLOW_RADIUS: f32 = 4.0;
+pub const DASH_FLASH_INTENSITY: f32 = 2.0; // Player light brightness while dashing
+pub const AMBIENT_LIGHT: [f32; 4] = [0.3, 0.3, 0.3, 1.0]; // Ambient term of the lighting shaders
+pub const SHADER_RELOAD_INTERVAL: f64 = 0.5; // Seconds between checks for changed shader files
+pub const SHADOW_MAP_RESOLUTION: i32 = 2048; // Texels per side of the depth texture rendered from the light
+pub const SHADOW_MAP_SLOT: i32 = 10; // Texture unit the shadow map is bound to, low units are used by materials
+pub const MASTER_VOLUME: f32 = 1.0; // Volumes go from 0 to 1, effects and music are scaled by the master volume
+pub const EFFECTS_VOLUME: f32 = 0.8;
+pub const MUSIC_VOLUME: f32 = 0.5;
+pub const SOUND_FALLOFF_DISTANCE: f32 = 20.0; // Distance from the camera's target at which sounds play at half volume
+pub const WALL_BUMP_MIN_SPEED: f32 = 3.0; // Slower wall contacts, like walking along a wall, make no sound
 
 // Predefined list of colors
 pub const PLAYER_COLORS: [Color; 5] = [
@@ -19,8 +89,6 @@ pub const PLAYER_COLORS: [Color; 5] = [
     Color { r: 75, g: 45, b: 159, a: 255 },  // #4B2D9F
 ];
 
-// FIXME: world voxel grid has some offset
-// FIXME: hoverd voxel a litle bit worond because of the offset
 // FIXME: worng bbox of player some times
 
 /* 
@@ -31,5 +99,3 @@ REFACTORING review all code
 */
 
 // TODO: rotating player and go forward
-// TODO: multiplayer base logic - server and client, connecting/disconnecting, sending data
-// TODO: add some sound
@@ -0,0 +1,514 @@
+use raylib::prelude::*;
+use std::collections::HashMap;
+use std::fs;
+use crate::state::{Action, Binding, Control, ControlDevice, Controls};
+use crate::config::GAMEPAD_DEADZONE;
+use crate::format::{FileFormat, FormatError, read_lines};
+
+pub const CONTROLS_FORMAT: FileFormat = FileFormat {
+    kind: "controls file",
+    header: "voxel_battle_controls",
+    version: 1,
+};
+
+// Action names in the controls file
+const ACTIONS: [(&str, Action); 37] = [
+    ("move_x", Action::MoveX),
+    ("move_y", Action::MoveY),
+    ("dash", Action::Dash),
+    ("join", Action::Join),
+    ("toggle_editor", Action::ToggleEditor),
+    ("cycle_camera_mode", Action::CycleCameraMode),
+    ("toggle_bot_fill", Action::ToggleBotFill),
+    ("cycle_bot_difficulty", Action::CycleBotDifficulty),
+    ("toggle_mute", Action::ToggleMute),
+    ("editor_place", Action::EditorPlace),
+    ("editor_toggle_build_mode", Action::EditorToggleBuildMode),
+    ("editor_rotate", Action::EditorRotate),
+    ("editor_continuous_remove", Action::EditorContinuousRemove),
+    ("editor_save_map", Action::EditorSaveMap),
+    ("editor_load_map", Action::EditorLoadMap),
+    ("editor_next_seed", Action::EditorNextSeed),
+    ("editor_next_preset", Action::EditorNextPreset),
+    ("editor_toggle_spawn_point", Action::EditorToggleSpawnPoint),
+    ("editor_tool_single", Action::EditorToolSingle),
+    ("editor_tool_box", Action::EditorToolBox),
+    ("editor_tool_hollow_box", Action::EditorToolHollowBox),
+    ("editor_tool_line", Action::EditorToolLine),
+    ("editor_tool_flood_fill", Action::EditorToolFloodFill),
+    ("editor_palette", Action::EditorPalette),
+    ("editor_undo", Action::EditorUndo),
+    ("editor_redo", Action::EditorRedo),
+    ("editor_toggle_light", Action::EditorToggleLight),
+    ("editor_select_next_light", Action::EditorSelectNextLight),
+    ("editor_light_x", Action::EditorLightX),
+    ("editor_light_y", Action::EditorLightY),
+    ("editor_light_z", Action::EditorLightZ),
+    ("editor_cycle_light_type", Action::EditorCycleLightType),
+    ("editor_check_arena", Action::EditorCheckArena),
+    ("replay_toggle_pause", Action::ReplayTogglePause),
+    ("replay_seek", Action::ReplaySeek),
+    ("replay_speed", Action::ReplaySpeed),
+    ("replay_restart", Action::ReplayRestart),
+];
+
+// Key names in the controls file, raylib's without the KEY_ prefix in lowercase and digits as themselves
+const KEYS: &[(&str, KeyboardKey)] = &[
+    ("a", KeyboardKey::KEY_A), ("b", KeyboardKey::KEY_B), ("c", KeyboardKey::KEY_C), ("d", KeyboardKey::KEY_D),
+    ("e", KeyboardKey::KEY_E), ("f", KeyboardKey::KEY_F), ("g", KeyboardKey::KEY_G), ("h", KeyboardKey::KEY_H),
+    ("i", KeyboardKey::KEY_I), ("j", KeyboardKey::KEY_J), ("k", KeyboardKey::KEY_K), ("l", KeyboardKey::KEY_L),
+    ("m", KeyboardKey::KEY_M), ("n", KeyboardKey::KEY_N), ("o", KeyboardKey::KEY_O), ("p", KeyboardKey::KEY_P),
+    ("q", KeyboardKey::KEY_Q), ("r", KeyboardKey::KEY_R), ("s", KeyboardKey::KEY_S), ("t", KeyboardKey::KEY_T),
+    ("u", KeyboardKey::KEY_U), ("v", KeyboardKey::KEY_V), ("w", KeyboardKey::KEY_W), ("x", KeyboardKey::KEY_X),
+    ("y", KeyboardKey::KEY_Y), ("z", KeyboardKey::KEY_Z),
+    ("0", KeyboardKey::KEY_ZERO), ("1", KeyboardKey::KEY_ONE), ("2", KeyboardKey::KEY_TWO), ("3", KeyboardKey::KEY_THREE),
+    ("4", KeyboardKey::KEY_FOUR), ("5", KeyboardKey::KEY_FIVE), ("6", KeyboardKey::KEY_SIX), ("7", KeyboardKey::KEY_SEVEN),
+    ("8", KeyboardKey::KEY_EIGHT), ("9", KeyboardKey::KEY_NINE),
+    ("f1", KeyboardKey::KEY_F1), ("f2", KeyboardKey::KEY_F2), ("f3", KeyboardKey::KEY_F3), ("f4", KeyboardKey::KEY_F4),
+    ("f5", KeyboardKey::KEY_F5), ("f6", KeyboardKey::KEY_F6), ("f7", KeyboardKey::KEY_F7), ("f8", KeyboardKey::KEY_F8),
+    ("f9", KeyboardKey::KEY_F9), ("f10", KeyboardKey::KEY_F10), ("f11", KeyboardKey::KEY_F11), ("f12", KeyboardKey::KEY_F12),
+    ("left", KeyboardKey::KEY_LEFT), ("right", KeyboardKey::KEY_RIGHT), ("up", KeyboardKey::KEY_UP), ("down", KeyboardKey::KEY_DOWN),
+    ("space", KeyboardKey::KEY_SPACE), ("enter", KeyboardKey::KEY_ENTER), ("tab", KeyboardKey::KEY_TAB),
+    ("escape", KeyboardKey::KEY_ESCAPE), ("backspace", KeyboardKey::KEY_BACKSPACE), ("insert", KeyboardKey::KEY_INSERT),
+    ("delete", KeyboardKey::KEY_DELETE), ("home", KeyboardKey::KEY_HOME), ("end", KeyboardKey::KEY_END),
+    ("page_up", KeyboardKey::KEY_PAGE_UP), ("page_down", KeyboardKey::KEY_PAGE_DOWN),
+    ("left_shift", KeyboardKey::KEY_LEFT_SHIFT), ("right_shift", KeyboardKey::KEY_RIGHT_SHIFT),
+    ("left_control", KeyboardKey::KEY_LEFT_CONTROL), ("right_control", KeyboardKey::KEY_RIGHT_CONTROL),
+    ("left_alt", KeyboardKey::KEY_LEFT_ALT), ("right_alt", KeyboardKey::KEY_RIGHT_ALT),
+    ("comma", KeyboardKey::KEY_COMMA), ("period", KeyboardKey::KEY_PERIOD), ("slash", KeyboardKey::KEY_SLASH),
+    ("semicolon", KeyboardKey::KEY_SEMICOLON), ("apostrophe", KeyboardKey::KEY_APOSTROPHE), ("minus", KeyboardKey::KEY_MINUS),
+    ("equal", KeyboardKey::KEY_EQUAL), ("left_bracket", KeyboardKey::KEY_LEFT_BRACKET),
+    ("right_bracket", KeyboardKey::KEY_RIGHT_BRACKET), ("backslash", KeyboardKey::KEY_BACKSLASH), ("grave", KeyboardKey::KEY_GRAVE),
+    ("kp_0", KeyboardKey::KEY_KP_0), ("kp_1", KeyboardKey::KEY_KP_1), ("kp_2", KeyboardKey::KEY_KP_2), ("kp_3", KeyboardKey::KEY_KP_3),
+    ("kp_4", KeyboardKey::KEY_KP_4), ("kp_5", KeyboardKey::KEY_KP_5), ("kp_6", KeyboardKey::KEY_KP_6), ("kp_7", KeyboardKey::KEY_KP_7),
+    ("kp_8", KeyboardKey::KEY_KP_8), ("kp_9", KeyboardKey::KEY_KP_9),
+    ("kp_enter", KeyboardKey::KEY_KP_ENTER),
+];
+
+// Bindings from the controls file, the built-in ones when it can't be read
+pub fn init_controls(path: &str) -> Controls {
+    match load_controls(path) {
+        Ok(controls) => controls,
+        Err(error) => {
+            eprintln!("Failed to load controls from {}, using the default bindings: {}", path, error);
+            default_controls()
+        }
+    }
+}
+
+pub fn load_controls(path: &str) -> Result<Controls, FormatError> {
+    parse_controls(&fs::read_to_string(path)?)
+}
+
+// One entry per line: "bind <device> <action> <binding>..." or "deadzone <gamepad|all> <value>".
+// A bind line replaces the default bindings of the action on that device, without bindings it unbinds it.
+pub fn parse_controls(text: &str) -> Result<Controls, FormatError> {
+    let (_, lines) = read_lines(&CONTROLS_FORMAT, text)?;
+
+    let mut controls = default_controls();
+    for (line, content) in lines {
+        let mut fields = content.split_whitespace();
+        match fields.next().unwrap_or("") {
+            "bind" => {
+                let name = fields.next().ok_or_else(|| FormatError::Parse { line, message: "missing device".to_string() })?;
+                let device = parse_control_device(name).ok_or_else(|| FormatError::Parse {
+                    line,
+                    message: format!("unknown device '{}'", name),
+                })?;
+                let name = fields.next().ok_or_else(|| FormatError::Parse { line, message: "missing action".to_string() })?;
+                let action = parse_action(name).ok_or_else(|| FormatError::Parse {
+                    line,
+                    message: format!("unknown action '{}'", name),
+                })?;
+                if let Some(message) = device_error(device, action) {
+                    return Err(FormatError::Parse { line, message });
+                }
+
+                let mut bindings = Vec::new();
+                for field in fields {
+                    let binding = parse_binding(field).ok_or_else(|| FormatError::Parse {
+                        line,
+                        message: format!("invalid binding '{}'", field),
+                    })?;
+                    if let Some(message) = binding_error(device, action, binding) {
+                        return Err(FormatError::Parse { line, message: format!("{}: {}", field, message) });
+                    }
+                    bindings.push(binding);
+                }
+                controls.bindings.insert((device, action), bindings);
+            }
+            "deadzone" => {
+                let gamepad = fields.next().ok_or_else(|| FormatError::Parse { line, message: "missing gamepad".to_string() })?;
+                let gamepads = match gamepad {
+                    "all" => 0..controls.deadzones.len(),
+                    _ => match gamepad.parse::<usize>() {
+                        Ok(index) if index < controls.deadzones.len() => index..index + 1,
+                        _ => return Err(FormatError::Parse { line, message: format!("invalid gamepad '{}'", gamepad) }),
+                    },
+                };
+                let deadzone = match fields.next().map(str::parse::<f32>) {
+                    Some(Ok(value)) if (0.0..1.0).contains(&value) => value,
+                    _ => return Err(FormatError::Parse { line, message: "deadzone must be at least 0 and below 1".to_string() }),
+                };
+                for index in gamepads {
+                    controls.deadzones[index] = deadzone;
+                }
+            }
+            keyword => return Err(FormatError::Parse { line, message: format!("unknown entry '{}'", keyword) }),
+        }
+    }
+    Ok(controls)
+}
+
+// The bindings the game ships with, also written out in resources/controls.txt
+pub fn default_controls() -> Controls {
+    use ControlDevice::{Gamepad, Keyboard, SecondKeyboard};
+    let key = |key: KeyboardKey| Binding::Single(Control::Key(key));
+    let keys = |negative: KeyboardKey, positive: KeyboardKey| Binding::Pair(Control::Key(negative), Control::Key(positive));
+    let mouse = |button: MouseButton| Binding::Single(Control::Mouse(button));
+    let button = |button: GamepadButton| Binding::Single(Control::GamepadButton(button));
+    let axis = |axis: GamepadAxis| Binding::Axis { axis, inverted: false };
+
+    let defaults = [
+        (Keyboard, Action::MoveX, keys(KeyboardKey::KEY_A, KeyboardKey::KEY_D)),
+        (Keyboard, Action::MoveY, keys(KeyboardKey::KEY_W, KeyboardKey::KEY_S)),
+        (Keyboard, Action::Dash, key(KeyboardKey::KEY_LEFT_SHIFT)),
+        (Keyboard, Action::Join, key(KeyboardKey::KEY_SPACE)),
+        (SecondKeyboard, Action::MoveX, keys(KeyboardKey::KEY_LEFT, KeyboardKey::KEY_RIGHT)),
+        (SecondKeyboard, Action::MoveY, keys(KeyboardKey::KEY_UP, KeyboardKey::KEY_DOWN)),
+        (SecondKeyboard, Action::Dash, key(KeyboardKey::KEY_RIGHT_SHIFT)),
+        (SecondKeyboard, Action::Join, key(KeyboardKey::KEY_ENTER)),
+        (Gamepad, Action::MoveX, axis(GamepadAxis::GAMEPAD_AXIS_LEFT_X)),
+        (Gamepad, Action::MoveY, axis(GamepadAxis::GAMEPAD_AXIS_LEFT_Y)),
+        (Gamepad, Action::Dash, button(GamepadButton::GAMEPAD_BUTTON_RIGHT_TRIGGER_1)),
+        (Gamepad, Action::Join, button(GamepadButton::GAMEPAD_BUTTON_RIGHT_FACE_DOWN)),
+        (Keyboard, Action::ToggleEditor, key(KeyboardKey::KEY_M)),
+        (Keyboard, Action::CycleCameraMode, key(KeyboardKey::KEY_C)),
+        (Keyboard, Action::ToggleBotFill, key(KeyboardKey::KEY_B)),
+        (Keyboard, Action::CycleBotDifficulty, key(KeyboardKey::KEY_V)),
+        (Keyboard, Action::ToggleMute, key(KeyboardKey::KEY_N)),
+        (Keyboard, Action::EditorPlace, mouse(MouseButton::MOUSE_BUTTON_LEFT)),
+        (Keyboard, Action::EditorToggleBuildMode, mouse(MouseButton::MOUSE_BUTTON_RIGHT)),
+        (Keyboard, Action::EditorRotate, mouse(MouseButton::MOUSE_BUTTON_MIDDLE)),
+        (Keyboard, Action::EditorContinuousRemove, key(KeyboardKey::KEY_LEFT_CONTROL)),
+        (Keyboard, Action::EditorSaveMap, key(KeyboardKey::KEY_F5)),
+        (Keyboard, Action::EditorLoadMap, key(KeyboardKey::KEY_F9)),
+        (Keyboard, Action::EditorNextSeed, key(KeyboardKey::KEY_F6)),
+        (Keyboard, Action::EditorNextPreset, key(KeyboardKey::KEY_F7)),
+        (Keyboard, Action::EditorToggleSpawnPoint, key(KeyboardKey::KEY_P)),
+        (Keyboard, Action::EditorToolSingle, key(KeyboardKey::KEY_ONE)),
+        (Keyboard, Action::EditorToolBox, key(KeyboardKey::KEY_TWO)),
+        (Keyboard, Action::EditorToolHollowBox, key(KeyboardKey::KEY_THREE)),
+        (Keyboard, Action::EditorToolLine, key(KeyboardKey::KEY_FOUR)),
+        (Keyboard, Action::EditorToolFloodFill, key(KeyboardKey::KEY_FIVE)),
+        (Keyboard, Action::EditorPalette, keys(KeyboardKey::KEY_Q, KeyboardKey::KEY_E)),
+        (Keyboard, Action::EditorUndo, Binding::WithCtrl(Control::Key(KeyboardKey::KEY_Z))),
+        (Keyboard, Action::EditorRedo, Binding::WithCtrl(Control::Key(KeyboardKey::KEY_Y))),
+        (Keyboard, Action::EditorToggleLight, key(KeyboardKey::KEY_L)),
+        (Keyboard, Action::EditorSelectNextLight, key(KeyboardKey::KEY_TAB)),
+        (Keyboard, Action::EditorLightX, keys(KeyboardKey::KEY_KP_4, KeyboardKey::KEY_KP_6)),
+        (Keyboard, Action::EditorLightY, keys(KeyboardKey::KEY_PAGE_DOWN, KeyboardKey::KEY_PAGE_UP)),
+        (Keyboard, Action::EditorLightZ, keys(KeyboardKey::KEY_KP_8, KeyboardKey::KEY_KP_2)),
+        (Keyboard, Action::EditorCycleLightType, key(KeyboardKey::KEY_T)),
+        (Keyboard, Action::EditorCheckArena, key(KeyboardKey::KEY_F8)),
+        (Keyboard, Action::ReplayTogglePause, key(KeyboardKey::KEY_SPACE)),
+        (Keyboard, Action::ReplaySeek, keys(KeyboardKey::KEY_LEFT, KeyboardKey::KEY_RIGHT)),
+        (Keyboard, Action::ReplaySpeed, keys(KeyboardKey::KEY_DOWN, KeyboardKey::KEY_UP)),
+        (Keyboard, Action::ReplayRestart, key(KeyboardKey::KEY_HOME)),
+    ];
+
+    let mut bindings = HashMap::new();
+    for (device, action, binding) in defaults {
+        bindings.insert((device, action), vec![binding]);
+    }
+    Controls { bindings, deadzones: [GAMEPAD_DEADZONE; 4] }
+}
+
+// Bindings of an action on a device, empty when it is unbound
+pub fn action_bindings(controls: &Controls, device: ControlDevice, action: Action) -> &[Binding] {
+    controls.bindings.get(&(device, action)).map(Vec::as_slice).unwrap_or(&[])
+}
+
+// Actions with a direction, bound to key pairs or gamepad axes instead of single keys
+pub fn is_axis_action(action: Action) -> bool {
+    matches!(
+        action,
+        Action::MoveX | Action::MoveY | Action::EditorPalette | Action::EditorLightX | Action::EditorLightY | Action::EditorLightZ
+            | Action::ReplaySeek | Action::ReplaySpeed
+    )
+}
+
+pub fn is_player_action(action: Action) -> bool {
+    matches!(action, Action::MoveX | Action::MoveY | Action::Dash | Action::Join)
+}
+
+// Editor and replay actions, they go along with the mouse so only the first keyboard has them
+pub fn is_keyboard_action(action: Action) -> bool {
+    !is_player_action(action)
+        && !matches!(
+            action,
+            Action::ToggleEditor | Action::CycleCameraMode | Action::ToggleBotFill | Action::CycleBotDifficulty | Action::ToggleMute
+        )
+}
+
+// Why the device can't have the action, None when it can
+fn device_error(device: ControlDevice, action: Action) -> Option<String> {
+    match device {
+        ControlDevice::SecondKeyboard if !is_player_action(action) => {
+            Some(format!("{} only has move_x, move_y, dash and join", control_device_name(device)))
+        }
+        ControlDevice::Gamepad if is_keyboard_action(action) => {
+            Some(format!("{} can only be bound on the keyboard", action_name(action)))
+        }
+        _ => None,
+    }
+}
+
+// Why the binding doesn't fit the action or the device, None when it does
+fn binding_error(device: ControlDevice, action: Action, binding: Binding) -> Option<String> {
+    let controls = match binding {
+        Binding::Single(control) | Binding::WithCtrl(control) => vec![control],
+        Binding::Pair(negative, positive) => vec![negative, positive],
+        Binding::Axis { .. } => Vec::new(),
+    };
+    let axis_binding = matches!(binding, Binding::Pair(..) | Binding::Axis { .. });
+    let uses_gamepad = matches!(binding, Binding::Axis { .. })
+        || controls.iter().any(|control| matches!(control, Control::GamepadButton(_)));
+    let uses_keyboard = matches!(binding, Binding::WithCtrl(_))
+        || controls.iter().any(|control| !matches!(control, Control::GamepadButton(_)));
+
+    if is_axis_action(action) && !axis_binding {
+        Some(format!("{} needs a pair like key:a/key:d or a gamepad axis", action_name(action)))
+    } else if !is_axis_action(action) && axis_binding {
+        Some(format!("{} needs a single key or button", action_name(action)))
+    } else if device == ControlDevice::Gamepad && uses_keyboard {
+        Some("gamepads only have buttons and axes".to_string())
+    } else if device != ControlDevice::Gamepad && uses_gamepad {
+        Some(format!("gamepad buttons and axes can't be bound on {}", control_device_name(device)))
+    } else {
+        None
+    }
+}
+
+// "key:<name>", "mouse:<name>" or "button:<name>" for button actions, prefixed with "ctrl+" to need Ctrl held.
+// Axis actions take "<negative>/<positive>" pairs of those or "axis:<name>", "-axis:<name>" for the reversed direction.
+pub fn parse_binding(text: &str) -> Option<Binding> {
+    if let Some(name) = text.strip_prefix("-axis:") {
+        return parse_gamepad_axis(name).map(|axis| Binding::Axis { axis, inverted: true });
+    }
+    if let Some(name) = text.strip_prefix("axis:") {
+        return parse_gamepad_axis(name).map(|axis| Binding::Axis { axis, inverted: false });
+    }
+    if let Some(control) = text.strip_prefix("ctrl+") {
+        return parse_control(control).map(Binding::WithCtrl);
+    }
+    if let Some((negative, positive)) = text.split_once('/') {
+        return Some(Binding::Pair(parse_control(negative)?, parse_control(positive)?));
+    }
+    parse_control(text).map(Binding::Single)
+}
+
+fn parse_control(text: &str) -> Option<Control> {
+    match text.split_once(':')? {
+        ("key", name) => KEYS.iter().find(|(key_name, _)| *key_name == name).map(|(_, key)| Control::Key(*key)),
+        ("mouse", name) => parse_mouse_button(name).map(Control::Mouse),
+        ("button", name) => parse_gamepad_button(name).map(Control::GamepadButton),
+        _ => None,
+    }
+}
+
+pub fn action_name(action: Action) -> &'static str {
+    ACTIONS.iter().find(|(_, named)| *named == action).map(|(name, _)| *name).expect("every action is in ACTIONS")
+}
+
+pub fn parse_action(name: &str) -> Option<Action> {
+    ACTIONS.iter().find(|(action_name, _)| *action_name == name).map(|(_, action)| *action)
+}
+
+pub fn control_device_name(device: ControlDevice) -> &'static str {
+    match device {
+        ControlDevice::Keyboard => "keyboard",
+        ControlDevice::SecondKeyboard => "keyboard2",
+        ControlDevice::Gamepad => "gamepad",
+    }
+}
+
+pub fn parse_control_device(name: &str) -> Option<ControlDevice> {
+    [ControlDevice::Keyboard, ControlDevice::SecondKeyboard, ControlDevice::Gamepad]
+        .into_iter().find(|device| control_device_name(*device) == name)
+}
+
+fn parse_mouse_button(name: &str) -> Option<MouseButton> {
+    [
+        ("left", MouseButton::MOUSE_BUTTON_LEFT),
+        ("right", MouseButton::MOUSE_BUTTON_RIGHT),
+        ("middle", MouseButton::MOUSE_BUTTON_MIDDLE),
+        ("side", MouseButton::MOUSE_BUTTON_SIDE),
+        ("extra", MouseButton::MOUSE_BUTTON_EXTRA),
+        ("forward", MouseButton::MOUSE_BUTTON_FORWARD),
+        ("back", MouseButton::MOUSE_BUTTON_BACK),
+    ].into_iter().find(|(button_name, _)| *button_name == name).map(|(_, button)| button)
+}
+
+// Face buttons by position, right_face_down is A on Xbox and Cross on PlayStation pads
+fn parse_gamepad_button(name: &str) -> Option<GamepadButton> {
+    [
+        ("left_face_up", GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_UP),
+        ("left_face_right", GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_RIGHT),
+        ("left_face_down", GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_DOWN),
+        ("left_face_left", GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_LEFT),
+        ("right_face_up", GamepadButton::GAMEPAD_BUTTON_RIGHT_FACE_UP),
+        ("right_face_right", GamepadButton::GAMEPAD_BUTTON_RIGHT_FACE_RIGHT),
+        ("right_face_down", GamepadButton::GAMEPAD_BUTTON_RIGHT_FACE_DOWN),
+        ("right_face_left", GamepadButton::GAMEPAD_BUTTON_RIGHT_FACE_LEFT),
+        ("left_trigger_1", GamepadButton::GAMEPAD_BUTTON_LEFT_TRIGGER_1),
+        ("left_trigger_2", GamepadButton::GAMEPAD_BUTTON_LEFT_TRIGGER_2),
+        ("right_trigger_1", GamepadButton::GAMEPAD_BUTTON_RIGHT_TRIGGER_1),
+        ("right_trigger_2", GamepadButton::GAMEPAD_BUTTON_RIGHT_TRIGGER_2),
+        ("middle_left", GamepadButton::GAMEPAD_BUTTON_MIDDLE_LEFT),
+        ("middle", GamepadButton::GAMEPAD_BUTTON_MIDDLE),
+        ("middle_right", GamepadButton::GAMEPAD_BUTTON_MIDDLE_RIGHT),
+        ("left_thumb", GamepadButton::GAMEPAD_BUTTON_LEFT_THUMB),
+        ("right_thumb", GamepadButton::GAMEPAD_BUTTON_RIGHT_THUMB),
+    ].into_iter().find(|(button_name, _)| *button_name == name).map(|(_, button)| button)
+}
+
+fn parse_gamepad_axis(name: &str) -> Option<GamepadAxis> {
+    [
+        ("left_x", GamepadAxis::GAMEPAD_AXIS_LEFT_X),
+        ("left_y", GamepadAxis::GAMEPAD_AXIS_LEFT_Y),
+        ("right_x", GamepadAxis::GAMEPAD_AXIS_RIGHT_X),
+        ("right_y", GamepadAxis::GAMEPAD_AXIS_RIGHT_Y),
+        ("left_trigger", GamepadAxis::GAMEPAD_AXIS_LEFT_TRIGGER),
+        ("right_trigger", GamepadAxis::GAMEPAD_AXIS_RIGHT_TRIGGER),
+    ].into_iter().find(|(axis_name, _)| *axis_name == name).map(|(_, axis)| axis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(entries: &str) -> Result<Controls, FormatError> {
+        parse_controls(&format!("voxel_battle_controls 1\n{}", entries))
+    }
+
+    fn parse_error(entries: &str) -> String {
+        match parse(entries) {
+            Err(FormatError::Parse { message, .. }) => message,
+            result => panic!("expected a parse error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn every_action_round_trips_through_its_name() {
+        for (name, action) in ACTIONS {
+            assert_eq!(action_name(action), name);
+            assert_eq!(parse_action(name), Some(action));
+        }
+        for (index, (name, action)) in ACTIONS.iter().enumerate() {
+            assert!(ACTIONS[index + 1..].iter().all(|(other_name, other)| other_name != name && other != action));
+        }
+        assert_eq!(parse_action("fly"), None);
+    }
+
+    #[test]
+    fn shipped_controls_file_matches_the_defaults() {
+        let controls = parse_controls(include_str!("../resources/controls.txt")).unwrap();
+        assert_eq!(controls, default_controls());
+    }
+
+    #[test]
+    fn actions_take_several_bindings_or_none() {
+        let controls = parse("bind keyboard dash key:left_shift key:x mouse:side\nbind keyboard join\n").unwrap();
+        assert_eq!(action_bindings(&controls, ControlDevice::Keyboard, Action::Dash), &[
+            Binding::Single(Control::Key(KeyboardKey::KEY_LEFT_SHIFT)),
+            Binding::Single(Control::Key(KeyboardKey::KEY_X)),
+            Binding::Single(Control::Mouse(MouseButton::MOUSE_BUTTON_SIDE)),
+        ]);
+        assert!(action_bindings(&controls, ControlDevice::Keyboard, Action::Join).is_empty());
+        assert_eq!(
+            action_bindings(&controls, ControlDevice::Gamepad, Action::MoveX),
+            &[Binding::Axis { axis: GamepadAxis::GAMEPAD_AXIS_LEFT_X, inverted: false }]
+        );
+
+        let controls = parse("bind gamepad move_y -axis:right_y button:left_face_up/button:left_face_down\n").unwrap();
+        assert_eq!(action_bindings(&controls, ControlDevice::Gamepad, Action::MoveY), &[
+            Binding::Axis { axis: GamepadAxis::GAMEPAD_AXIS_RIGHT_Y, inverted: true },
+            Binding::Pair(
+                Control::GamepadButton(GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_UP),
+                Control::GamepadButton(GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_DOWN),
+            ),
+        ]);
+    }
+
+    #[test]
+    fn deadzones_are_set_per_gamepad() {
+        let controls = parse("deadzone all 0.2\ndeadzone 2 0.35\n").unwrap();
+        assert_eq!(controls.deadzones, [0.2, 0.2, 0.35, 0.2]);
+
+        assert_eq!(parse_error("deadzone 4 0.2"), "invalid gamepad '4'");
+        assert_eq!(parse_error("deadzone first 0.2"), "invalid gamepad 'first'");
+        assert_eq!(parse_error("deadzone 0 1.0"), "deadzone must be at least 0 and below 1");
+        assert_eq!(parse_error("deadzone 0 -0.1"), "deadzone must be at least 0 and below 1");
+        assert_eq!(parse_error("deadzone 0"), "deadzone must be at least 0 and below 1");
+    }
+
+    #[test]
+    fn ctrl_prefix_needs_a_keyboard_button_action() {
+        assert_eq!(parse_binding("ctrl+key:z"), Some(Binding::WithCtrl(Control::Key(KeyboardKey::KEY_Z))));
+        assert_eq!(parse_binding("ctrl+mouse:left"), Some(Binding::WithCtrl(Control::Mouse(MouseButton::MOUSE_BUTTON_LEFT))));
+        assert_eq!(parse_binding("ctrl+key:nothing"), None);
+
+        let controls = parse("bind keyboard editor_undo ctrl+key:u key:backspace\n").unwrap();
+        assert_eq!(action_bindings(&controls, ControlDevice::Keyboard, Action::EditorUndo), &[
+            Binding::WithCtrl(Control::Key(KeyboardKey::KEY_U)),
+            Binding::Single(Control::Key(KeyboardKey::KEY_BACKSPACE)),
+        ]);
+        assert_eq!(
+            parse_error("bind gamepad join ctrl+button:middle"),
+            "ctrl+button:middle: gamepads only have buttons and axes"
+        );
+        assert_eq!(
+            parse_error("bind keyboard move_x ctrl+key:a"),
+            "ctrl+key:a: move_x needs a pair like key:a/key:d or a gamepad axis"
+        );
+    }
+
+    #[test]
+    fn invalid_entries_are_rejected() {
+        assert_eq!(parse_error("bind mouse dash key:x"), "unknown device 'mouse'");
+        assert_eq!(parse_error("bind keyboard fly key:x"), "unknown action 'fly'");
+        assert_eq!(parse_error("bind keyboard dash key:nothing"), "invalid binding 'key:nothing'");
+        assert_eq!(parse_error("bind keyboard2 editor_undo key:u"), "keyboard2 only has move_x, move_y, dash and join");
+        assert_eq!(parse_error("bind gamepad editor_place button:middle"), "editor_place can only be bound on the keyboard");
+        assert_eq!(parse_error("bind keyboard dash key:a/key:d"), "key:a/key:d: dash needs a single key or button");
+        assert_eq!(parse_error("bind keyboard move_x key:a"), "key:a: move_x needs a pair like key:a/key:d or a gamepad axis");
+        assert_eq!(
+            parse_error("bind keyboard2 dash button:middle"),
+            "button:middle: gamepad buttons and axes can't be bound on keyboard2"
+        );
+        assert_eq!(parse_error("unbind keyboard dash"), "unknown entry 'unbind'");
+        assert!(matches!(parse("bind keyboard\n"), Err(FormatError::Parse { line: 2, .. })));
+    }
+
+    #[test]
+    fn second_keyboard_keys_are_free_outside_replays() {
+        let controls = default_controls();
+        let keys = |device, action| -> Vec<Control> {
+            action_bindings(&controls, device, action).iter().flat_map(|binding| match *binding {
+                Binding::Single(control) | Binding::WithCtrl(control) => vec![control],
+                Binding::Pair(negative, positive) => vec![negative, positive],
+                Binding::Axis { .. } => Vec::new(),
+            }).collect()
+        };
+        let replay_actions = [Action::ReplayTogglePause, Action::ReplaySeek, Action::ReplaySpeed, Action::ReplayRestart];
+        for (_, second) in ACTIONS.into_iter().filter(|(_, action)| is_player_action(*action)) {
+            for (_, first) in ACTIONS.into_iter().filter(|(_, action)| !replay_actions.contains(action)) {
+                let first_keys = keys(ControlDevice::Keyboard, first);
+                for control in keys(ControlDevice::SecondKeyboard, second) {
+                    assert!(!first_keys.contains(&control), "{:?} is bound to {} on both keyboards", control, action_name(first));
+                }
+            }
+        }
+    }
+}
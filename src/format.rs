@@ -0,0 +1,100 @@
+use std::fmt;
+use std::str::FromStr;
+
+// A versioned data file. Text files start with a "<header> <version>" line, replays with the header bytes
+// followed by the version.
+pub struct FileFormat {
+    pub kind: &'static str, // What the file is called in error messages
+    pub header: &'static str,
+    pub version: u32, // Latest version, every version from 1 up to it loads
+}
+
+// Errors every file format shares, loaders with their own checks wrap it in their error type
+#[derive(Debug)]
+pub enum FormatError {
+    Io(std::io::Error),
+    MissingHeader { kind: &'static str, header: &'static str },
+    UnsupportedVersion { kind: &'static str, version: u32, latest: u32 },
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::Io(error) => write!(f, "i/o error: {}", error),
+            FormatError::MissingHeader { kind, header } => write!(f, "not a {} (missing '{}' header)", kind, header),
+            FormatError::UnsupportedVersion { kind, version, latest } => write!(
+                f, "unsupported {} version {} (latest is {})", kind, version, latest
+            ),
+            FormatError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+impl From<std::io::Error> for FormatError {
+    fn from(error: std::io::Error) -> Self {
+        FormatError::Io(error)
+    }
+}
+
+// Check the header line of a text file and return its version with the remaining lines. Lines come with
+// their 1-based numbers, comments after '#' and blank lines are left out.
+pub fn read_lines<'a>(format: &FileFormat, text: &'a str) -> Result<(u32, impl Iterator<Item = (usize, &'a str)>), FormatError> {
+    let mut lines = text.lines().enumerate()
+        .map(|(index, line)| (index + 1, line.split('#').next().unwrap_or("").trim()))
+        .filter(|(_, line)| !line.is_empty());
+
+    let (header_line, header) = lines.next().ok_or_else(|| missing_header(format))?;
+    let mut header_fields = header.split_whitespace();
+    if header_fields.next() != Some(format.header) {
+        return Err(missing_header(format));
+    }
+    let version = parse_field(header_fields.next(), header_line, "version")?;
+    check_version(format, version)?;
+    Ok((version, lines))
+}
+
+pub fn missing_header(format: &FileFormat) -> FormatError {
+    FormatError::MissingHeader { kind: format.kind, header: format.header }
+}
+
+pub fn check_version(format: &FileFormat, version: u32) -> Result<(), FormatError> {
+    if version == 0 || version > format.version {
+        return Err(FormatError::UnsupportedVersion { kind: format.kind, version, latest: format.version });
+    }
+    Ok(())
+}
+
+pub fn parse_field<T: FromStr>(field: Option<&str>, line: usize, name: &str) -> Result<T, FormatError> {
+    let field = field.ok_or_else(|| FormatError::Parse { line, message: format!("missing {}", name) })?;
+    field.parse().map_err(|_| FormatError::Parse { line, message: format!("invalid {} '{}'", name, field) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_FORMAT: FileFormat = FileFormat { kind: "test file", header: "test_header", version: 2 };
+
+    #[test]
+    fn lines_skip_comments_and_blank_lines() {
+        let text = "# leading comment\ntest_header 1\n\nfirst # trailing comment\n   \nsecond\n";
+        let (version, lines) = read_lines(&TEST_FORMAT, text).unwrap();
+        assert_eq!(version, 1);
+        assert_eq!(lines.collect::<Vec<_>>(), vec![(4, "first"), (6, "second")]);
+    }
+
+    #[test]
+    fn header_and_version_are_checked() {
+        assert!(matches!(read_lines(&TEST_FORMAT, ""), Err(FormatError::MissingHeader { .. })));
+        assert!(matches!(read_lines(&TEST_FORMAT, "other_header 1"), Err(FormatError::MissingHeader { .. })));
+        assert!(matches!(read_lines(&TEST_FORMAT, "test_header"), Err(FormatError::Parse { line: 1, .. })));
+        assert!(matches!(
+            read_lines(&TEST_FORMAT, "test_header 3"),
+            Err(FormatError::UnsupportedVersion { version: 3, latest: 2, .. })
+        ));
+        assert!(matches!(read_lines(&TEST_FORMAT, "test_header 0"), Err(FormatError::UnsupportedVersion { .. })));
+    }
+}
@@ -0,0 +1,444 @@
+use raylib::prelude::Vector3;
+use noise::{Fbm, MultiFractal, NoiseFn, Seedable, SuperSimplex};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+use std::fmt;
+use crate::state::{ArenaPreset, ArenaSettings, GameState, VoxelType, World};
+use crate::logic::{create_world, get_voxel, replace_world, set_voxel, respawn_players};
+use crate::config::{MAX_HILL_HEIGHT, SPAWN_AREA_RADIUS};
+
+const SPAWN_COUNT: usize = 5; // One spawn area per possible player
+const MAZE_CELL_SIZE: i32 = 3; // Corridor width plus one wall voxel
+const PILLAR_SPACING: i32 = 5;
+
+#[derive(Debug)]
+pub enum GenerationError {
+    UnreachableSpawn { x: i32, z: i32 },
+}
+
+impl fmt::Display for GenerationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenerationError::UnreachableSpawn { x, z } => write!(f, "spawn area at ({}, {}) cannot be reached", x, z),
+        }
+    }
+}
+
+impl std::error::Error for GenerationError {}
+
+pub fn arena_preset_name(preset: ArenaPreset) -> &'static str {
+    match preset {
+        ArenaPreset::Flat => "flat",
+        ArenaPreset::Hills => "hills",
+        ArenaPreset::Maze => "maze",
+        ArenaPreset::Pillars => "pillars",
+    }
+}
+
+pub fn parse_arena_preset(name: &str) -> Option<ArenaPreset> {
+    match name {
+        "flat" => Some(ArenaPreset::Flat),
+        "hills" => Some(ArenaPreset::Hills),
+        "maze" => Some(ArenaPreset::Maze),
+        "pillars" => Some(ArenaPreset::Pillars),
+        _ => None,
+    }
+}
+
+pub fn next_arena_preset(preset: ArenaPreset) -> ArenaPreset {
+    match preset {
+        ArenaPreset::Flat => ArenaPreset::Hills,
+        ArenaPreset::Hills => ArenaPreset::Maze,
+        ArenaPreset::Maze => ArenaPreset::Pillars,
+        ArenaPreset::Pillars => ArenaPreset::Flat,
+    }
+}
+
+// Replace the current world with a generated arena and move players onto its spawn points.
+// The state is left as it was if the arena cannot be generated.
+pub fn apply_generated_arena(state: &mut GameState, settings: ArenaSettings) -> Result<(), GenerationError> {
+    let world = &state.world;
+    let arena = generate_arena(world.width, world.height, world.depth, world.voxel_size, settings)?;
+    replace_world(&mut state.world, arena);
+    state.arena = Some(settings);
+    respawn_players(state);
+    Ok(())
+}
+
+// Build an arena from a seed. The same settings and dimensions always produce the same world.
+pub fn generate_arena(
+    width: i32, height: i32, depth: i32, voxel_size: f32, settings: ArenaSettings,
+) -> Result<World, GenerationError> {
+    let mut world = create_world(width, height, depth, voxel_size);
+    let mut rng = StdRng::seed_from_u64(settings.seed as u64);
+
+    // Keep room above the terrain for walls and players
+    let max_ground = (height - 4).clamp(1, MAX_HILL_HEIGHT + 1);
+    let mut heights = vec![1; (width * depth) as usize];
+
+    if settings.preset == ArenaPreset::Hills {
+        let terrain = Fbm::new()
+            .set_seed(settings.seed)
+            .set_octaves(3)
+            .set_frequency(0.08);
+        for x in 0..width {
+            for z in 0..depth {
+                let value = terrain.get([x as f64, z as f64]).clamp(-1.0, 1.0);
+                let ground = 1 + ((value + 1.0) / 2.0 * (max_ground - 1) as f64).round() as i32;
+                heights[(z * width + x) as usize] = ground.clamp(1, max_ground);
+            }
+        }
+    }
+
+    for x in 0..width {
+        for z in 0..depth {
+            for y in 0..heights[(z * width + x) as usize] {
+                set_voxel(&mut world, x, y, z, VoxelType::GROUND);
+            }
+        }
+    }
+
+    match settings.preset {
+        ArenaPreset::Flat => place_wall_clusters(&mut world, &heights, &mut rng, 6),
+        ArenaPreset::Hills => {
+            // Rocky outcrops where a second noise layer peaks
+            let rocks = SuperSimplex::new().set_seed(settings.seed.wrapping_add(1));
+            for x in 0..width {
+                for z in 0..depth {
+                    if rocks.get([x as f64 * 0.15, z as f64 * 0.15]) > 0.45 {
+                        let ground = heights[(z * width + x) as usize];
+                        fill_column(&mut world, x, z, ground, ground + 2, VoxelType::WALL);
+                    }
+                }
+            }
+        }
+        ArenaPreset::Maze => place_maze(&mut world, &heights, &mut rng),
+        ArenaPreset::Pillars => place_pillars(&mut world, &heights, &mut rng),
+    }
+
+    place_spawn_areas(&mut world, &mut heights)?;
+    Ok(world)
+}
+
+fn fill_column(world: &mut World, x: i32, z: i32, from_y: i32, to_y: i32, voxel_type: VoxelType) {
+    for y in from_y..to_y.min(world.height) {
+        set_voxel(world, x, y, z, voxel_type);
+    }
+}
+
+fn place_wall_clusters(world: &mut World, heights: &[i32], rng: &mut StdRng, count: usize) {
+    for _ in 0..count {
+        // Clusters never grow past the world edges, small worlds get narrower walls
+        let size_x = rng.gen_range(1..=4).min(world.width);
+        let size_z = rng.gen_range(1..=4).min(world.depth);
+        let wall_height = rng.gen_range(1..=3);
+        if size_x <= 0 || size_z <= 0 {
+            continue;
+        }
+        let start_x = rng.gen_range(0..(world.width - size_x).max(1));
+        let start_z = rng.gen_range(0..(world.depth - size_z).max(1));
+        for x in start_x..start_x + size_x {
+            for z in start_z..start_z + size_z {
+                let ground = heights[(z * world.width + x) as usize];
+                fill_column(world, x, z, ground, ground + wall_height, VoxelType::WALL);
+            }
+        }
+    }
+}
+
+fn place_maze(world: &mut World, heights: &[i32], rng: &mut StdRng) {
+    let cells_x = (world.width - 1) / MAZE_CELL_SIZE;
+    let cells_z = (world.depth - 1) / MAZE_CELL_SIZE;
+    if cells_x < 2 || cells_z < 2 {
+        return;
+    }
+
+    // Start fully walled, then carve passages with a randomized depth-first search
+    let mut open = vec![true; (world.width * world.depth) as usize];
+    for x in 0..world.width {
+        for z in 0..world.depth {
+            let on_grid_line = x % MAZE_CELL_SIZE == 0 || z % MAZE_CELL_SIZE == 0;
+            let inside = x <= cells_x * MAZE_CELL_SIZE && z <= cells_z * MAZE_CELL_SIZE;
+            open[(z * world.width + x) as usize] = !(on_grid_line && inside);
+        }
+    }
+
+    let mut visited = vec![false; (cells_x * cells_z) as usize];
+    let mut stack = vec![(rng.gen_range(0..cells_x), rng.gen_range(0..cells_z))];
+    visited[(stack[0].1 * cells_x + stack[0].0) as usize] = true;
+    while let Some(&(cx, cz)) = stack.last() {
+        let neighbors: Vec<(i32, i32)> = [(1, 0), (-1, 0), (0, 1), (0, -1)].iter()
+            .map(|(dx, dz)| (cx + dx, cz + dz))
+            .filter(|(nx, nz)| *nx >= 0 && *nx < cells_x && *nz >= 0 && *nz < cells_z)
+            .filter(|(nx, nz)| !visited[(nz * cells_x + nx) as usize])
+            .collect();
+        if neighbors.is_empty() {
+            stack.pop();
+            continue;
+        }
+        let (nx, nz) = neighbors[rng.gen_range(0..neighbors.len())];
+        visited[(nz * cells_x + nx) as usize] = true;
+        open_maze_passage(world, &mut open, (cx, cz), (nx, nz));
+        stack.push((nx, nz));
+    }
+
+    // Knock out extra walls so the maze has loops to fight around
+    let extra_openings = (cells_x * cells_z / 4).max(1);
+    for _ in 0..extra_openings {
+        let cx = rng.gen_range(0..cells_x - 1);
+        let cz = rng.gen_range(0..cells_z);
+        open_maze_passage(world, &mut open, (cx, cz), (cx + 1, cz));
+    }
+
+    for x in 0..world.width {
+        for z in 0..world.depth {
+            if !open[(z * world.width + x) as usize] {
+                let ground = heights[(z * world.width + x) as usize];
+                fill_column(world, x, z, ground, ground + 2, VoxelType::WALL);
+            }
+        }
+    }
+}
+
+fn open_maze_passage(world: &World, open: &mut [bool], from: (i32, i32), to: (i32, i32)) {
+    // The wall between two cells lies on the grid line shared by both
+    let (ax, az) = (from.0.min(to.0), from.1.min(to.1));
+    for offset in 1..MAZE_CELL_SIZE {
+        let (x, z) = if from.0 != to.0 {
+            ((ax + 1) * MAZE_CELL_SIZE, az * MAZE_CELL_SIZE + offset)
+        } else {
+            (ax * MAZE_CELL_SIZE + offset, (az + 1) * MAZE_CELL_SIZE)
+        };
+        if x < world.width && z < world.depth {
+            open[(z * world.width + x) as usize] = true;
+        }
+    }
+}
+
+fn place_pillars(world: &mut World, heights: &[i32], rng: &mut StdRng) {
+    let mut grid_x = PILLAR_SPACING / 2;
+    while grid_x < world.width {
+        let mut grid_z = PILLAR_SPACING / 2;
+        while grid_z < world.depth {
+            if rng.gen_bool(0.75) {
+                let x = (grid_x + rng.gen_range(-1..=1)).clamp(0, world.width - 1);
+                let z = (grid_z + rng.gen_range(-1..=1)).clamp(0, world.depth - 1);
+                let thickness = rng.gen_range(1..=2);
+                let pillar_height = rng.gen_range(2..=4);
+                for px in x..(x + thickness).min(world.width) {
+                    for pz in z..(z + thickness).min(world.depth) {
+                        let ground = heights[(pz * world.width + px) as usize];
+                        fill_column(world, px, pz, ground, ground + pillar_height, VoxelType::WALL);
+                    }
+                }
+            }
+            grid_z += PILLAR_SPACING;
+        }
+        grid_x += PILLAR_SPACING;
+    }
+}
+
+// Clear flat spawn areas on a ring around the center and make sure every one of them
+// can be walked to from the first
+fn place_spawn_areas(world: &mut World, heights: &mut [i32]) -> Result<(), GenerationError> {
+    let center_x = world.width as f32 / 2.0;
+    let center_z = world.depth as f32 / 2.0;
+    let radius = (world.width.min(world.depth) as f32 * 0.35).max(1.0);
+
+    let mut spawn_cells = Vec::new();
+    for index in 0..SPAWN_COUNT {
+        let angle = index as f32 / SPAWN_COUNT as f32 * std::f32::consts::TAU;
+        let x = ((center_x + angle.cos() * radius) as i32).clamp(0, world.width - 1);
+        let z = ((center_z + angle.sin() * radius) as i32).clamp(0, world.depth - 1);
+        if !spawn_cells.contains(&(x, z)) {
+            spawn_cells.push((x, z));
+        }
+    }
+
+    for &(spawn_x, spawn_z) in &spawn_cells {
+        let ground = heights[(spawn_z * world.width + spawn_x) as usize];
+        flatten_spawn_area(world, heights, (spawn_x, spawn_z), ground);
+    }
+
+    // Corridors may cut through spawn areas dug earlier, so keep checking until every spawn
+    // is connected. One pass per spawn is enough unless corridors keep breaking each other.
+    let (first_x, first_z) = spawn_cells[0];
+    for _ in 0..spawn_cells.len() {
+        let mut connected = true;
+        for &(spawn_x, spawn_z) in &spawn_cells {
+            let reachable = reachable_columns(world, heights, first_x, first_z);
+            if !reachable[(spawn_z * world.width + spawn_x) as usize] {
+                carve_corridor(world, heights, (spawn_x, spawn_z), (first_x, first_z));
+                connected = false;
+            }
+        }
+        if connected {
+            break;
+        }
+    }
+
+    let reachable = reachable_columns(world, heights, first_x, first_z);
+    for &(x, z) in &spawn_cells {
+        if !reachable[(z * world.width + x) as usize] {
+            return Err(GenerationError::UnreachableSpawn { x, z });
+        }
+        let ground = heights[(z * world.width + x) as usize];
+        world.spawn_points.push(Vector3::new(
+            x as f32 * world.voxel_size,
+            ground as f32 * world.voxel_size,
+            z as f32 * world.voxel_size,
+        ));
+    }
+    Ok(())
+}
+
+fn flatten_spawn_area(world: &mut World, heights: &mut [i32], (spawn_x, spawn_z): (i32, i32), ground: i32) {
+    for x in spawn_x - SPAWN_AREA_RADIUS..=spawn_x + SPAWN_AREA_RADIUS {
+        for z in spawn_z - SPAWN_AREA_RADIUS..=spawn_z + SPAWN_AREA_RADIUS {
+            if x >= 0 && x < world.width && z >= 0 && z < world.depth {
+                flatten_column(world, heights, x, z, ground);
+            }
+        }
+    }
+}
+
+// Make a column exactly `ground` voxels tall with no walls on top
+fn flatten_column(world: &mut World, heights: &mut [i32], x: i32, z: i32, ground: i32) {
+    for y in 0..world.height {
+        let voxel_type = if y < ground { VoxelType::GROUND } else { VoxelType::EMPTY };
+        set_voxel(world, x, y, z, voxel_type);
+    }
+    heights[(z * world.width + x) as usize] = ground;
+}
+
+// Columns a player can stand on: ground top with free space above
+fn is_column_walkable(world: &World, heights: &[i32], x: i32, z: i32) -> bool {
+    let ground = heights[(z * world.width + x) as usize];
+    get_voxel(world, x, ground, z) == VoxelType::EMPTY
+}
+
+// Flood fill over walkable columns, stepping at most one voxel up or down
+fn reachable_columns(world: &World, heights: &[i32], start_x: i32, start_z: i32) -> Vec<bool> {
+    let mut reachable = vec![false; (world.width * world.depth) as usize];
+    if !is_column_walkable(world, heights, start_x, start_z) {
+        return reachable;
+    }
+    let mut queue = VecDeque::new();
+    reachable[(start_z * world.width + start_x) as usize] = true;
+    queue.push_back((start_x, start_z));
+
+    while let Some((x, z)) = queue.pop_front() {
+        let ground = heights[(z * world.width + x) as usize];
+        for (dx, dz) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            let (nx, nz) = (x + dx, z + dz);
+            if nx < 0 || nx >= world.width || nz < 0 || nz >= world.depth {
+                continue;
+            }
+            let index = (nz * world.width + nx) as usize;
+            if reachable[index] || (heights[index] - ground).abs() > 1 || !is_column_walkable(world, heights, nx, nz) {
+                continue;
+            }
+            reachable[index] = true;
+            queue.push_back((nx, nz));
+        }
+    }
+    reachable
+}
+
+// Dig an L-shaped corridor from a spawn to another column, ramping at most one voxel per step
+// so it ends exactly at that column's height. A spawn too far above or below the end for the
+// ramp is moved to the closest height that works.
+fn carve_corridor(world: &mut World, heights: &mut [i32], from: (i32, i32), to: (i32, i32)) {
+    let target_ground = heights[(to.1 * world.width + to.0) as usize];
+    let length = (to.0 - from.0).abs() + (to.1 - from.1).abs();
+    let start_ground = heights[(from.1 * world.width + from.0) as usize]
+        .clamp(target_ground - length, target_ground + length);
+    flatten_spawn_area(world, heights, from, start_ground);
+
+    let (mut x, mut z) = from;
+    let mut step = 0;
+    while (x, z) != to {
+        if x != to.0 {
+            x += (to.0 - x).signum();
+        } else {
+            z += (to.1 - z).signum();
+        }
+        step += 1;
+        let ground = start_ground + (target_ground - start_ground).clamp(-step, step);
+        flatten_column(world, heights, x, z, ground);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::navigation::{is_standable, set_agent_size, update_navigation, walkable_regions};
+    use crate::state::{BlockRegistry, NavGrid, PlayerState};
+
+    const PRESETS: [ArenaPreset; 4] = [ArenaPreset::Flat, ArenaPreset::Hills, ArenaPreset::Maze, ArenaPreset::Pillars];
+
+    // Every spawn is inside the world, has room for a player and can be walked to from every other one
+    fn assert_spawns_playable(world: &World, expected_spawns: usize, context: &str) {
+        assert_eq!(world.spawn_points.len(), expected_spawns, "{}", context);
+        let size = PlayerState::default().size;
+        let blocks = BlockRegistry::default();
+        let mut nav = NavGrid::default();
+        set_agent_size(&mut nav, size);
+        update_navigation(&mut nav, world, &blocks);
+        let regions = walkable_regions(&nav);
+
+        let mut spawn_regions = Vec::new();
+        for spawn in &world.spawn_points {
+            let cell = (
+                (spawn.x / world.voxel_size).round() as i32,
+                (spawn.y / world.voxel_size).round() as i32,
+                (spawn.z / world.voxel_size).round() as i32,
+            );
+            assert!((0..world.width).contains(&cell.0) && (0..world.depth).contains(&cell.2), "{}: {:?}", context, cell);
+            assert!(is_standable(world, &blocks, size, cell), "{}: spawn {:?} is blocked", context, cell);
+            spawn_regions.push(regions.iter().position(|region| region.contains(&cell)));
+        }
+        assert!(spawn_regions[0].is_some(), "{}", context);
+        assert!(spawn_regions.iter().all(|region| *region == spawn_regions[0]), "{}: {:?}", context, spawn_regions);
+    }
+
+    #[test]
+    fn same_seed_generates_same_world() {
+        for preset in PRESETS {
+            let settings = ArenaSettings { seed: 1234, preset };
+            let first = generate_arena(25, 12, 25, 1.0, settings).unwrap();
+            let second = generate_arena(25, 12, 25, 1.0, settings).unwrap();
+            assert_eq!(first, second, "{:?}", preset);
+        }
+    }
+
+    #[test]
+    fn every_preset_has_reachable_spawns() {
+        for preset in PRESETS {
+            for seed in 0..16 {
+                let world = generate_arena(25, 12, 25, 1.0, ArenaSettings { seed, preset }).unwrap();
+                assert_spawns_playable(&world, SPAWN_COUNT, &format!("{:?} seed {}", preset, seed));
+            }
+        }
+    }
+
+    #[test]
+    fn corridor_ends_at_the_target_height() {
+        let mut world = create_world(8, 12, 3, 1.0);
+        let mut heights: Vec<i32> = (0..8 * 3).map(|index| if index % 8 == 0 { 11 } else { 1 }).collect();
+        heights[8 + 7] = 2;
+        carve_corridor(&mut world, &mut heights, (0, 1), (7, 1));
+        let corridor: Vec<i32> = (0..8).map(|x| heights[8 + x]).collect();
+        assert_eq!(corridor, vec![9, 8, 7, 6, 5, 4, 3, 2]);
+    }
+
+    #[test]
+    fn narrow_worlds_keep_their_spawns_reachable() {
+        for seed in 0..32 {
+            let world = generate_arena(2, 8, 3, 1.0, ArenaSettings { seed, preset: ArenaPreset::Flat }).unwrap();
+            assert_eq!(world.voxels.len(), 2 * 8 * 3);
+            assert_spawns_playable(&world, SPAWN_COUNT, &format!("seed {}", seed));
+        }
+    }
+}
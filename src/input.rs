@@ -0,0 +1,292 @@
+use raylib::prelude::*;
+use std::collections::HashMap;
+use crate::state::{Action, Binding, Control, ControlDevice, Controls, EditorInputFrame, EditorTool, InputFrame, PlayerInputFrame, ReplayInputFrame};
+use crate::controls::action_bindings;
+use crate::config::SECOND_KEYBOARD_ID;
+
+// Read the keyboard, gamepads and mouse through the bindings into a snapshot for the logic
+pub fn collect_input(camera: Camera3D, controls: &Controls) -> InputFrame {
+    let mut players = HashMap::new();
+    players.insert(0, collect_keyboard_input(controls, ControlDevice::Keyboard));
+    // The second keyboard player only exists while one of its actions is bound
+    let second_keyboard_bound = controls.bindings.iter()
+        .any(|((device, _), bindings)| *device == ControlDevice::SecondKeyboard && !bindings.is_empty());
+    if second_keyboard_bound {
+        players.insert(SECOND_KEYBOARD_ID, collect_keyboard_input(controls, ControlDevice::SecondKeyboard));
+    }
+
+    // Gamepad 0 -> Player 1, Gamepad 1 -> Player 2, etc.
+    let gamepads: Vec<i32> = (0..controls.deadzones.len() as i32)
+        .filter(|gamepad_id| unsafe { ffi::IsGamepadAvailable(*gamepad_id) })
+        .collect();
+    for gamepad_id in &gamepads {
+        players.insert(gamepad_id + 1, collect_gamepad_input(controls, *gamepad_id));
+    }
+
+    // Game actions work from the keyboard and from every gamepad
+    let game_action = |action: Action| {
+        action_pressed(controls, ControlDevice::Keyboard, action, 0)
+            || gamepads.iter().any(|gamepad_id| action_pressed(controls, ControlDevice::Gamepad, action, *gamepad_id))
+    };
+
+    InputFrame {
+        players,
+        toggle_editor: game_action(Action::ToggleEditor),
+        cycle_camera_mode: game_action(Action::CycleCameraMode),
+        toggle_bot_fill: game_action(Action::ToggleBotFill),
+        cycle_bot_difficulty: game_action(Action::CycleBotDifficulty),
+        toggle_mute: game_action(Action::ToggleMute),
+        editor: collect_editor_input(camera, controls),
+    }
+}
+
+fn collect_keyboard_input(controls: &Controls, device: ControlDevice) -> PlayerInputFrame {
+    PlayerInputFrame {
+        movement: Vector2::new(
+            action_value(controls, device, Action::MoveX, 0, 0.0),
+            action_value(controls, device, Action::MoveY, 0, 0.0),
+        ),
+        dash_pressed: action_pressed(controls, device, Action::Dash, 0),
+        join_pressed: action_pressed(controls, device, Action::Join, 0),
+    }
+}
+
+fn collect_gamepad_input(controls: &Controls, gamepad_id: i32) -> PlayerInputFrame {
+    let device = ControlDevice::Gamepad;
+    let deadzone = controls.deadzones[gamepad_id as usize];
+    let mut movement = Vector2::new(
+        action_value(controls, device, Action::MoveX, gamepad_id, deadzone),
+        action_value(controls, device, Action::MoveY, gamepad_id, deadzone),
+    );
+
+    // Normalize the vector if it's not zero
+    if movement.x != 0.0 || movement.y != 0.0 {
+        movement = movement.normalized();
+    }
+
+    PlayerInputFrame {
+        movement,
+        dash_pressed: action_pressed(controls, device, Action::Dash, gamepad_id),
+        // Gamepad players join by moving the stick, the join button toggles ready
+        join_pressed: action_pressed(controls, device, Action::Join, gamepad_id),
+    }
+}
+
+fn collect_editor_input(camera: Camera3D, controls: &Controls) -> EditorInputFrame {
+    let pressed = |action: Action| action_pressed(controls, ControlDevice::Keyboard, action, 0);
+    let down = |action: Action| action_down(controls, ControlDevice::Keyboard, action, 0);
+    let step = |action: Action| action_step(controls, ControlDevice::Keyboard, action, 0);
+    unsafe {
+        let mouse_pos = ffi::GetMousePosition();
+        EditorInputFrame {
+            mouse_ray: ffi::GetScreenToWorldRay(mouse_pos, camera.into()).into(),
+            mouse_delta: ffi::GetMouseDelta().into(),
+            mouse_wheel: ffi::GetMouseWheelMove(),
+            place_pressed: pressed(Action::EditorPlace),
+            place_down: down(Action::EditorPlace),
+            toggle_build_mode: pressed(Action::EditorToggleBuildMode),
+            rotate_down: down(Action::EditorRotate),
+            continuous_remove: down(Action::EditorContinuousRemove),
+            save_map: pressed(Action::EditorSaveMap),
+            load_map: pressed(Action::EditorLoadMap),
+            next_seed: pressed(Action::EditorNextSeed),
+            next_preset: pressed(Action::EditorNextPreset),
+            toggle_spawn_point: pressed(Action::EditorToggleSpawnPoint),
+            select_tool: collect_tool_selection(controls),
+            palette_step: step(Action::EditorPalette),
+            undo: pressed(Action::EditorUndo),
+            redo: pressed(Action::EditorRedo),
+            toggle_light: pressed(Action::EditorToggleLight),
+            select_next_light: pressed(Action::EditorSelectNextLight),
+            light_step: (step(Action::EditorLightX), step(Action::EditorLightY), step(Action::EditorLightZ)),
+            cycle_light_type: pressed(Action::EditorCycleLightType),
+            check_arena: pressed(Action::EditorCheckArena),
+        }
+    }
+}
+
+// Read the playback keys and camera controls while watching a replay.
+// The camera pans with the keyboard player's movement and turns with the editor's rotate binding.
+pub fn collect_replay_input(controls: &Controls) -> ReplayInputFrame {
+    let keyboard = ControlDevice::Keyboard;
+    unsafe {
+        ReplayInputFrame {
+            toggle_pause: action_pressed(controls, keyboard, Action::ReplayTogglePause, 0),
+            seek_step: action_step(controls, keyboard, Action::ReplaySeek, 0),
+            speed_step: action_step(controls, keyboard, Action::ReplaySpeed, 0),
+            restart: action_pressed(controls, keyboard, Action::ReplayRestart, 0),
+            pan: collect_keyboard_input(controls, keyboard).movement,
+            rotate_down: action_down(controls, keyboard, Action::EditorRotate, 0),
+            mouse_delta: ffi::GetMouseDelta().into(),
+            zoom: ffi::GetMouseWheelMove(),
+        }
+    }
+}
+
+fn collect_tool_selection(controls: &Controls) -> Option<EditorTool> {
+    let tools = [
+        (Action::EditorToolSingle, EditorTool::Single),
+        (Action::EditorToolBox, EditorTool::Box),
+        (Action::EditorToolHollowBox, EditorTool::HollowBox),
+        (Action::EditorToolLine, EditorTool::Line),
+        (Action::EditorToolFloodFill, EditorTool::FloodFill),
+    ];
+    tools.iter()
+        .find(|(action, _)| action_pressed(controls, ControlDevice::Keyboard, *action, 0))
+        .map(|(_, tool)| *tool)
+}
+
+// Whether a binding of a button action is held, gamepad controls are read from `gamepad_id`
+fn action_down(controls: &Controls, device: ControlDevice, action: Action, gamepad_id: i32) -> bool {
+    action_bindings(controls, device, action).iter().any(|binding| match *binding {
+        Binding::Single(control) => control_down(control, gamepad_id),
+        Binding::WithCtrl(control) => ctrl_down() && control_down(control, gamepad_id),
+        _ => false,
+    })
+}
+
+fn action_pressed(controls: &Controls, device: ControlDevice, action: Action, gamepad_id: i32) -> bool {
+    action_bindings(controls, device, action).iter().any(|binding| match *binding {
+        Binding::Single(control) => control_pressed(control, gamepad_id),
+        Binding::WithCtrl(control) => ctrl_down() && control_pressed(control, gamepad_id),
+        _ => false,
+    })
+}
+
+// Held direction of an axis action from -1 to 1, axis movement up to the deadzone counts as centered
+fn action_value(controls: &Controls, device: ControlDevice, action: Action, gamepad_id: i32, deadzone: f32) -> f32 {
+    let value: f32 = action_bindings(controls, device, action).iter().map(|binding| match *binding {
+        Binding::Pair(negative, positive) => {
+            control_down(positive, gamepad_id) as i32 as f32 - control_down(negative, gamepad_id) as i32 as f32
+        }
+        Binding::Axis { axis, inverted } => {
+            let movement = unsafe { ffi::GetGamepadAxisMovement(gamepad_id, axis as i32) };
+            let movement = if inverted { -movement } else { movement };
+            if movement.abs() > deadzone { movement } else { 0.0 }
+        }
+        _ => 0.0,
+    }).sum();
+    value.clamp(-1.0, 1.0)
+}
+
+// Presses of an axis action this frame, 1 for the positive direction and -1 for the negative one
+fn action_step(controls: &Controls, device: ControlDevice, action: Action, gamepad_id: i32) -> i32 {
+    action_bindings(controls, device, action).iter().map(|binding| match *binding {
+        Binding::Pair(negative, positive) => {
+            control_pressed(positive, gamepad_id) as i32 - control_pressed(negative, gamepad_id) as i32
+        }
+        _ => 0,
+    }).sum()
+}
+
+fn control_down(control: Control, gamepad_id: i32) -> bool {
+    unsafe {
+        match control {
+            Control::Key(key) => ffi::IsKeyDown(key as i32),
+            Control::Mouse(button) => ffi::IsMouseButtonDown(button as i32),
+            Control::GamepadButton(button) => ffi::IsGamepadButtonDown(gamepad_id, button as i32),
+        }
+    }
+}
+
+fn control_pressed(control: Control, gamepad_id: i32) -> bool {
+    unsafe {
+        match control {
+            Control::Key(key) => ffi::IsKeyPressed(key as i32),
+            Control::Mouse(button) => ffi::IsMouseButtonPressed(button as i32),
+            Control::GamepadButton(button) => ffi::IsGamepadButtonPressed(gamepad_id, button as i32),
+        }
+    }
+}
+
+fn ctrl_down() -> bool {
+    unsafe { ffi::IsKeyDown(KeyboardKey::KEY_LEFT_CONTROL as i32) || ffi::IsKeyDown(KeyboardKey::KEY_RIGHT_CONTROL as i32) }
+}
+
+// Input of the single player controlled on this machine in network games:
+// the keyboard, or the first gamepad while the keyboard is idle
+pub fn local_player_input(frame: &InputFrame) -> PlayerInputFrame {
+    let keyboard = frame.players.get(&0).copied().unwrap_or_default();
+    let gamepad = frame.players.get(&1).copied().unwrap_or_default();
+    PlayerInputFrame {
+        movement: if keyboard.movement != Vector2::zero() { keyboard.movement } else { gamepad.movement },
+        dash_pressed: keyboard.dash_pressed || gamepad.dash_pressed,
+        join_pressed: keyboard.join_pressed || gamepad.join_pressed,
+    }
+}
+
+// Fold a newly collected frame into input waiting for the next simulation tick.
+// Held state comes from the latest frame, presses are kept until a tick consumes them.
+pub fn merge_input(pending: &mut InputFrame, latest: InputFrame) {
+    let mut players = latest.players;
+    for (id, player) in players.iter_mut() {
+        if let Some(previous) = pending.players.get(id) {
+            player.dash_pressed |= previous.dash_pressed;
+            player.join_pressed |= previous.join_pressed;
+        }
+    }
+
+    let previous = pending.editor;
+    let mut editor = latest.editor;
+    editor.mouse_delta += previous.mouse_delta;
+    editor.mouse_wheel += previous.mouse_wheel;
+    editor.place_pressed |= previous.place_pressed;
+    editor.toggle_build_mode |= previous.toggle_build_mode;
+    editor.save_map |= previous.save_map;
+    editor.load_map |= previous.load_map;
+    editor.next_seed |= previous.next_seed;
+    editor.next_preset |= previous.next_preset;
+    editor.toggle_spawn_point |= previous.toggle_spawn_point;
+    editor.select_tool = editor.select_tool.or(previous.select_tool);
+    editor.palette_step += previous.palette_step;
+    editor.undo |= previous.undo;
+    editor.redo |= previous.redo;
+    editor.toggle_light |= previous.toggle_light;
+    editor.select_next_light |= previous.select_next_light;
+    editor.light_step.0 += previous.light_step.0;
+    editor.light_step.1 += previous.light_step.1;
+    editor.light_step.2 += previous.light_step.2;
+    editor.cycle_light_type |= previous.cycle_light_type;
+    editor.check_arena |= previous.check_arena;
+
+    *pending = InputFrame {
+        players,
+        toggle_editor: latest.toggle_editor || pending.toggle_editor,
+        cycle_camera_mode: latest.cycle_camera_mode || pending.cycle_camera_mode,
+        toggle_bot_fill: latest.toggle_bot_fill || pending.toggle_bot_fill,
+        cycle_bot_difficulty: latest.cycle_bot_difficulty || pending.cycle_bot_difficulty,
+        toggle_mute: latest.toggle_mute || pending.toggle_mute,
+        editor,
+    };
+}
+
+// Drop presses and accumulated mouse motion once a tick has consumed them
+pub fn clear_pressed(frame: &mut InputFrame) {
+    for player in frame.players.values_mut() {
+        player.dash_pressed = false;
+        player.join_pressed = false;
+    }
+    frame.toggle_editor = false;
+    frame.cycle_camera_mode = false;
+    frame.toggle_bot_fill = false;
+    frame.cycle_bot_difficulty = false;
+    frame.toggle_mute = false;
+    frame.editor.mouse_delta = Vector2::zero();
+    frame.editor.mouse_wheel = 0.0;
+    frame.editor.place_pressed = false;
+    frame.editor.toggle_build_mode = false;
+    frame.editor.save_map = false;
+    frame.editor.load_map = false;
+    frame.editor.next_seed = false;
+    frame.editor.next_preset = false;
+    frame.editor.toggle_spawn_point = false;
+    frame.editor.select_tool = None;
+    frame.editor.palette_step = 0;
+    frame.editor.undo = false;
+    frame.editor.redo = false;
+    frame.editor.toggle_light = false;
+    frame.editor.select_next_light = false;
+    frame.editor.light_step = (0, 0, 0);
+    frame.editor.cycle_light_type = false;
+    frame.editor.check_arena = false;
+}
@@ -2,9 +2,25 @@ pub mod config;
 pub mod state;
 pub mod logic;
 pub mod rendering;
+pub mod shaders;
 pub mod utils;
+pub mod map;
+pub mod meshing;
+pub mod blocks;
+pub mod postprocess;
+pub mod generation;
+pub mod input;
+pub mod args;
+pub mod network;
+pub mod bots;
+pub mod navigation;
+pub mod audio;
+pub mod replay;
+pub mod controls;
+pub mod format;
 
-pub use state::GameState;
+pub use state::{GameState, InputFrame};
 pub use logic::{init, update};
 pub use rendering::render;
+pub use input::{collect_input, merge_input, clear_pressed};
 pub use config::{SCREEN_WIDTH, SCREEN_HEIGHT, VOXEL_SIZE, DEBUG_COLOR, GRID_COLOR, LIGHT_COLOR, PLAYER_COLORS};
\ No newline at end of file
@@ -1,200 +1,483 @@
 use raylib::prelude::*;
-use raylib::ffi::ShaderLocationIndex::SHADER_LOC_VECTOR_VIEW;
-use raylib::ffi::ShaderUniformDataType::SHADER_UNIFORM_VEC4;
-use crate::state::{GameState, PlayerInput, PlayerState, ShaderType, VoxelType, World, Voxel};
+use std::collections::{HashMap, HashSet, VecDeque};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use crate::state::{
+    ArenaPreset, ArenaSettings, BlockRegistry, CameraMode, CameraState, EditorInputFrame, EditorTool, GameEvent, GameState, InputFrame, LightSource, LightType, MatchPhase,
+    PlayerCamera, PlayerInput, PlayerInputFrame, PlayerState, SimulationRng, VoxelEdit, VoxelType, World, Voxel,
+};
 use crate::utils::{generate_random_color, lerp_f32};
-use crate::config::{GLSL_VERSION, PLAYER_COLORS};
+use crate::config::{
+    PLAYER_COLORS, EDITOR_STATUS_DURATION, DASH_DAMAGE_PER_SPEED, DASH_KNOCKBACK, HIT_INVULNERABILITY, BLOCK_DAMAGE_PER_SPEED, GRAVITY,
+    MAX_FALL_SPEED, BOUNCE_MIN_SPEED, FALL_ELIMINATION_DEPTH, BLOCKS_PATH, PLAYER_MAX_HEALTH, MIN_PLAYERS_TO_START, COUNTDOWN_DURATION,
+    ROUND_OVER_DURATION, MATCH_OVER_DURATION, EDITOR_HISTORY_LIMIT, EDITOR_FLOOD_FILL_LIMIT, CHUNK_SIZE, MAX_LIGHTS, EDITOR_LIGHT_HEIGHT,
+    MAX_SPLIT_PANES, SPLIT_DISTANCE, MERGE_DISTANCE, PLAYER_CAMERA_HEIGHT, PLAYER_CAMERA_DISTANCE, WALL_BUMP_MIN_SPEED, SECOND_KEYBOARD_ID,
+};
+use crate::map::{save_map, load_map};
+use crate::blocks::{block_properties, load_blocks};
+use crate::generation::{apply_generated_arena, arena_preset_name, next_arena_preset};
+use crate::navigation::{is_standable, node_at, set_agent_size, standing_cell, update_navigation, walkable_regions};
 
 
 pub fn init(state: &mut GameState) {
-    state.world = World::default();
+    state.blocks = match load_blocks(BLOCKS_PATH) {
+        Ok(blocks) => blocks,
+        Err(error) => {
+            eprintln!("Failed to load blocks from {}, using built-in ground and wall: {}", BLOCKS_PATH, error);
+            BlockRegistry::default()
+        }
+    };
+    replace_world(&mut state.world, World::default());
+    state.lights = vec![LightSource::default()];
+    set_agent_size(&mut state.navigation, PlayerState::default().size);
+    seed_simulation(state, rand::random());
     state.next_player_id = 1; // Start from 1 for gamepad players
     
     // Create floor voxels
     for x in 0..state.world.width {
         for z in 0..state.world.depth {
-            set_voxel(&mut state.world, x, 0, z, VoxelType::Ground);
+            set_voxel(&mut state.world, x, 0, z, VoxelType::GROUND);
         }
     }
     
-    // Initialize keyboard player input but don't create the player yet
-    state.player_inputs.insert(0, PlayerInput {
-        movement: Vector2::zero(),
-        movement_speed: 5.0, // 5 units per second
-    });
+    // Initialize keyboard player inputs but don't create the players yet
+    for id in [0, SECOND_KEYBOARD_ID] {
+        state.player_inputs.insert(id, PlayerInput {
+            movement: Vector2::zero(),
+            movement_speed: 5.0, // 5 units per second
+        });
+    }
+}
 
-    // Load basic lighting shader
-    let vs_path = std::ffi::CString::new(format!("resources/shaders/glsl{}/lighting.vs", GLSL_VERSION)).unwrap();
-    let fs_path = std::ffi::CString::new(format!("resources/shaders/glsl{}/lighting.fs", GLSL_VERSION)).unwrap();
-    
-    let shader = unsafe {
-        ffi::LoadShader(vs_path.as_ptr(), fs_path.as_ptr())
-    };
-    
-    // Get some required shader locations
-    let view_pos = std::ffi::CString::new("viewPos").unwrap();
-    unsafe {
-        let loc = ffi::GetShaderLocation(shader, view_pos.as_ptr());
-        *shader.locs.offset(SHADER_LOC_VECTOR_VIEW as isize) = loc;
+// Restart the random choices of the simulation, the same seed and inputs replay the same game
+pub fn seed_simulation(state: &mut GameState, seed: u64) {
+    state.rng = SimulationRng { seed, rng: StdRng::seed_from_u64(seed) };
+}
+
+// Advance the simulation by one fixed tick
+pub fn update(state: &mut GameState, input: &InputFrame, delta: f32) {
+    // Remember where everything was so the renderer can interpolate between ticks
+    for player in state.players.values_mut() {
+        player.previous_position = player.position;
     }
-    
-    // Ambient light level (some basic lighting)
-    let ambient = std::ffi::CString::new("ambient").unwrap();
-    let ambient_loc = unsafe { ffi::GetShaderLocation(shader, ambient.as_ptr()) };
-    let ambient_color = [0.3f32, 0.3f32, 0.3f32, 1.0f32]; // Increased ambient light
-    unsafe {
-        ffi::SetShaderValue(shader, ambient_loc, ambient_color.as_ptr() as *const std::ffi::c_void, SHADER_UNIFORM_VEC4 as i32);
-    }
-
-    // Set up light source
-    let light_enabled = std::ffi::CString::new("lights[0].enabled").unwrap();
-    let light_type = std::ffi::CString::new("lights[0].type").unwrap();
-    let light_position = std::ffi::CString::new("lights[0].position").unwrap();
-    let light_color = std::ffi::CString::new("lights[0].color").unwrap();
-    
-    unsafe {
-        // Enable light
-        let enabled_loc = ffi::GetShaderLocation(shader, light_enabled.as_ptr());
-        let enabled = [1i32];
-        ffi::SetShaderValue(shader, enabled_loc, enabled.as_ptr() as *const std::ffi::c_void, ffi::ShaderUniformDataType::SHADER_UNIFORM_INT as i32);
-        
-        // Set light type to directional light
-        let type_loc = ffi::GetShaderLocation(shader, light_type.as_ptr());
-        let light_type = [0i32]; // LIGHT_DIRECTIONAL
-        ffi::SetShaderValue(shader, type_loc, light_type.as_ptr() as *const std::ffi::c_void, ffi::ShaderUniformDataType::SHADER_UNIFORM_INT as i32);
-        
-        // Set light target (direction)
-        let light_target = std::ffi::CString::new("lights[0].target").unwrap();
-        let target_loc = ffi::GetShaderLocation(shader, light_target.as_ptr());
-        let target = [state.light_source.target.x, state.light_source.target.y, state.light_source.target.z];
-        ffi::SetShaderValue(shader, target_loc, target.as_ptr() as *const std::ffi::c_void, ffi::ShaderUniformDataType::SHADER_UNIFORM_VEC3 as i32);
-        
-        // Set light position (still needed for directional light)
-        let pos_loc = ffi::GetShaderLocation(shader, light_position.as_ptr());
-        let pos = [state.light_source.position.x, state.light_source.position.y, state.light_source.position.z];
-        ffi::SetShaderValue(shader, pos_loc, pos.as_ptr() as *const std::ffi::c_void, ffi::ShaderUniformDataType::SHADER_UNIFORM_VEC3 as i32);
+    remember_cameras(&mut state.camera_state);
+    state.events.clear();
+
+    // Toggle debug mode
+    if input.toggle_editor {
+        state.editor.active = !state.editor.active;
         
-        // Set light color with reduced intensity
-        let color_loc = ffi::GetShaderLocation(shader, light_color.as_ptr());
-        let color = [
-            state.light_source.color.r as f32 / 255.0 * 0.8, // Reduced intensity
-            state.light_source.color.g as f32 / 255.0 * 0.8,
-            state.light_source.color.b as f32 / 255.0 * 0.8,
-            state.light_source.color.a as f32 / 255.0
-        ];
-        ffi::SetShaderValue(shader, color_loc, color.as_ptr() as *const std::ffi::c_void, SHADER_UNIFORM_VEC4 as i32);
+        if state.editor.active {
+            // Save camera state before entering debug mode
+            state.editor.camera.game_camera = Some(state.camera_state.camera);
+            state.editor.camera.game_camera_offset = Some(state.camera_state.offset);
+            state.editor.camera.game_camera_height = Some(state.camera_state.height);
+            state.editor.camera.game_camera_angle = Some(state.camera_state.angle);
+        }
     }
+    if input.cycle_camera_mode {
+        cycle_camera_mode(state);
+    }
+
+    update_player_inputs(state, input);
+    update_player_position(state, input, delta);
+    apply_block_damage(state, delta);
+    resolve_player_collisions(state);
+    update_eliminations(state);
+    update_match(state, delta);
+    handle_voxel_input(state, &input.editor);
+    handle_history_input(state, &input.editor);
+    handle_map_input(state, &input.editor);
+    handle_light_input(state, &input.editor);
+    update_camera(state, &input.editor, delta);
+    update_editor_status(state, delta);
 
-    state.shaders.insert(ShaderType::Lighting, shader);
+    // Only the chunks changed during the tick are looked at again
+    update_navigation(&mut state.navigation, &state.world, &state.blocks);
 }
 
-pub fn update(state: &mut GameState, delta: f32) {
-    // Toggle debug mode with M
-    unsafe {
-        if ffi::IsKeyPressed(KeyboardKey::KEY_M as i32) {
-            state.editor.active = !state.editor.active;
-            
-            if state.editor.active {
-                // Save camera state before entering debug mode
-                state.editor.camera.game_camera = Some(state.camera_state.camera);
-                state.editor.camera.game_camera_offset = Some(state.camera_state.offset);
-                state.editor.camera.game_camera_height = Some(state.camera_state.height);
-                state.editor.camera.game_camera_angle = Some(state.camera_state.angle);
+fn create_player(world: &World, blocks: &BlockRegistry, players: &HashMap<i32, PlayerState>, color: Color) -> PlayerState {
+    let position = choose_spawn_position(world, blocks, players);
+    PlayerState {
+        position,
+        previous_position: position,
+        color,
+        original_color: color,
+        ..PlayerState::default()
+    }
+}
+
+// Pick the first map spawn point not taken by a player, preferring spawn points with room to stand.
+// Maps without spawn points place players on a ring around the world center.
+fn choose_spawn_position(world: &World, blocks: &BlockRegistry, players: &HashMap<i32, PlayerState>) -> Vector3 {
+    let is_free = |spawn: &Vector3| players.values().all(|player| {
+        let dx = player.position.x - spawn.x;
+        let dz = player.position.z - spawn.z;
+        (dx * dx + dz * dz).sqrt() > world.voxel_size
+    });
+
+    let center = Vector3::new(
+        world.width as f32 / 2.0, // Center X
+        1.0, // Set player 1 unit above the floor
+        world.depth as f32 / 2.0, // Center Z
+    );
+    if world.spawn_points.is_empty() {
+        let radius = world.width.min(world.depth) as f32 / 4.0;
+        let slots = PLAYER_COLORS.len();
+        return (0..slots)
+            .map(|slot| {
+                let angle = slot as f32 / slots as f32 * std::f32::consts::TAU;
+                center + Vector3::new(angle.cos() * radius, 0.0, angle.sin() * radius)
+            })
+            .find(|spawn| is_free(spawn))
+            .unwrap_or(center);
+    }
+
+    let size = PlayerState::default().size;
+    let can_stand = |spawn: &Vector3| is_standable(world, blocks, size, standing_cell(world, size, *spawn));
+    if let Some(spawn) = world.spawn_points.iter().find(|spawn| is_free(spawn) && can_stand(spawn)) {
+        *spawn
+    } else if let Some(spawn) = world.spawn_points.iter().find(|spawn| is_free(spawn)) {
+        *spawn
+    } else {
+        world.spawn_points[0]
+    }
+}
+
+fn set_editor_status(state: &mut GameState, message: String) {
+    state.editor.status_message = Some(message);
+    state.editor.status_timer = EDITOR_STATUS_DURATION;
+}
+
+fn update_editor_status(state: &mut GameState, delta: f32) {
+    if state.editor.status_timer > 0.0 {
+        state.editor.status_timer -= delta;
+        if state.editor.status_timer <= 0.0 {
+            state.editor.status_message = None;
+        }
+    }
+}
+
+// L adds a point light above the hovered cell or deletes the light in its column, Tab selects the next light,
+// the keypad arrows and Page Up / Down move it and T changes its type
+fn handle_light_input(state: &mut GameState, input: &EditorInputFrame) {
+    if !state.editor.active {
+        return;
+    }
+    // Map loads and the server replace the lights wholesale
+    if state.editor.selected_light.is_some_and(|index| index >= state.lights.len()) {
+        state.editor.selected_light = None;
+    }
+
+    if input.toggle_light {
+        if let Some((x, y, z)) = state.editor.hovered_voxel {
+            let voxel_size = state.world.voxel_size;
+            let cell = Vector3::new(x as f32, y as f32, z as f32) * voxel_size;
+            let existing = state.lights.iter().position(|light| {
+                let dx = light.position.x - cell.x;
+                let dz = light.position.z - cell.z;
+                (dx * dx + dz * dz).sqrt() < voxel_size / 2.0
+            });
+            match existing {
+                Some(index) => {
+                    state.lights.remove(index);
+                    state.editor.selected_light = None;
+                    set_editor_status(state, format!("Deleted light {}", index + 1));
+                }
+                None if state.lights.len() >= MAX_LIGHTS => {
+                    set_editor_status(state, format!("A map can have at most {} lights", MAX_LIGHTS));
+                }
+                None => {
+                    // Pointing down at the cell, so switching the light to a spot light lights it up
+                    state.lights.push(LightSource {
+                        light_type: LightType::Point,
+                        position: cell + Vector3::new(0.0, EDITOR_LIGHT_HEIGHT as f32 * voxel_size, 0.0),
+                        target: cell,
+                        ..LightSource::default()
+                    });
+                    let count = state.lights.len();
+                    state.editor.selected_light = Some(count - 1);
+                    set_editor_status(state, format!("Added light {}", count));
+                }
             }
         }
     }
 
-    update_player_inputs(state);
-    update_player_position(state, delta);
-    handle_voxel_input(state);
-    update_camera(state, delta);
+    if input.select_next_light && !state.lights.is_empty() {
+        let next = state.editor.selected_light.map_or(0, |index| (index + 1) % state.lights.len());
+        state.editor.selected_light = Some(next);
+    }
+
+    let Some(index) = state.editor.selected_light else {
+        return;
+    };
+    let (dx, dy, dz) = input.light_step;
+    if (dx, dy, dz) != (0, 0, 0) {
+        // The target moves along so directional and spot lights keep shining the same way
+        let step = Vector3::new(dx as f32, dy as f32, dz as f32) * state.world.voxel_size;
+        let light = &mut state.lights[index];
+        light.position += step;
+        light.target += step;
+    }
+    if input.cycle_light_type {
+        let light = &mut state.lights[index];
+        light.light_type = match light.light_type {
+            LightType::Directional => LightType::Point,
+            LightType::Point => LightType::Spot,
+            LightType::Spot => LightType::Directional,
+        };
+        let message = format!("Light {} is now a {} light", index + 1, light_type_name(light.light_type));
+        set_editor_status(state, message);
+    }
 }
 
-fn update_player_inputs(state: &mut GameState) {
+fn handle_map_input(state: &mut GameState, input: &EditorInputFrame) {
+    if !state.editor.active {
+        return;
+    }
+
+    // F5 saves the arena, F9 reloads it from disk
+    if input.save_map {
+        let path = state.editor.map_path.clone();
+        match save_map(state, &path) {
+            Ok(()) => set_editor_status(state, format!("Saved map to {}", path)),
+            Err(error) => set_editor_status(state, format!("Failed to save {}: {}", path, error)),
+        }
+    }
+
+    if input.load_map {
+        let path = state.editor.map_path.clone();
+        match load_map(state, &path) {
+            Ok(()) => {
+                state.arena = None;
+                clear_edit_history(state);
+                forget_round_world(state);
+                respawn_players(state);
+                state.editor.selected_light = None;
+                set_editor_status(state, format!("Loaded map from {}", path));
+            }
+            Err(error) => set_editor_status(state, format!("Failed to load {}: {}", path, error)),
+        }
+    }
+
+    // F6 generates an arena with the next seed, F7 switches to the next preset
+    let generate_next_seed = input.next_seed;
+    let generate_next_preset = input.next_preset;
+    if generate_next_seed || generate_next_preset {
+        let mut settings = state.arena.unwrap_or(ArenaSettings { seed: 0, preset: ArenaPreset::Flat });
+        if generate_next_seed {
+            settings.seed = settings.seed.wrapping_add(1);
+        }
+        if generate_next_preset {
+            settings.preset = next_arena_preset(settings.preset);
+        }
+        let preset_name = arena_preset_name(settings.preset);
+        match apply_generated_arena(state, settings) {
+            Ok(()) => {
+                clear_edit_history(state);
+                forget_round_world(state);
+                set_editor_status(state, format!("Generated {} arena (seed {})", preset_name, settings.seed));
+            }
+            Err(error) => set_editor_status(
+                state, format!("Failed to generate {} arena (seed {}): {}", preset_name, settings.seed, error),
+            ),
+        }
+    }
+
+    // P toggles a spawn point on the hovered cell
+    if input.toggle_spawn_point && !arena_locked(state, true) {
+        if let Some((x, y, z)) = state.editor.hovered_voxel {
+            // In remove mode the hovered cell is the solid voxel, so spawn on top of it
+            let y = if state.editor.build_mode { y } else { y + 1 };
+            if is_valid_position(&state.world, x, y, z) {
+                let voxel_size = state.world.voxel_size;
+                let position = Vector3::new(x as f32 * voxel_size, y as f32 * voxel_size, z as f32 * voxel_size);
+                let existing = state.world.spawn_points.iter()
+                    .position(|spawn| (*spawn - position).length() < voxel_size / 2.0);
+                match existing {
+                    Some(index) => {
+                        state.world.spawn_points.remove(index);
+                    }
+                    None => {
+                        state.world.spawn_points.push(position);
+                        let size = state.navigation.agent_size;
+                        if !is_standable(&state.world, &state.blocks, size, standing_cell(&state.world, size, position)) {
+                            set_editor_status(state, format!("Spawn point ({}, {}, {}) has no room to stand", x, y, z));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // F8 checks that players can walk between all parts of the arena and from every spawn point
+    if input.check_arena {
+        update_navigation(&mut state.navigation, &state.world, &state.blocks);
+        let message = arena_connectivity_report(state);
+        set_editor_status(state, message);
+    }
+}
+
+// Number of separate walkable areas and the spawn points outside the largest one
+fn arena_connectivity_report(state: &GameState) -> String {
+    let regions = walkable_regions(&state.navigation);
+    let Some(main_region) = regions.first() else {
+        return "Arena has nowhere to stand".to_string();
+    };
+    let main_region: HashSet<(i32, i32, i32)> = main_region.iter().copied().collect();
+
+    let stranded: Vec<String> = state.world.spawn_points.iter().enumerate()
+        .filter(|(_, spawn)| !node_at(&state.navigation, &state.world, **spawn).is_some_and(|node| main_region.contains(&node)))
+        .map(|(index, _)| (index + 1).to_string())
+        .collect();
+
+    let mut message = if regions.len() == 1 {
+        "Arena is fully connected".to_string()
+    } else {
+        format!("Arena has {} separate areas", regions.len())
+    };
+    if !stranded.is_empty() {
+        message += &format!(", spawn points {} can't reach the largest one", stranded.join(", "));
+    }
+    message
+}
+
+// Create the player for an input ID, fails when every player color is taken
+pub fn add_player(state: &mut GameState, id: i32) -> bool {
+    let used_colors: Vec<Color> = state.players.values()
+        .map(|p| p.color)
+        .collect();
+    let Some(color) = generate_random_color(&mut state.rng.rng, &used_colors, &PLAYER_COLORS) else {
+        return false;
+    };
+
+    state.player_inputs.insert(id, PlayerInput {
+        movement: Vector2::zero(),
+        movement_speed: 5.0, // Same speed as local players
+    });
+    let mut player = create_player(&state.world, &state.blocks, &state.players, color);
+    // Players joining a round in progress wait for the next one
+    player.is_alive = state.match_state.phase != MatchPhase::Playing;
+    state.events.push(GameEvent::PlayerJoined { player_id: id, position: player.position });
+    state.players.insert(id, player);
+    true
+}
+
+pub fn remove_player(state: &mut GameState, id: i32) {
+    state.players.remove(&id);
+    state.player_inputs.remove(&id);
+}
+
+// Advance a network client by one tick: only the local player is simulated (predicted),
+// everyone else is taken from server snapshots
+pub fn update_client(state: &mut GameState, local_id: i32, input: &PlayerInputFrame, delta: f32) {
+    if let Some(player) = state.players.get_mut(&local_id) {
+        player.previous_position = player.position;
+    }
+    remember_cameras(&mut state.camera_state);
+
+    predict_player(state, local_id, input, delta);
+    update_camera(state, &EditorInputFrame::default(), delta);
+}
+
+// Run one tick of a single player exactly like the server does
+pub fn predict_player(state: &mut GameState, id: i32, input: &PlayerInputFrame, delta: f32) {
+    let Some(player_input) = state.player_inputs.get_mut(&id) else {
+        return;
+    };
+    player_input.movement = input.movement;
+    if state.match_state.phase == MatchPhase::Countdown {
+        return;
+    }
+    if let Some(player) = state.players.get_mut(&id) {
+        // Voxels only break on the server, the client sees it in the next world update
+        step_player(&state.world, &state.blocks, player, player_input, input.dash_pressed, delta);
+    }
+}
+
+// Move every player to a distinct spawn point of the current world and bring them back to life
+pub fn respawn_players(state: &mut GameState) {
+    let mut ids: Vec<i32> = state.players.keys().copied().collect();
+    ids.sort();
+    let mut placed: HashMap<i32, PlayerState> = HashMap::new();
+    for id in ids {
+        if let Some(mut player) = state.players.remove(&id) {
+            revive_player(&mut player, choose_spawn_position(&state.world, &state.blocks, &placed));
+            placed.insert(id, player);
+        }
+    }
+    state.players = placed;
+}
+
+fn revive_player(player: &mut PlayerState, position: Vector3) {
+    player.position = position;
+    player.previous_position = position;
+    player.velocity = Vector3::zero();
+    player.is_dashing = false;
+    player.dash_cooldown = 0.0;
+    player.color = player.original_color;
+    player.health = PLAYER_MAX_HEALTH;
+    player.is_alive = true;
+    player.hit_cooldown = 0.0;
+    player.is_grounded = false;
+}
+
+fn update_player_inputs(state: &mut GameState, input: &InputFrame) {
     // Get all currently used colors before any mutable borrows
     let used_colors: Vec<Color> = state.players.values()
         .map(|p| p.color)
         .collect();
 
-    // Update inputs for all players
-    for (id, input) in state.player_inputs.iter_mut() {
-        if *id == 0 {
-            // First player uses keyboard
-            let mut movement = Vector2::zero();
-            unsafe {
-                if ffi::IsKeyDown(KeyboardKey::KEY_D as i32) {
-                    movement.x += 1.0;
-                }
-                if ffi::IsKeyDown(KeyboardKey::KEY_A as i32) {
-                    movement.x -= 1.0;
-                }
-                if ffi::IsKeyDown(KeyboardKey::KEY_S as i32) {
-                    movement.y += 1.0;
-                }
-                if ffi::IsKeyDown(KeyboardKey::KEY_W as i32) {
-                    movement.y -= 1.0;
-                }
-            }
-            input.movement = movement;
-
-            // Check for space key to spawn keyboard player
-            if !state.players.contains_key(id) && state.players.len() < 5 && unsafe { ffi::IsKeyPressed(KeyboardKey::KEY_SPACE as i32) } {
-                if let Some(color) = generate_random_color(&used_colors, &PLAYER_COLORS) {
-                    let mut player = PlayerState::default();
-                    player.position.x = state.world.width as f32 / 2.0; // Center X
-                    player.position.y = 1.0; // Set player 1 unit above the floor
-                    player.position.z = state.world.depth as f32 / 2.0; // Center Z
-                    player.color = color;
-                    player.original_color = color;
-                    player.is_ready = true;
-                    state.players.insert(*id, player);
+    // Players join and toggle ready only in the lobby
+    let in_lobby = state.match_state.phase == MatchPhase::Lobby;
+
+    // Update inputs for all players, sorted so joins pick colors and spawn points in the same order every run
+    let mut ids: Vec<i32> = state.player_inputs.keys().copied().collect();
+    ids.sort();
+    for id in &ids {
+        let Some(player_input) = state.player_inputs.get_mut(id) else {
+            continue;
+        };
+        // Skip devices that are not connected this frame
+        let Some(frame) = input.players.get(id) else {
+            continue;
+        };
+
+        if *id == 0 || *id == SECOND_KEYBOARD_ID {
+            // First player uses keyboard, the second keyboard player shares it
+            player_input.movement = frame.movement;
+
+            // Join key spawns the keyboard player, pressing it again toggles ready
+            if in_lobby && frame.join_pressed {
+                if let Some(player) = state.players.get_mut(id) {
+                    player.is_ready = !player.is_ready;
+                } else if state.players.len() < 5 {
+                    if let Some(color) = generate_random_color(&mut state.rng.rng, &used_colors, &PLAYER_COLORS) {
+                        let player = create_player(&state.world, &state.blocks, &state.players, color);
+                        state.events.push(GameEvent::PlayerJoined { player_id: *id, position: player.position });
+                        state.players.insert(*id, player);
+                    }
                 }
             }
         } else {
-            // Other players use gamepads
-            let gamepad_id = (*id - 1) as i32; // Convert player ID to gamepad ID
-            unsafe {
-                // Check if gamepad is available and connected
-                let is_available = ffi::IsGamepadAvailable(gamepad_id);
-                if is_available {
-                    // Try different axis indices for Xbox controller
-                    let axis_x = ffi::GetGamepadAxisMovement(gamepad_id, 0); // Left stick X
-                    let axis_y = ffi::GetGamepadAxisMovement(gamepad_id, 1); // Left stick Y
-                    
-                    // Simple deadzone check
-                    let deadzone = 0.1;
-                    let mut movement = Vector2::zero();
-                    
-                    if axis_x.abs() > deadzone {
-                        movement.x = axis_x;
-                    }
-                    if axis_y.abs() > deadzone {
-                        movement.y = axis_y;
-                    }
-                    
-                    // Normalize the vector if it's not zero
-                    if movement.x != 0.0 || movement.y != 0.0 {
-                        let length = (movement.x * movement.x + movement.y * movement.y).sqrt();
-                        movement.x /= length;
-                        movement.y /= length;
+            // Other players use gamepads, the deadzone is already applied by the input layer
+            let movement = frame.movement;
+
+            // Set same movement speed as keyboard players
+            player_input.movement_speed = 5.0;
+            player_input.movement = movement;
+
+            // Any gamepad input spawns the gamepad player, the join button toggles ready afterwards
+            if in_lobby {
+                if let Some(player) = state.players.get_mut(id) {
+                    if frame.join_pressed {
+                        player.is_ready = !player.is_ready;
                     }
-                    
-                    // Set same movement speed as keyboard players
-                    input.movement_speed = 5.0;
-                    input.movement = movement;
-
-                    // Check for any gamepad input to spawn gamepad player
-                    if !state.players.contains_key(id) && state.players.len() < 5 && (movement.x != 0.0 || movement.y != 0.0) {
-                        if let Some(color) = generate_random_color(&used_colors, &PLAYER_COLORS) {
-                            let mut player = PlayerState::default();
-                            player.position.x = state.world.width as f32 / 2.0; // Center X
-                            player.position.y = 1.0; // Set player 1 unit above the floor
-                            player.position.z = state.world.depth as f32 / 2.0; // Center Z
-                            player.color = color;
-                            player.original_color = color;
-                            player.is_ready = true;
-                            state.players.insert(*id, player);
-                        }
+                } else if state.players.len() < 5 && (movement.x != 0.0 || movement.y != 0.0 || frame.join_pressed) {
+                    if let Some(color) = generate_random_color(&mut state.rng.rng, &used_colors, &PLAYER_COLORS) {
+                        let player = create_player(&state.world, &state.blocks, &state.players, color);
+                        state.events.push(GameEvent::PlayerJoined { player_id: *id, position: player.position });
+                        state.players.insert(*id, player);
                     }
                 }
             }
@@ -203,240 +486,561 @@ fn update_player_inputs(state: &mut GameState) {
 
     // Check for new gamepads only if we haven't reached the maximum number of players
     if state.next_player_id < 5 && state.players.len() < 5 { // Allow up to 4 gamepad players (IDs 1-4)
-        for i in 0..4 {
-            unsafe {
-                let is_available = ffi::IsGamepadAvailable(i);
-                if is_available {
-                    let player_id = i + 1; // Gamepad 0 -> Player 1, Gamepad 1 -> Player 2, etc.
-                    if !state.player_inputs.contains_key(&player_id) {
-                        println!("New gamepad detected: {} -> Player {}", i, player_id);
-                        
-                        // Create player input with same speed as keyboard players
-                        state.player_inputs.insert(player_id, PlayerInput {
-                            movement: Vector2::zero(),
-                            movement_speed: 5.0, // Same speed as keyboard players
-                        });
-                    }
+        for player_id in 1..=4 {
+            if input.players.contains_key(&player_id) {
+                if let std::collections::hash_map::Entry::Vacant(entry) = state.player_inputs.entry(player_id) {
+                    println!("New gamepad detected: {} -> Player {}", player_id - 1, player_id);
+                    
+                    // Create player input with same speed as keyboard players
+                    entry.insert(PlayerInput {
+                        movement: Vector2::zero(),
+                        movement_speed: 5.0, // Same speed as keyboard players
+                    });
                 }
             }
         }
     }
 }
 
-fn check_voxel_collision(world: &World, position: Vector3, size: Vector3) -> Option<Voxel> {
+// First solid voxel overlapping the box centered at `position`
+fn check_voxel_collision(world: &World, blocks: &BlockRegistry, position: Vector3, size: Vector3) -> Option<Voxel> {
     let voxel_size = world.voxel_size;
-    
-    // Calculate the player's bounding box in voxel coordinates
-    let min_x = (position.x / voxel_size).floor() as i32;
-    let max_x = (position.x / voxel_size).ceil() as i32;
-    let min_y = (position.y / voxel_size).floor() as i32;
-    let max_y = (position.y / voxel_size).ceil() as i32;
-    let min_z = (position.z / voxel_size).floor() as i32;
-    let max_z = (position.z / voxel_size).ceil() as i32;
-
-    // Check for ground beneath the player
-    let player_bottom = position.y - size.y/2.0;
-    let player_bottom_voxel_y = (player_bottom / voxel_size).floor() as i32;
-    
-    // Check all voxels under the player's feet
-    let mut has_ground = true;
-    for x in min_x..=max_x {
-        for z in min_z..=max_z {
-            let voxel_type = get_voxel(world, x, player_bottom_voxel_y, z);
-            if voxel_type != VoxelType::Ground {
-                has_ground = false;
-                break;
-            }
-        }
-        if !has_ground {
-            break;
-        }
-    }
-    
-    if !has_ground {
-        // Return the first non-ground voxel found
-        for x in min_x..=max_x {
-            for z in min_z..=max_z {
-                let voxel_type = get_voxel(world, x, player_bottom_voxel_y, z);
-                if voxel_type != VoxelType::Ground {
+    // Voxel (x, y, z) spans [x - 0.5, x + 0.5) voxels, shrink the box slightly so touching faces don't collide
+    let epsilon = 0.001;
+    let min_cell = |center: f32, half: f32| ((center - half) / voxel_size + 0.5 + epsilon).floor() as i32;
+    let max_cell = |center: f32, half: f32| ((center + half) / voxel_size + 0.5 - epsilon).floor() as i32;
+
+    for y in min_cell(position.y, size.y / 2.0)..=max_cell(position.y, size.y / 2.0) {
+        for z in min_cell(position.z, size.z / 2.0)..=max_cell(position.z, size.z / 2.0) {
+            for x in min_cell(position.x, size.x / 2.0)..=max_cell(position.x, size.x / 2.0) {
+                let voxel_type = get_voxel(world, x, y, z);
+                if block_properties(blocks, voxel_type).solid {
                     return Some(Voxel {
-                        position: Vector3::new(x as f32, player_bottom_voxel_y as f32, z as f32),
+                        position: Vector3::new(x as f32, y as f32, z as f32),
                         voxel_type
                     });
                 }
             }
         }
     }
+    None
+}
 
-    // Check all voxels in the player's bounding box for wall collisions
-    for x in min_x..=max_x {
-        for y in min_y..=max_y {
-            for z in min_z..=max_z {
-                let voxel_type = get_voxel(world, x, y, z);
-                // Only check for Wall collisions, ignore Ground
-                if voxel_type == VoxelType::Wall {
-                    // Calculate the actual voxel position
-                    let voxel_pos = Vector3::new(
-                        x as f32 * voxel_size,
-                        y as f32 * voxel_size,
-                        z as f32 * voxel_size
-                    );
-                    
-                    // Check if the player is actually inside the voxel
-                    let dx = (position.x - voxel_pos.x).abs();
-                    let dy = (position.y - voxel_pos.y).abs();
-                    let dz = (position.z - voxel_pos.z).abs();
-                    
-                    // Collision check with player's size for each axis
-                    if dx < size.x/2.0 + voxel_size/2.0 &&
-                       dy < size.y/2.0 + voxel_size/2.0 &&
-                       dz < size.z/2.0 + voxel_size/2.0 {
-                        return Some(Voxel {
-                            position: Vector3::new(x as f32, y as f32, z as f32),
-                            voxel_type
-                        });
-                    }
+fn update_player_position(state: &mut GameState, frame: &InputFrame, delta: f32) {
+    // Players wait at their spawns until the countdown ends
+    if state.match_state.phase == MatchPhase::Countdown {
+        return;
+    }
+
+    // Players move in ID order so events and voxel hits don't depend on hash map order
+    let mut ids: Vec<i32> = state.player_inputs.keys().copied().collect();
+    ids.sort();
+    let mut voxel_hits = Vec::new();
+    for id in ids {
+        if let (Some(input), Some(player)) = (state.player_inputs.get(&id), state.players.get_mut(&id)) {
+            // Check for dash input of the player's device
+            let is_dash_pressed = frame.players.get(&id).is_some_and(|device| device.dash_pressed);
+            let step = step_player(&state.world, &state.blocks, player, input, is_dash_pressed, delta);
+            if step.dash_started {
+                state.events.push(GameEvent::DashStarted { player_id: id, position: player.position });
+            }
+            if let Some((voxel, impact_speed)) = step.wall_hit {
+                if impact_speed >= WALL_BUMP_MIN_SPEED {
+                    state.events.push(GameEvent::WallBumped { player_id: id, position: player.position, speed: impact_speed });
+                }
+                if step.dash_hit {
+                    voxel_hits.push((id, voxel, impact_speed));
                 }
             }
         }
     }
-    None
+
+    // Terrain only breaks during a round
+    if state.match_state.phase == MatchPhase::Playing {
+        for (id, voxel, impact_speed) in voxel_hits {
+            damage_voxel(state, id, voxel, impact_speed);
+        }
+    }
 }
 
-fn update_player_position(state: &mut GameState, delta: f32) {
-    for (id, input) in state.player_inputs.iter() {
-        if let Some(player) = state.players.get_mut(id) {
-            // Update dash cooldown
-            if player.dash_cooldown > 0.0 {
-                player.dash_cooldown -= delta;
-            }
+// Wear down a destructible voxel hit by a dash, it breaks once its hit points are used up
+fn damage_voxel(state: &mut GameState, player_id: i32, voxel: Voxel, impact_speed: f32) {
+    let block = block_properties(&state.blocks, voxel.voxel_type);
+    if !block.destructible {
+        return;
+    }
+    let hit_points = block.hit_points;
 
-            // Check for dash input based on player type
-            let is_dash_pressed = if *id == 0 {
-                // Keyboard player (ID 0) uses Shift key
-                unsafe { ffi::IsKeyPressed(KeyboardKey::KEY_LEFT_SHIFT as i32) }
-            } else {
-                // Gamepad players use right trigger
-                let gamepad_id = (*id - 1) as i32;
-                unsafe { ffi::IsGamepadButtonPressed(gamepad_id, GamepadButton::GAMEPAD_BUTTON_RIGHT_TRIGGER_1 as i32) }
+    let position = (voxel.position.x as i32, voxel.position.y as i32, voxel.position.z as i32);
+    let damage = state.voxel_damage.entry(position).or_insert(0.0);
+    *damage += impact_speed * BLOCK_DAMAGE_PER_SPEED;
+    if *damage < hit_points {
+        return;
+    }
+
+    state.voxel_damage.remove(&position);
+    let (x, y, z) = position;
+    set_voxel(&mut state.world, x, y, z, VoxelType::EMPTY);
+    state.events.push(GameEvent::VoxelBroken { position, voxel_type: voxel.voxel_type, player_id });
+}
+
+// Players standing on damaging blocks lose health during a round
+fn apply_block_damage(state: &mut GameState, delta: f32) {
+    if state.match_state.phase != MatchPhase::Playing {
+        return;
+    }
+    for player in state.players.values_mut() {
+        if player.is_alive && player.is_grounded {
+            player.health -= block_properties(&state.blocks, player.ground_block).damage * delta;
+        }
+    }
+}
+
+// What happened to a player during one tick
+#[derive(Debug, Default)]
+struct StepOutcome {
+    dash_started: bool,
+    wall_hit: Option<(Voxel, f32)>, // Voxel the player ran into and the horizontal speed of the impact
+    dash_hit: bool, // The player was dashing when it hit the voxel
+}
+
+// Advance a single player by one tick
+fn step_player(
+    world: &World, blocks: &BlockRegistry, player: &mut PlayerState, input: &PlayerInput, is_dash_pressed: bool, delta: f32,
+) -> StepOutcome {
+    let mut outcome = StepOutcome::default();
+
+    // Eliminated players stay where they fell
+    if !player.is_alive {
+        return outcome;
+    }
+
+    if player.hit_cooldown > 0.0 {
+        player.hit_cooldown -= delta;
+    }
+
+    // Steering, dashing and friction only act on the horizontal velocity, gravity is applied below
+    let vertical_velocity = player.velocity.y;
+    player.velocity.y = 0.0;
+
+    // Update dash cooldown
+    if player.dash_cooldown > 0.0 {
+        player.dash_cooldown -= delta;
+    }
+
+    // Start dash if dash button is pressed, not already dashing, and cooldown is ready
+    if is_dash_pressed && !player.is_dashing && player.dash_cooldown <= 0.0 && input.movement.length() > 0.0 {
+        outcome.dash_started = true;
+        player.is_dashing = true;
+        player.dash_cooldown = 0.8; // Cooldown time
+        // Normalize the movement vector to ensure consistent dash speed in all directions
+        let normalized_movement = input.movement.normalized();
+        player.dash_direction = Vector3::new(normalized_movement.x, 0.0, normalized_movement.y);
+        // Store current velocity before dash
+        player.pre_dash_velocity = player.velocity;
+        // Store original color
+        player.original_color = player.color;
+        // Set initial dash velocity
+        player.velocity = player.dash_direction * 15.0;
+    }
+
+    // Update color during dash
+    if player.is_dashing {
+        let progress = (0.8 - player.dash_cooldown) / 0.8; // Progress from 0 to 1
+        if progress < 0.1 {
+            // Smooth transition to white at the start
+            let t = progress * 10.0; // Scale to 0-1 range
+            player.color = Color {
+                r: ((player.original_color.r as f32 * (1.0 - t) + 255.0 * t).min(255.0)) as u8,
+                g: ((player.original_color.g as f32 * (1.0 - t) + 255.0 * t).min(255.0)) as u8,
+                b: ((player.original_color.b as f32 * (1.0 - t) + 255.0 * t).min(255.0)) as u8,
+                a: player.original_color.a,
+            };
+        } else {
+            // Smooth transition back to original color
+            let t = (progress - 0.1) / 0.9; // Scale to 0-1 range
+            player.color = Color {
+                r: ((255.0 * (1.0 - t) + player.original_color.r as f32 * t).min(255.0)) as u8,
+                g: ((255.0 * (1.0 - t) + player.original_color.g as f32 * t).min(255.0)) as u8,
+                b: ((255.0 * (1.0 - t) + player.original_color.b as f32 * t).min(255.0)) as u8,
+                a: player.original_color.a,
             };
+        }
+    } else if player.dash_cooldown > 0.0 {
+        // Continue smooth transition back to original color during cooldown
+        let progress = (0.8 - player.dash_cooldown) / 0.8; // Progress from 0 to 1
+        let t = progress; // Scale to 0-1 range
+        player.color = Color {
+            r: ((255.0 * (1.0 - t) + player.original_color.r as f32 * t).min(255.0)) as u8,
+            g: ((255.0 * (1.0 - t) + player.original_color.g as f32 * t).min(255.0)) as u8,
+            b: ((255.0 * (1.0 - t) + player.original_color.b as f32 * t).min(255.0)) as u8,
+            a: player.original_color.a,
+        };
+    } else {
+        // Reset to original color when cooldown is complete
+        player.color = player.original_color;
+    }
+
+    // Calculate target velocity based on input
+    let target_velocity = if player.is_dashing {
+        // During dash, maintain dash direction and speed
+        player.dash_direction * 20.0 // Increased from 20.0 to 30.0 for faster dash
+    } else {
+        // Normal movement
+        Vector3::new(
+            input.movement.x * input.movement_speed * 1.5,
+            0.0,
+            input.movement.y * input.movement_speed * 1.5
+        )
+    };
+
+    // Slippery blocks reduce how quickly players speed up and slow down
+    let grip = if player.is_grounded { block_properties(blocks, player.ground_block).friction } else { 1.0 };
+
+    // Same rate for speeding up and slowing down
+    let acceleration_rate = 3.0 * grip;
+
+    // Update velocity with acceleration (only if not dashing)
+    if !player.is_dashing {
+        let acceleration = (target_velocity - player.velocity) * acceleration_rate;
+        player.velocity += acceleration * delta;
+    }
+    
+    // Apply friction when no input is given and not dashing
+    if target_velocity.length() == 0.0 && !player.is_dashing {
+        let friction = 5.0 * grip; // Lower friction for smoother stopping
+        player.velocity = player.velocity.lerp(Vector3::zero(), friction * delta);
+    }
+
+    // End dash after 0.2 seconds
+    if player.is_dashing {
+        player.dash_cooldown -= delta;
+        if player.dash_cooldown <= 0.6 { // Dash duration is 0.2 seconds
+            player.is_dashing = false;
+            // Calculate interpolation factor based on remaining cooldown
+            // This will give us a smooth transition from dash speed to pre-dash speed
+            let transition_time = 0.03; // Reduced from 0.05 to 0.03 seconds for faster transition
+            let transition_progress = (0.6 - player.dash_cooldown) / transition_time;
+            let t = transition_progress.min(1.0); // Clamp to 1.0
             
-            // Start dash if dash button is pressed, not already dashing, and cooldown is ready
-            if is_dash_pressed && !player.is_dashing && player.dash_cooldown <= 0.0 && input.movement.length() > 0.0 {
-                player.is_dashing = true;
-                player.dash_cooldown = 0.8; // Cooldown time
-                // Normalize the movement vector to ensure consistent dash speed in all directions
-                let normalized_movement = input.movement.normalized();
-                player.dash_direction = Vector3::new(normalized_movement.x, 0.0, normalized_movement.y);
-                // Store current velocity before dash
-                player.pre_dash_velocity = player.velocity;
-                // Store original color
-                player.original_color = player.color;
-                // Set initial dash velocity
-                player.velocity = player.dash_direction * 15.0;
-            }
-
-            // Update color during dash
-            if player.is_dashing {
-                let progress = (0.8 - player.dash_cooldown) / 0.8; // Progress from 0 to 1
-                if progress < 0.1 {
-                    // Smooth transition to white at the start
-                    let t = progress * 10.0; // Scale to 0-1 range
-                    player.color = Color {
-                        r: ((player.original_color.r as f32 * (1.0 - t) + 255.0 * t).min(255.0)) as u8,
-                        g: ((player.original_color.g as f32 * (1.0 - t) + 255.0 * t).min(255.0)) as u8,
-                        b: ((player.original_color.b as f32 * (1.0 - t) + 255.0 * t).min(255.0)) as u8,
-                        a: player.original_color.a,
-                    };
-                } else {
-                    // Smooth transition back to original color
-                    let t = (progress - 0.1) / 0.9; // Scale to 0-1 range
-                    player.color = Color {
-                        r: ((255.0 * (1.0 - t) + player.original_color.r as f32 * t).min(255.0)) as u8,
-                        g: ((255.0 * (1.0 - t) + player.original_color.g as f32 * t).min(255.0)) as u8,
-                        b: ((255.0 * (1.0 - t) + player.original_color.b as f32 * t).min(255.0)) as u8,
-                        a: player.original_color.a,
-                    };
-                }
-            } else if player.dash_cooldown > 0.0 {
-                // Continue smooth transition back to original color during cooldown
-                let progress = (0.8 - player.dash_cooldown) / 0.8; // Progress from 0 to 1
-                let t = progress; // Scale to 0-1 range
-                player.color = Color {
-                    r: ((255.0 * (1.0 - t) + player.original_color.r as f32 * t).min(255.0)) as u8,
-                    g: ((255.0 * (1.0 - t) + player.original_color.g as f32 * t).min(255.0)) as u8,
-                    b: ((255.0 * (1.0 - t) + player.original_color.b as f32 * t).min(255.0)) as u8,
-                    a: player.original_color.a,
-                };
-            } else {
-                // Reset to original color when cooldown is complete
-                player.color = player.original_color;
+            // Smoothly interpolate between dash velocity and pre-dash velocity
+            player.velocity = player.velocity.lerp(player.pre_dash_velocity, t * 5.0); // Increased from 3.0 to 5.0 for faster transition
+        }
+    }
+
+    player.velocity.y = (vertical_velocity - GRAVITY * delta).max(-MAX_FALL_SPEED);
+
+    // Calculate movement based on velocity
+    let movement = player.velocity * delta;
+    let voxel_size = world.voxel_size;
+
+    // Horizontal movement, stepping up onto walkable blocks one voxel higher
+    let horizontal = Vector3::new(movement.x, 0.0, movement.z);
+    if horizontal.length() > 0.0 {
+        let new_position = player.position + horizontal;
+        let stepped_position = new_position + Vector3::new(0.0, voxel_size, 0.0);
+        match check_voxel_collision(world, blocks, new_position, player.size) {
+            None => player.position = new_position,
+            Some(voxel) if block_properties(blocks, voxel.voxel_type).walkable && player.is_grounded &&
+                check_voxel_collision(world, blocks, stepped_position, player.size).is_none() => {
+                player.position = stepped_position;
+            }
+            Some(voxel) => {
+                outcome.wall_hit = Some((voxel, Vector3::new(player.velocity.x, 0.0, player.velocity.z).length()));
+                outcome.dash_hit = player.is_dashing;
+                // Stop movement in the direction of collision
+                player.velocity.x = 0.0;
+                player.velocity.z = 0.0;
+                // Also stop dash if we hit something
+                player.is_dashing = false;
             }
+        }
+    }
 
-            // Calculate target velocity based on input
-            let target_velocity = if player.is_dashing {
-                // During dash, maintain dash direction and speed
-                player.dash_direction * 20.0 // Increased from 20.0 to 30.0 for faster dash
-            } else {
-                // Normal movement
-                Vector3::new(
-                    input.movement.x * input.movement_speed * 1.5,
-                    0.0,
-                    input.movement.y * input.movement_speed * 1.5
-                )
-            };
+    // Vertical movement, landing on top of the voxel under the player's feet
+    let new_position = player.position + Vector3::new(0.0, movement.y, 0.0);
+    match check_voxel_collision(world, blocks, new_position, player.size) {
+        None => {
+            player.position = new_position;
+            player.is_grounded = false;
+        }
+        Some(voxel) => {
+            let mut bounce_speed = 0.0;
+            if player.velocity.y < 0.0 {
+                let feet_row = ((new_position.y - player.size.y / 2.0) / voxel_size + 0.5).floor();
+                player.position.y = (feet_row + 0.5) * voxel_size + player.size.y / 2.0;
+                player.ground_block = voxel.voxel_type;
+                bounce_speed = (-player.velocity.y * block_properties(blocks, voxel.voxel_type).bounce).min(MAX_FALL_SPEED);
+                player.is_grounded = bounce_speed < BOUNCE_MIN_SPEED;
+            }
+            player.velocity.y = if bounce_speed < BOUNCE_MIN_SPEED { 0.0 } else { bounce_speed };
+        }
+    }
+    outcome
+}
 
-            // Calculate acceleration based on whether we're speeding up or slowing down
-            let acceleration_rate = if target_velocity.length() > 0.0 {
-                3.0 // Increased from 1.5 to 3.0 for faster acceleration
-            } else {
-                3.0 // Increased from 3.0 to 3.0 for faster deceleration
-            };
+fn players_overlap(first: &PlayerState, second: &PlayerState) -> bool {
+    let delta = first.position - second.position;
+    delta.x.abs() < (first.size.x + second.size.x) / 2.0 &&
+    delta.y.abs() < (first.size.y + second.size.y) / 2.0 &&
+    delta.z.abs() < (first.size.z + second.size.z) / 2.0
+}
 
-            // Update velocity with acceleration (only if not dashing)
-            if !player.is_dashing {
-                let acceleration = (target_velocity - player.velocity) * acceleration_rate;
-                player.velocity += acceleration * delta;
+// Push overlapping players apart and apply dash hits between them, hits only hurt during a round
+fn resolve_player_collisions(state: &mut GameState) {
+    let hits_enabled = state.match_state.phase == MatchPhase::Playing;
+
+    // Sorted so the result doesn't depend on hash map order
+    let mut ids: Vec<i32> = state.players.iter()
+        .filter(|(_, player)| player.is_alive)
+        .map(|(id, _)| *id)
+        .collect();
+    ids.sort();
+
+    for (index, first_id) in ids.iter().enumerate() {
+        for second_id in &ids[index + 1..] {
+            let mut first = state.players[first_id];
+            let mut second = state.players[second_id];
+            if !players_overlap(&first, &second) {
+                continue;
             }
-            
-            // Apply friction when no input is given and not dashing
-            if target_velocity.length() == 0.0 && !player.is_dashing {
-                let friction = 5.0; // Lower friction for smoother stopping
-                player.velocity = player.velocity.lerp(Vector3::zero(), friction * delta);
-            }
-
-            // End dash after 0.2 seconds
-            if player.is_dashing {
-                player.dash_cooldown -= delta;
-                if player.dash_cooldown <= 0.6 { // Dash duration is 0.2 seconds
-                    player.is_dashing = false;
-                    // Calculate interpolation factor based on remaining cooldown
-                    // This will give us a smooth transition from dash speed to pre-dash speed
-                    let transition_time = 0.03; // Reduced from 0.05 to 0.03 seconds for faster transition
-                    let transition_progress = (0.6 - player.dash_cooldown) / transition_time;
-                    let t = transition_progress.min(1.0); // Clamp to 1.0
-                    
-                    // Smoothly interpolate between dash velocity and pre-dash velocity
-                    player.velocity = player.velocity.lerp(player.pre_dash_velocity, t * 5.0); // Increased from 3.0 to 5.0 for faster transition
-                }
+
+            // Both hits use the state before the collision so neither player wins by ID order
+            let (first_before, second_before) = (first, second);
+            if first_before.is_dashing {
+                end_dash_on_hit(&mut first);
+            }
+            if second_before.is_dashing {
+                end_dash_on_hit(&mut second);
+            }
+            if hits_enabled && first_before.is_dashing {
+                apply_dash_hit(&first_before, &mut second);
+            }
+            if hits_enabled && second_before.is_dashing {
+                apply_dash_hit(&second_before, &mut first);
+            }
+
+            separate_players(&state.world, &state.blocks, &mut first, &mut second);
+            state.players.insert(*first_id, first);
+            state.players.insert(*second_id, second);
+        }
+    }
+}
+
+// Eliminate players without health and players that fell out of the arena.
+// Outside of a round players that fell out are put back on a spawn point instead.
+fn update_eliminations(state: &mut GameState) {
+    if state.match_state.phase != MatchPhase::Playing {
+        let mut fallen: Vec<i32> = state.players.iter()
+            .filter(|(_, player)| player.position.y < -FALL_ELIMINATION_DEPTH)
+            .map(|(id, _)| *id)
+            .collect();
+        fallen.sort();
+        for id in fallen {
+            if let Some(mut player) = state.players.remove(&id) {
+                revive_player(&mut player, choose_spawn_position(&state.world, &state.blocks, &state.players));
+                state.players.insert(id, player);
+            }
+        }
+        return;
+    }
+
+    let mut ids: Vec<i32> = state.players.keys().copied().collect();
+    ids.sort();
+    for id in ids {
+        let Some(player) = state.players.get_mut(&id) else {
+            continue;
+        };
+        if !player.is_alive {
+            continue;
+        }
+        if player.health <= 0.0 || player.position.y < -FALL_ELIMINATION_DEPTH {
+            player.health = 0.0;
+            player.is_alive = false;
+            player.is_dashing = false;
+            player.velocity = Vector3::zero();
+            player.color = player.original_color;
+            state.events.push(GameEvent::PlayerEliminated { player_id: id, position: player.position });
+        }
+    }
+}
+
+// Advance the lobby -> countdown -> round -> round over -> match over cycle
+fn update_match(state: &mut GameState, delta: f32) {
+    let match_state = &mut state.match_state;
+    match match_state.phase {
+        MatchPhase::Lobby => {
+            let everyone_ready = state.players.values().all(|player| player.is_ready);
+            if state.players.len() >= MIN_PLAYERS_TO_START && everyone_ready {
+                start_round(state);
+            }
+        }
+        MatchPhase::Countdown => {
+            if state.players.len() < MIN_PLAYERS_TO_START {
+                return_to_lobby(state);
+                return;
+            }
+            match_state.phase_timer -= delta;
+            if match_state.phase_timer <= 0.0 {
+                match_state.phase = MatchPhase::Playing;
+                match_state.phase_timer = 0.0;
+                state.round_world = Some(state.world.clone());
+                state.events.push(GameEvent::RoundStarted { round: match_state.round });
+            }
+        }
+        MatchPhase::Playing => {
+            let alive: Vec<i32> = state.players.iter()
+                .filter(|(_, player)| player.is_alive)
+                .map(|(id, _)| *id)
+                .collect();
+            if alive.len() > 1 {
+                return;
             }
 
-            // Calculate movement based on velocity
-            let movement = player.velocity * delta;
+            let winner = alive.first().copied();
+            match_state.round_winner = winner;
+            let wins = match winner {
+                Some(id) => {
+                    let wins = match_state.round_wins.entry(id).or_insert(0);
+                    *wins += 1;
+                    *wins
+                }
+                None => 0,
+            };
 
-            if movement.length() > 0.0 {
-                let new_position = player.position + movement;
-                let collision = check_voxel_collision(&state.world, new_position, player.size);
-                if collision.is_none() {
-                    player.position = new_position;
+            if wins >= match_state.rounds_to_win {
+                match_state.phase = MatchPhase::MatchOver;
+                match_state.phase_timer = MATCH_OVER_DURATION;
+                match_state.match_winner = winner;
+            } else {
+                match_state.phase = MatchPhase::RoundOver;
+                match_state.phase_timer = ROUND_OVER_DURATION;
+            }
+        }
+        MatchPhase::RoundOver => {
+            match_state.phase_timer -= delta;
+            if match_state.phase_timer <= 0.0 {
+                if state.players.len() < MIN_PLAYERS_TO_START {
+                    return_to_lobby(state);
                 } else {
-                    // Stop movement in the direction of collision
-                    player.velocity = Vector3::zero();
-                    // Also stop dash if we hit something
-                    player.is_dashing = false;
+                    start_round(state);
                 }
             }
         }
+        MatchPhase::MatchOver => {
+            match_state.phase_timer -= delta;
+            if match_state.phase_timer <= 0.0 {
+                return_to_lobby(state);
+            }
+        }
+    }
+}
+
+// Put everyone back on the spawn points of the same arena and count down to the next round
+fn start_round(state: &mut GameState) {
+    let match_state = &mut state.match_state;
+    match_state.round += 1;
+    match_state.round_winner = None;
+    match_state.phase = MatchPhase::Countdown;
+    match_state.phase_timer = COUNTDOWN_DURATION;
+    restore_round_world(state);
+    respawn_players(state);
+}
+
+fn return_to_lobby(state: &mut GameState) {
+    let match_state = &mut state.match_state;
+    match_state.phase = MatchPhase::Lobby;
+    match_state.phase_timer = 0.0;
+    match_state.round = 0;
+    match_state.round_wins.clear();
+    match_state.round_winner = None;
+    match_state.match_winner = None;
+    for player in state.players.values_mut() {
+        player.is_ready = false;
+    }
+    restore_round_world(state);
+    respawn_players(state);
+}
+
+// Bring back the voxels broken during the last round
+fn restore_round_world(state: &mut GameState) {
+    if let Some(world) = state.round_world.take() {
+        let next_version = state.world.next_chunk_version;
+        state.world = world;
+        continue_chunk_versions(&mut state.world, next_version);
+    }
+    state.voxel_damage.clear();
+}
+
+// A new world replaced the one of the round, keep it when the round ends
+fn forget_round_world(state: &mut GameState) {
+    state.round_world = None;
+    state.voxel_damage.clear();
+}
+
+// The attacker bounces off and continues with its speed from before the dash
+fn end_dash_on_hit(attacker: &mut PlayerState) {
+    attacker.is_dashing = false;
+    attacker.velocity = attacker.pre_dash_velocity;
+}
+
+// Damage and knock back the target based on how fast the attacker hit it
+fn apply_dash_hit(attacker: &PlayerState, target: &mut PlayerState) {
+    if target.hit_cooldown > 0.0 {
+        return;
+    }
+
+    let mut direction = Vector3::new(target.position.x - attacker.position.x, 0.0, target.position.z - attacker.position.z);
+    if direction.length() < f32::EPSILON {
+        direction = attacker.dash_direction;
+    }
+    let direction = direction.normalized();
+
+    let impact_speed = attacker.velocity.dot(direction).max(0.0);
+    target.health -= impact_speed * DASH_DAMAGE_PER_SPEED;
+    target.velocity += direction * impact_speed * DASH_KNOCKBACK;
+    target.is_dashing = false;
+    target.hit_cooldown = HIT_INVULNERABILITY;
+}
+
+// Move both players out of each other along the axis of least penetration,
+// a player blocked by a wall leaves the whole push to the other one
+fn separate_players(world: &World, blocks: &BlockRegistry, first: &mut PlayerState, second: &mut PlayerState) {
+    let delta = second.position - first.position;
+    let penetration_x = (first.size.x + second.size.x) / 2.0 - delta.x.abs();
+    let penetration_z = (first.size.z + second.size.z) / 2.0 - delta.z.abs();
+    if penetration_x <= 0.0 || penetration_z <= 0.0 {
+        return;
+    }
+
+    let push = if penetration_x < penetration_z {
+        Vector3::new(if delta.x >= 0.0 { penetration_x } else { -penetration_x }, 0.0, 0.0)
+    } else {
+        Vector3::new(0.0, 0.0, if delta.z >= 0.0 { penetration_z } else { -penetration_z })
+    };
+
+    let first_target = first.position - push / 2.0;
+    let second_target = second.position + push / 2.0;
+    let first_free = check_voxel_collision(world, blocks, first_target, first.size).is_none();
+    let second_free = check_voxel_collision(world, blocks, second_target, second.size).is_none();
+    match (first_free, second_free) {
+        (true, true) => {
+            first.position = first_target;
+            second.position = second_target;
+        }
+        (true, false) => {
+            let target = first.position - push;
+            if check_voxel_collision(world, blocks, target, first.size).is_none() {
+                first.position = target;
+            }
+        }
+        (false, true) => {
+            let target = second.position + push;
+            if check_voxel_collision(world, blocks, target, second.size).is_none() {
+                second.position = target;
+            }
+        }
+        (false, false) => {}
     }
 }
 
@@ -463,58 +1067,22 @@ fn is_voxel_occupied_by_player(state: &GameState, x: i32, y: i32, z: i32) -> boo
     false
 }
 
-fn handle_voxel_input(state: &mut GameState) {
-    let mouse_pos = unsafe { ffi::GetMousePosition() };
-    let ray: Ray = unsafe { ffi::GetScreenToWorldRay(mouse_pos, state.camera_state.camera.into()).into() };
+fn handle_voxel_input(state: &mut GameState, input: &EditorInputFrame) {
+    let ray = input.mouse_ray;
     
     let voxel_size = state.world.voxel_size;
-    let mut closest_collision: Option<(i32, i32, i32, f32, Vector3)> = None;
 
     // First check collisions with existing voxels
-    for voxel in &state.world.voxels {
-        if voxel.voxel_type == VoxelType::Empty {
-            continue;
-        }
-
-        let x = voxel.position.x as i32;
-        let y = voxel.position.y as i32;
-        let z = voxel.position.z as i32;
-
-        // Create bounding box for the voxel
-        let min = Vector3::new(
-            x as f32 * voxel_size,
-            y as f32 * voxel_size,
-            z as f32 * voxel_size
-        );
-        let max = Vector3::new(
-            (x + 1) as f32 * voxel_size,
-            (y + 1) as f32 * voxel_size,
-            (z + 1) as f32 * voxel_size
-        );
-        let bbox = BoundingBox::new(min, max);
-
-        // Check ray collision with the voxel's bounding box
-        let collision: RayCollision = unsafe { ffi::GetRayCollisionBox(ray.into(), bbox.into()).into() };
-        if collision.hit {
-            // Update closest collision if this one is closer
-            if let Some((_, _, _, current_dist, _)) = closest_collision {
-                if collision.distance < current_dist {
-                    closest_collision = Some((x, y, z, collision.distance, collision.normal));
-                }
-            } else {
-                closest_collision = Some((x, y, z, collision.distance, collision.normal));
-            }
-        }
-    }
+    let mut closest_collision = raycast_voxels(&state.world, ray);
 
     // If no collision with existing voxels, check for ground layer
     if closest_collision.is_none() {
-        // Calculate where the ray would hit the ground plane (y=0)
+        // Calculate where the ray would hit the ground plane (y=0), voxels are centered on their coordinates
         let t = -ray.position.y / ray.direction.y;
         if t > 0.0 {
             let hit_point = ray.position + ray.direction * t;
-            let x = (hit_point.x / voxel_size).floor() as i32;
-            let z = (hit_point.z / voxel_size).floor() as i32;
+            let x = (hit_point.x / voxel_size).round() as i32;
+            let z = (hit_point.z / voxel_size).round() as i32;
             
             if x >= 0 && x < state.world.width && z >= 0 && z < state.world.depth {
                 closest_collision = Some((x, 0, z, t, Vector3::new(0.0, 1.0, 0.0)));
@@ -523,7 +1091,7 @@ fn handle_voxel_input(state: &mut GameState) {
     }
 
     // Toggle build mode with right click
-    if unsafe { ffi::IsMouseButtonPressed(MouseButton::MOUSE_BUTTON_RIGHT as i32) } {
+    if input.toggle_build_mode {
         state.editor.build_mode = !state.editor.build_mode;
     }
 
@@ -531,7 +1099,7 @@ fn handle_voxel_input(state: &mut GameState) {
     if let Some((x, y, z, _, normal)) = closest_collision {
         if state.editor.build_mode {
             // In build mode, show where the new voxel will be placed
-            let (new_x, new_y, new_z) = if get_voxel(&state.world, x, y, z) == VoxelType::Empty {
+            let (new_x, new_y, new_z) = if get_voxel(&state.world, x, y, z) == VoxelType::EMPTY {
                 (x, y, z)
             } else {
                 // If clicking on an existing voxel, show where the new one will be placed
@@ -556,31 +1124,38 @@ fn handle_voxel_input(state: &mut GameState) {
         return;
     }
 
+    if let Some(tool) = input.select_tool {
+        state.editor.tool = tool;
+        state.editor.drag_start = None;
+    }
+
+    // Q / E cycle through the palette, skipping empty space
+    if input.palette_step != 0 {
+        let count = state.blocks.blocks.len() as i32 - 1;
+        let index = (state.editor.selected_block.0 as i32 - 1 + input.palette_step).rem_euclid(count);
+        state.editor.selected_block = VoxelType(index as u8 + 1);
+    }
+    if arena_locked(state, input.place_pressed) {
+        state.editor.drag_start = None;
+        return;
+    }
+    if state.editor.tool != EditorTool::Single {
+        handle_brush_input(state, input);
+        return;
+    }
+
     if let Some((x, y, z, _, normal)) = closest_collision {
         // Check if there's already a voxel at this position
         let existing_voxel = get_voxel(&state.world, x, y, z);
         
-        if unsafe { ffi::IsMouseButtonPressed(MouseButton::MOUSE_BUTTON_LEFT as i32) } {
+        if input.place_pressed {
             if state.editor.build_mode {
                 // Build mode - place new voxels
-                if existing_voxel == VoxelType::Empty {
+                if existing_voxel == VoxelType::EMPTY {
                     // Check if the new voxel position overlaps with any player
                     if !is_voxel_occupied_by_player(state, x, y, z) {
-                        // Get player height (assuming first player)
-                        let player_height = if let Some(player) = state.players.get(&0) {
-                            player.position.y
-                        } else {
-                            1.0 // Default height if no player
-                        };
-                        
-                        // Check if this was a floor voxel (y = 0) or if it's below player height
-                        let voxel_type = if y == 0 || (y as f32) < player_height {
-                            VoxelType::Ground
-                        } else {
-                            VoxelType::Wall
-                        };
-                        
-                        set_voxel(&mut state.world, x, y, z, voxel_type);
+                        let voxel_type = state.editor.selected_block;
+                        edit_voxel(state, x, y, z, voxel_type);
                     }
                 } else {
                     // If clicking on an existing voxel, try to place a new one based on the clicked face
@@ -594,45 +1169,281 @@ fn handle_voxel_input(state: &mut GameState) {
                     if new_x >= 0 && new_x < state.world.width &&
                        new_y >= 0 && new_y < state.world.height &&
                        new_z >= 0 && new_z < state.world.depth &&
-                       get_voxel(&state.world, new_x, new_y, new_z) == VoxelType::Empty &&
+                       get_voxel(&state.world, new_x, new_y, new_z) == VoxelType::EMPTY &&
                        !is_voxel_occupied_by_player(state, new_x, new_y, new_z) {
-                        // Get player height (assuming first player)
-                        let player_height = if let Some(player) = state.players.get(&0) {
-                            player.position.y
-                        } else {
-                            1.0 // Default height if no player
-                        };
-                        
-                        // Set voxel type based on height relative to player
-                        let voxel_type = if new_y == 0 || (new_y as f32) < player_height {
-                            VoxelType::Ground
-                        } else {
-                            VoxelType::Wall
-                        };
-                        
-                        set_voxel(&mut state.world, new_x, new_y, new_z, voxel_type);
+                        let voxel_type = state.editor.selected_block;
+                        edit_voxel(state, new_x, new_y, new_z, voxel_type);
                     }
                 }
             } else {
                 // Remove mode - remove existing voxels
-                if existing_voxel != VoxelType::Empty && !is_voxel_occupied_by_player(state, x, y, z) {
-                    set_voxel(&mut state.world, x, y, z, VoxelType::Empty);
+                if existing_voxel != VoxelType::EMPTY && !is_voxel_occupied_by_player(state, x, y, z) {
+                    edit_voxel(state, x, y, z, VoxelType::EMPTY);
                 }
             }
         }
         
         // Check for continuous removal with Ctrl+left click
-        let is_ctrl_pressed = unsafe { ffi::IsKeyDown(KeyboardKey::KEY_LEFT_CONTROL as i32) };
-        let is_left_pressed = unsafe { ffi::IsMouseButtonDown(MouseButton::MOUSE_BUTTON_LEFT as i32) };
-        if !state.editor.build_mode && is_ctrl_pressed && is_left_pressed {
-            if existing_voxel != VoxelType::Empty && !is_voxel_occupied_by_player(state, x, y, z) {
-                set_voxel(&mut state.world, x, y, z, VoxelType::Empty);
+        let is_ctrl_pressed = input.continuous_remove;
+        let is_left_pressed = input.place_down;
+        if !state.editor.build_mode && is_ctrl_pressed && is_left_pressed &&
+           existing_voxel != VoxelType::EMPTY && !is_voxel_occupied_by_player(state, x, y, z) {
+            edit_voxel(state, x, y, z, VoxelType::EMPTY);
+        }
+    }
+}
+
+// Shape tools apply when the drag is released, flood fill applies on click
+fn handle_brush_input(state: &mut GameState, input: &EditorInputFrame) {
+    let hovered = state.editor.hovered_voxel;
+    if state.editor.tool == EditorTool::FloodFill {
+        if input.place_pressed {
+            if let Some(seed) = hovered {
+                let cells = flood_fill_cells(&state.world, seed, state.editor.build_mode);
+                apply_brush(state, &cells);
+            }
+        }
+        return;
+    }
+
+    if input.place_pressed && state.editor.drag_start.is_none() {
+        state.editor.drag_start = hovered;
+    }
+    if !input.place_down {
+        if let Some(start) = state.editor.drag_start.take() {
+            let cells = shape_cells(state.editor.tool, start, hovered.unwrap_or(start));
+            apply_brush(state, &cells);
+        }
+    }
+}
+
+// Fill the empty cells in build mode or erase the solid ones in remove mode, leaving cells with players alone
+fn apply_brush(state: &mut GameState, cells: &[(i32, i32, i32)]) {
+    for &(x, y, z) in cells {
+        if !is_valid_position(&state.world, x, y, z) || is_voxel_occupied_by_player(state, x, y, z) {
+            continue;
+        }
+        let existing_voxel = get_voxel(&state.world, x, y, z);
+        if state.editor.build_mode && existing_voxel == VoxelType::EMPTY {
+            let voxel_type = state.editor.selected_block;
+            edit_voxel(state, x, y, z, voxel_type);
+        } else if !state.editor.build_mode && existing_voxel != VoxelType::EMPTY {
+            edit_voxel(state, x, y, z, VoxelType::EMPTY);
+        }
+    }
+}
+
+// Cells the current tool would change, used for the editor preview
+pub fn brush_preview(state: &GameState) -> Vec<(i32, i32, i32)> {
+    let Some(hovered) = state.editor.hovered_voxel else {
+        return Vec::new();
+    };
+    match state.editor.tool {
+        EditorTool::Single => vec![hovered],
+        EditorTool::FloodFill => flood_fill_cells(&state.world, hovered, state.editor.build_mode),
+        tool => shape_cells(tool, state.editor.drag_start.unwrap_or(hovered), hovered),
+    }
+}
+
+// Cells covered by a box, hollow box or line dragged between two cells
+pub fn shape_cells(tool: EditorTool, start: (i32, i32, i32), end: (i32, i32, i32)) -> Vec<(i32, i32, i32)> {
+    let (min_x, max_x) = (start.0.min(end.0), start.0.max(end.0));
+    let (min_y, max_y) = (start.1.min(end.1), start.1.max(end.1));
+    let (min_z, max_z) = (start.2.min(end.2), start.2.max(end.2));
+
+    match tool {
+        EditorTool::Box | EditorTool::HollowBox => {
+            let mut cells = Vec::new();
+            for x in min_x..=max_x {
+                for y in min_y..=max_y {
+                    for z in min_z..=max_z {
+                        let is_side = x == min_x || x == max_x || z == min_z || z == max_z;
+                        if tool == EditorTool::Box || is_side {
+                            cells.push((x, y, z));
+                        }
+                    }
+                }
+            }
+            cells
+        }
+        EditorTool::Line => {
+            let steps = (end.0 - start.0).abs().max((end.1 - start.1).abs()).max((end.2 - start.2).abs());
+            if steps == 0 {
+                return vec![start];
             }
+            (0..=steps)
+                .map(|step| {
+                    let t = step as f32 / steps as f32;
+                    (
+                        lerp_f32(start.0 as f32, end.0 as f32, t).round() as i32,
+                        lerp_f32(start.1 as f32, end.1 as f32, t).round() as i32,
+                        lerp_f32(start.2 as f32, end.2 as f32, t).round() as i32,
+                    )
+                })
+                .collect()
         }
+        EditorTool::Single | EditorTool::FloodFill => vec![end],
     }
 }
 
-fn update_camera(state: &mut GameState, delta: f32) {
+// In build mode: the empty space connected to the seed at or below its height, like pouring into a pit.
+// In remove mode: every voxel connected to the seed with the same type.
+pub fn flood_fill_cells(world: &World, seed: (i32, i32, i32), build_mode: bool) -> Vec<(i32, i32, i32)> {
+    let (seed_x, seed_y, seed_z) = seed;
+    if !is_valid_position(world, seed_x, seed_y, seed_z) {
+        return Vec::new();
+    }
+    let target = get_voxel(world, seed_x, seed_y, seed_z);
+    if build_mode != (target == VoxelType::EMPTY) {
+        return Vec::new();
+    }
+
+    let mut visited = HashSet::from([seed]);
+    let mut queue = VecDeque::from([seed]);
+    let mut cells = Vec::new();
+    while let Some((x, y, z)) = queue.pop_front() {
+        cells.push((x, y, z));
+        if cells.len() >= EDITOR_FLOOD_FILL_LIMIT {
+            break;
+        }
+        let neighbors = [(x + 1, y, z), (x - 1, y, z), (x, y + 1, z), (x, y - 1, z), (x, y, z + 1), (x, y, z - 1)];
+        for (nx, ny, nz) in neighbors {
+            if !is_valid_position(world, nx, ny, nz) || get_voxel(world, nx, ny, nz) != target {
+                continue;
+            }
+            if build_mode && ny > seed_y {
+                continue;
+            }
+            if visited.insert((nx, ny, nz)) {
+                queue.push_back((nx, ny, nz));
+            }
+        }
+    }
+    cells
+}
+
+pub fn camera_mode_name(mode: CameraMode) -> &'static str {
+    match mode {
+        CameraMode::Shared => "shared",
+        CameraMode::Split => "split",
+        CameraMode::Dynamic => "dynamic",
+    }
+}
+
+pub fn parse_camera_mode(name: &str) -> Option<CameraMode> {
+    [CameraMode::Shared, CameraMode::Split, CameraMode::Dynamic].into_iter().find(|mode| camera_mode_name(*mode) == name)
+}
+
+pub fn light_type_name(light_type: LightType) -> &'static str {
+    match light_type {
+        LightType::Directional => "directional",
+        LightType::Point => "point",
+        LightType::Spot => "spot",
+    }
+}
+
+pub fn parse_light_type(name: &str) -> Option<LightType> {
+    [LightType::Directional, LightType::Point, LightType::Spot].into_iter().find(|light_type| light_type_name(*light_type) == name)
+}
+
+pub fn editor_tool_name(tool: EditorTool) -> &'static str {
+    match tool {
+        EditorTool::Single => "single",
+        EditorTool::Box => "box",
+        EditorTool::HollowBox => "hollow box",
+        EditorTool::Line => "line",
+        EditorTool::FloodFill => "flood fill",
+    }
+}
+
+// Rounds put the arena back as it was when they started, so edits made during one would be lost.
+// Tells the editor when `editing` is an attempt to change it.
+fn arena_locked(state: &mut GameState, editing: bool) -> bool {
+    let locked = state.round_world.is_some();
+    if locked && editing {
+        set_editor_status(state, "The arena can't be edited during a round".to_string());
+    }
+    locked
+}
+
+// Change a voxel and record it in the stroke in progress
+fn edit_voxel(state: &mut GameState, x: i32, y: i32, z: i32, voxel_type: VoxelType) {
+    let old_type = get_voxel(&state.world, x, y, z);
+    if old_type == voxel_type {
+        return;
+    }
+    set_voxel(&mut state.world, x, y, z, voxel_type);
+    state.editor.current_stroke.push(VoxelEdit { position: (x, y, z), old_type, new_type: voxel_type });
+    push_edit_event(state, (x, y, z), old_type, voxel_type);
+}
+
+// Editor change of a voxel as a placed or removed event, replacing one block with another counts as placing
+fn push_edit_event(state: &mut GameState, position: (i32, i32, i32), old_type: VoxelType, new_type: VoxelType) {
+    let event = if new_type == VoxelType::EMPTY {
+        GameEvent::VoxelRemoved { position, voxel_type: old_type }
+    } else {
+        GameEvent::VoxelPlaced { position, voxel_type: new_type }
+    };
+    state.events.push(event);
+}
+
+// Strokes end when the mouse button is released, Ctrl+Z / Ctrl+Y undo and redo whole strokes
+fn handle_history_input(state: &mut GameState, input: &EditorInputFrame) {
+    if !input.place_down || !state.editor.active || input.undo || input.redo {
+        finish_stroke(state);
+    }
+    if !state.editor.active || arena_locked(state, input.undo || input.redo) {
+        return;
+    }
+
+    if input.undo {
+        if let Some(stroke) = state.editor.undo_stack.pop_back() {
+            for edit in stroke.iter().rev() {
+                let (x, y, z) = edit.position;
+                set_voxel(&mut state.world, x, y, z, edit.old_type);
+                push_edit_event(state, edit.position, edit.new_type, edit.old_type);
+            }
+            set_editor_status(state, format!("Undid {} voxel edits", stroke.len()));
+            state.editor.redo_stack.push(stroke);
+        } else {
+            set_editor_status(state, "Nothing to undo".to_string());
+        }
+    } else if input.redo {
+        if let Some(stroke) = state.editor.redo_stack.pop() {
+            for edit in &stroke {
+                let (x, y, z) = edit.position;
+                set_voxel(&mut state.world, x, y, z, edit.new_type);
+                push_edit_event(state, edit.position, edit.old_type, edit.new_type);
+            }
+            set_editor_status(state, format!("Redid {} voxel edits", stroke.len()));
+            state.editor.undo_stack.push_back(stroke);
+        } else {
+            set_editor_status(state, "Nothing to redo".to_string());
+        }
+    }
+}
+
+fn finish_stroke(state: &mut GameState) {
+    if state.editor.current_stroke.is_empty() {
+        return;
+    }
+    let stroke = std::mem::take(&mut state.editor.current_stroke);
+    state.editor.undo_stack.push_back(stroke);
+    if state.editor.undo_stack.len() > EDITOR_HISTORY_LIMIT {
+        state.editor.undo_stack.pop_front();
+    }
+    // A new edit makes the undone strokes unreachable
+    state.editor.redo_stack.clear();
+}
+
+// Edits refer to the previous world, forget them when it's replaced
+fn clear_edit_history(state: &mut GameState) {
+    state.editor.current_stroke.clear();
+    state.editor.undo_stack.clear();
+    state.editor.redo_stack.clear();
+}
+
+fn update_camera(state: &mut GameState, input: &EditorInputFrame, delta: f32) {
     // Handle transition from debug mode
     if let Some(pre_camera) = &state.editor.camera.game_camera {
         let transition_speed = 5.0 * delta;
@@ -673,8 +1484,11 @@ fn update_camera(state: &mut GameState, delta: f32) {
         }
     }
 
-    // Debug mode camera controls
+    // Debug mode camera controls, the editor always uses the shared camera
     if state.editor.active {
+        state.camera_state.split = false;
+        state.camera_state.player_cameras.clear();
+
         // Set center point to the center of the world at y=0
         state.editor.camera.center = Vector3::new(
             state.world.width as f32 * state.world.voxel_size / 2.0,
@@ -683,14 +1497,14 @@ fn update_camera(state: &mut GameState, delta: f32) {
         );
 
         // Handle mouse wheel zoom in debug mode
-        let wheel_move = unsafe { ffi::GetMouseWheelMove() as f32 };
+        let wheel_move = input.mouse_wheel;
         if wheel_move != 0.0 {
             state.editor.camera.distance = (state.editor.camera.distance * (1.0 - wheel_move * 0.1)).clamp(5.0, 50.0);
         }
 
         // Handle middle mouse button rotation
-        if unsafe { ffi::IsMouseButtonDown(MouseButton::MOUSE_BUTTON_MIDDLE as i32) } {
-            let mouse_delta = unsafe { ffi::GetMouseDelta() };
+        if input.rotate_down {
+            let mouse_delta = input.mouse_delta;
             state.editor.camera.rotation.x -= mouse_delta.x * 0.01;
             state.editor.camera.rotation.y = (state.editor.camera.rotation.y + mouse_delta.y * 0.01).clamp(-1.5, 1.5);
         }
@@ -720,8 +1534,11 @@ fn update_camera(state: &mut GameState, delta: f32) {
             transition_speed
         );
     } else {
-        // Game mode camera controls
-        if !state.players.is_empty() {
+        // Game mode camera controls, eliminated players are not framed
+        let alive_players: Vec<&PlayerState> = state.players.values()
+            .filter(|player| player.is_alive)
+            .collect();
+        if !alive_players.is_empty() {
             // Calculate center point between all players
             let mut center = Vector3::zero();
             let mut min_x = f32::MAX;
@@ -729,7 +1546,7 @@ fn update_camera(state: &mut GameState, delta: f32) {
             let mut min_z = f32::MAX;
             let mut max_z = f32::MIN;
             
-            for player in state.players.values() {
+            for player in &alive_players {
                 center += player.position;
                 min_x = min_x.min(player.position.x);
                 max_x = max_x.max(player.position.x);
@@ -737,7 +1554,7 @@ fn update_camera(state: &mut GameState, delta: f32) {
                 max_z = max_z.max(player.position.z);
             }
             
-            center /= state.players.len() as f32;
+            center /= alive_players.len() as f32;
             
             // Calculate required distance to see all players
             let width = max_x - min_x;
@@ -745,8 +1562,8 @@ fn update_camera(state: &mut GameState, delta: f32) {
             let max_dimension = width.max(depth);
             
             // Calculate target height and distance based on player spread
-            let target_height = (max_dimension * 0.5).max(15.0).min(30.0);
-            let target_distance = (max_dimension * 0.7).max(20.0).min(40.0);
+            let target_height = (max_dimension * 0.5).clamp(15.0, 30.0);
+            let target_distance = (max_dimension * 0.7).clamp(20.0, 40.0);
             
             // Smoothly adjust camera height and distance with slower speed
             let height_transition_speed = 1.0 * delta;
@@ -772,31 +1589,334 @@ fn update_camera(state: &mut GameState, delta: f32) {
                 target_transition_speed
             );
         }
+
+        // The shared camera keeps following while split so merging back doesn't jump
+        update_player_cameras(state, delta);
     }
 }
 
-fn is_valid_position(world: &World, x: i32, y: i32, z: i32) -> bool {
+// Split or merge the screen for the camera mode and move the camera of every pane after its player
+fn update_player_cameras(state: &mut GameState, delta: f32) {
+    let mut ids: Vec<i32> = state.players.iter()
+        .filter(|(_, player)| player.is_alive)
+        .map(|(id, _)| *id)
+        .collect();
+    ids.sort();
+    ids.truncate(MAX_SPLIT_PANES);
+
+    let camera_state = &mut state.camera_state;
+    camera_state.split = ids.len() >= 2 && match camera_state.mode {
+        CameraMode::Shared => false,
+        CameraMode::Split => true,
+        CameraMode::Dynamic => {
+            // Merging takes a smaller spread than splitting so the screen doesn't flicker at the threshold
+            let threshold = if camera_state.split { MERGE_DISTANCE } else { SPLIT_DISTANCE };
+            player_spread(ids.iter().map(|id| state.players[id].position)) > threshold
+        }
+    };
+    if !camera_state.split {
+        camera_state.player_cameras.clear();
+        return;
+    }
+
+    // New panes start at the shared camera and move out to their player from there
+    let shared = camera_state.camera;
+    camera_state.player_cameras.retain(|view| ids.contains(&view.player_id));
+    for id in &ids {
+        if !camera_state.player_cameras.iter().any(|view| view.player_id == *id) {
+            camera_state.player_cameras.push(PlayerCamera { player_id: *id, camera: shared, previous_camera: shared });
+        }
+    }
+    camera_state.player_cameras.sort_by_key(|view| view.player_id);
+
+    let transition_speed = 2.0 * delta;
+    for view in camera_state.player_cameras.iter_mut() {
+        let position = state.players[&view.player_id].position;
+        let camera_position = Vector3::new(position.x, position.y + PLAYER_CAMERA_HEIGHT, position.z + PLAYER_CAMERA_DISTANCE);
+        view.camera.position = view.camera.position.lerp(camera_position, transition_speed);
+        view.camera.target = view.camera.target.lerp(position, transition_speed);
+    }
+}
+
+// Largest distance between the positions along the x or z axis
+fn player_spread(positions: impl Iterator<Item = Vector3>) -> f32 {
+    let mut min = Vector2::new(f32::MAX, f32::MAX);
+    let mut max = Vector2::new(f32::MIN, f32::MIN);
+    for position in positions {
+        min = Vector2::new(min.x.min(position.x), min.y.min(position.z));
+        max = Vector2::new(max.x.max(position.x), max.y.max(position.z));
+    }
+    (max.x - min.x).max(max.y - min.y).max(0.0)
+}
+
+// Keep the cameras of the last tick so the renderer can interpolate between ticks
+fn remember_cameras(camera_state: &mut CameraState) {
+    camera_state.previous_camera = camera_state.camera;
+    for view in camera_state.player_cameras.iter_mut() {
+        view.previous_camera = view.camera;
+    }
+}
+
+// Switch to the next camera mode, the split follows on the next tick
+pub fn cycle_camera_mode(state: &mut GameState) {
+    state.camera_state.mode = match state.camera_state.mode {
+        CameraMode::Shared => CameraMode::Split,
+        CameraMode::Split => CameraMode::Dynamic,
+        CameraMode::Dynamic => CameraMode::Shared,
+    };
+}
+
+pub fn is_valid_position(world: &World, x: i32, y: i32, z: i32) -> bool {
     x >= 0 && x < world.width && y >= 0 && y < world.height && z >= 0 && z < world.depth
 }
 
-fn get_voxel(world: &World, x: i32, y: i32, z: i32) -> VoxelType {
-    if is_valid_position(world, x, y, z) {
-        if let Some(voxel) = world.voxels.iter().find(|v| v.position.x == x as f32 && v.position.y == y as f32 && v.position.z == z as f32) {
-            voxel.voxel_type
-        } else {
-            VoxelType::Empty
+pub fn create_world(width: i32, height: i32, depth: i32, voxel_size: f32) -> World {
+    let mut world = World {
+        width,
+        height,
+        depth,
+        voxels: vec![VoxelType::EMPTY; (width * height * depth) as usize],
+        voxel_size,
+        spawn_points: Vec::new(),
+        chunk_versions: Vec::new(),
+        next_chunk_version: 2,
+    };
+    let (chunks_x, chunks_y, chunks_z) = chunk_dimensions(&world);
+    world.chunk_versions = vec![1; (chunks_x * chunks_y * chunks_z) as usize];
+    world
+}
+
+// Put a world that wasn't derived from the current one (loaded, generated or received) in its place.
+// Every chunk gets a version the old world never used, so meshes and navigation built for it are redone.
+pub fn replace_world(world: &mut World, new_world: World) {
+    let next_version = world.next_chunk_version.max(new_world.next_chunk_version);
+    *world = new_world;
+    world.next_chunk_version = next_version;
+    for index in 0..world.chunk_versions.len() {
+        world.chunk_versions[index] = next_chunk_version(world);
+    }
+}
+
+// Keep numbering after going back to an earlier copy of the same world: chunks changed since the copy
+// differ from the versions built for them, later changes never reuse a version built before
+pub fn continue_chunk_versions(world: &mut World, next_version: u64) {
+    world.next_chunk_version = world.next_chunk_version.max(next_version);
+}
+
+// Render chunks along each axis, the last chunk of an axis may be partial
+pub fn chunk_dimensions(world: &World) -> (i32, i32, i32) {
+    let chunks = |size: i32| (size + CHUNK_SIZE - 1) / CHUNK_SIZE;
+    (chunks(world.width), chunks(world.height), chunks(world.depth))
+}
+
+pub fn chunk_index(world: &World, chunk_x: i32, chunk_y: i32, chunk_z: i32) -> usize {
+    let (chunks_x, _, chunks_z) = chunk_dimensions(world);
+    ((chunk_y * chunks_z + chunk_z) * chunks_x + chunk_x) as usize
+}
+
+pub fn chunk_position(world: &World, index: usize) -> (i32, i32, i32) {
+    let (chunks_x, _, chunks_z) = chunk_dimensions(world);
+    let index = index as i32;
+    (index % chunks_x, index / (chunks_x * chunks_z), (index / chunks_x) % chunks_z)
+}
+
+fn next_chunk_version(world: &mut World) -> u64 {
+    let version = world.next_chunk_version;
+    world.next_chunk_version += 1;
+    version
+}
+
+// A changed voxel affects the faces of its neighbors, which may belong to the adjacent chunks
+fn mark_chunks_dirty(world: &mut World, x: i32, y: i32, z: i32) {
+    let (chunks_x, chunks_y, chunks_z) = chunk_dimensions(world);
+    for (dx, dy, dz) in [(0, 0, 0), (1, 0, 0), (-1, 0, 0), (0, 1, 0), (0, -1, 0), (0, 0, 1), (0, 0, -1)] {
+        let chunk_x = (x + dx).div_euclid(CHUNK_SIZE);
+        let chunk_y = (y + dy).div_euclid(CHUNK_SIZE);
+        let chunk_z = (z + dz).div_euclid(CHUNK_SIZE);
+        if !(0..chunks_x).contains(&chunk_x) || !(0..chunks_y).contains(&chunk_y) || !(0..chunks_z).contains(&chunk_z) {
+            continue;
         }
+        let index = chunk_index(world, chunk_x, chunk_y, chunk_z);
+        world.chunk_versions[index] = next_chunk_version(world);
+    }
+}
+
+fn voxel_index(world: &World, x: i32, y: i32, z: i32) -> usize {
+    ((y * world.depth + z) * world.width + x) as usize
+}
+
+pub fn get_voxel(world: &World, x: i32, y: i32, z: i32) -> VoxelType {
+    if is_valid_position(world, x, y, z) {
+        world.voxels[voxel_index(world, x, y, z)]
     } else {
-        VoxelType::Empty
+        VoxelType::EMPTY
     }
 }
 
-fn set_voxel(world: &mut World, x: i32, y: i32, z: i32, voxel_type: VoxelType) {
+pub fn set_voxel(world: &mut World, x: i32, y: i32, z: i32, voxel_type: VoxelType) {
     if is_valid_position(world, x, y, z) {
-        if let Some(voxel) = world.voxels.iter_mut().find(|v| v.position.x == x as f32 && v.position.y == y as f32 && v.position.z == z as f32) {
-            voxel.voxel_type = voxel_type;
-        } else if voxel_type != VoxelType::Empty {
-            world.voxels.push(Voxel { position: Vector3::new(x as f32, y as f32, z as f32), voxel_type });
+        let index = voxel_index(world, x, y, z);
+        if world.voxels[index] != voxel_type {
+            world.voxels[index] = voxel_type;
+            mark_chunks_dirty(world, x, y, z);
+        }
+    }
+}
+
+// Iterate over all non-empty cells of the world
+pub fn iter_voxels(world: &World) -> impl Iterator<Item = Voxel> + '_ {
+    let width = world.width as usize;
+    let depth = world.depth as usize;
+    world.voxels.iter().enumerate()
+        .filter(|(_, voxel_type)| **voxel_type != VoxelType::EMPTY)
+        .map(move |(index, voxel_type)| Voxel {
+            position: Vector3::new(
+                (index % width) as f32,
+                (index / (width * depth)) as f32,
+                ((index / width) % depth) as f32,
+            ),
+            voxel_type: *voxel_type,
+        })
+}
+
+// Walk the grid cells along the ray (Amanatides & Woo) and return the first non-empty voxel
+// together with the hit distance and the normal of the face the ray entered through
+fn raycast_voxels(world: &World, ray: Ray) -> Option<(i32, i32, i32, f32, Vector3)> {
+    let voxel_size = world.voxel_size;
+    // Voxels are centered on their coordinates, shifting the ray by half a voxel makes cell x span [x, x + 1)
+    let half = voxel_size / 2.0;
+    let origin = [ray.position.x + half, ray.position.y + half, ray.position.z + half];
+    let direction = [ray.direction.x, ray.direction.y, ray.direction.z];
+    let limits = [world.width, world.height, world.depth];
+
+    // Clip the ray against the world bounds
+    let mut t_enter = 0.0f32;
+    let mut t_exit = f32::MAX;
+    let mut enter_axis = None;
+    for axis in 0..3 {
+        let bound = limits[axis] as f32 * voxel_size;
+        if direction[axis].abs() < f32::EPSILON {
+            if origin[axis] < 0.0 || origin[axis] > bound {
+                return None;
+            }
+            continue;
+        }
+        let t0 = -origin[axis] / direction[axis];
+        let t1 = (bound - origin[axis]) / direction[axis];
+        let (near, far) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+        if near > t_enter {
+            t_enter = near;
+            enter_axis = Some(axis);
+        }
+        t_exit = t_exit.min(far);
+    }
+    if t_enter > t_exit {
+        return None;
+    }
+
+    let mut cell = [0i32; 3];
+    let mut step = [0i32; 3];
+    let mut t_max = [f32::MAX; 3];
+    let mut t_delta = [f32::MAX; 3];
+    for axis in 0..3 {
+        let start = origin[axis] + direction[axis] * t_enter;
+        cell[axis] = ((start / voxel_size).floor() as i32).clamp(0, limits[axis] - 1);
+        if direction[axis] > 0.0 {
+            step[axis] = 1;
+            t_max[axis] = t_enter + ((cell[axis] + 1) as f32 * voxel_size - start) / direction[axis];
+            t_delta[axis] = voxel_size / direction[axis];
+        } else if direction[axis] < 0.0 {
+            step[axis] = -1;
+            t_max[axis] = t_enter + (cell[axis] as f32 * voxel_size - start) / direction[axis];
+            t_delta[axis] = -voxel_size / direction[axis];
+        }
+    }
+
+    let mut distance = t_enter;
+    let mut normal_axis = enter_axis;
+    loop {
+        if get_voxel(world, cell[0], cell[1], cell[2]) != VoxelType::EMPTY {
+            let mut normal = [0.0f32; 3];
+            if let Some(axis) = normal_axis {
+                normal[axis] = -step[axis] as f32;
+            }
+            return Some((cell[0], cell[1], cell[2], distance, Vector3::new(normal[0], normal[1], normal[2])));
+        }
+
+        // Advance along the axis whose cell boundary is closest
+        let axis = if t_max[0] < t_max[1] {
+            if t_max[0] < t_max[2] { 0 } else { 2 }
+        } else if t_max[1] < t_max[2] { 1 } else { 2 };
+
+        if t_max[axis] > t_exit {
+            return None;
+        }
+        cell[axis] += step[axis];
+        if cell[axis] < 0 || cell[axis] >= limits[axis] {
+            return None;
         }
+        distance = t_max[axis];
+        t_max[axis] += t_delta[axis];
+        normal_axis = Some(axis);
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TICK_RATE;
+
+    const DELTA: f32 = 1.0 / TICK_RATE as f32;
+
+    fn frame(players: &[(i32, PlayerInputFrame)]) -> InputFrame {
+        InputFrame { players: players.iter().copied().collect(), ..InputFrame::default() }
+    }
+
+    fn run(state: &mut GameState, input: &InputFrame, ticks: usize) {
+        for _ in 0..ticks {
+            update(state, input, DELTA);
+        }
+    }
+
+    #[test]
+    fn input_frames_join_move_and_dash() {
+        let mut state = GameState::default();
+        init(&mut state);
+        seed_simulation(&mut state, 7);
+
+        // The keyboard joins on its join key, a gamepad on any input once it has been seen
+        let idle = PlayerInputFrame::default();
+        let join = PlayerInputFrame { join_pressed: true, ..idle };
+        update(&mut state, &frame(&[(0, join), (2, idle)]), DELTA);
+        assert!(state.players.contains_key(&0));
+        assert!(matches!(state.events[..], [GameEvent::PlayerJoined { player_id: 0, .. }]));
+        assert!(!state.players.contains_key(&2));
+        let right = PlayerInputFrame { movement: Vector2::new(1.0, 0.0), ..idle };
+        update(&mut state, &frame(&[(0, idle), (2, right)]), DELTA);
+        assert!(matches!(state.events[..], [GameEvent::PlayerJoined { player_id: 2, .. }]));
+
+        // Pressing join again toggles ready
+        update(&mut state, &frame(&[(0, join)]), DELTA);
+        assert!(state.players[&0].is_ready);
+        assert!(!state.players[&2].is_ready);
+
+        // Let the players land, then walk the keyboard player right and the gamepad player down
+        run(&mut state, &frame(&[(0, idle), (2, idle)]), TICK_RATE as usize);
+        let start = (state.players[&0].position, state.players[&2].position);
+        let down = PlayerInputFrame { movement: Vector2::new(0.0, 1.0), ..idle };
+        run(&mut state, &frame(&[(0, right), (2, down)]), 10);
+        let keyboard = state.players[&0].position - start.0;
+        let gamepad = state.players[&2].position - start.1;
+        assert!(keyboard.x > 0.2 && keyboard.z.abs() < 0.01, "keyboard player moved {:?}", keyboard);
+        assert!(gamepad.z > 0.2 && gamepad.x.abs() < 0.01, "gamepad player moved {:?}", gamepad);
+
+        // Dashing needs a direction and speeds the player up along it
+        let walking_speed = state.players[&0].velocity.x;
+        update(&mut state, &frame(&[(0, PlayerInputFrame { dash_pressed: true, ..idle }), (2, idle)]), DELTA);
+        assert!(!state.players[&0].is_dashing);
+        update(&mut state, &frame(&[(0, PlayerInputFrame { dash_pressed: true, ..right }), (2, idle)]), DELTA);
+        assert!(state.players[&0].is_dashing);
+        assert!(state.players[&0].velocity.x > walking_speed);
+        assert!(matches!(state.events[..], [GameEvent::DashStarted { player_id: 0, .. }]));
+    }
+}
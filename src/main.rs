@@ -1,17 +1,45 @@
 use std::time::Instant;
+use raylib::prelude::{RaylibHandle, RaylibThread};
 use raylib::ffi::{SetConfigFlags, ConfigFlags};
-use crate::state::GameState;
-use crate::logic::{init, update};
-use crate::rendering::render;
-use crate::config::{SCREEN_WIDTH, SCREEN_HEIGHT};
-
-mod state;
-mod logic;
-mod rendering;
-mod utils;
-mod config;
+use voxel_battle::state::{AudioState, ClientState, Controls, GameState, InputFrame, RenderState, Replay};
+use voxel_battle::logic::{cycle_camera_mode, init, update, update_client};
+use voxel_battle::rendering::{init_render, render};
+use voxel_battle::input::{collect_input, collect_replay_input, merge_input, clear_pressed, local_player_input};
+use voxel_battle::args::{apply_arena_args, apply_audio_args, apply_bot_args, apply_camera_args, arg_value};
+use voxel_battle::audio::{close_audio, init_audio, play_event_sounds, toggle_mute, update_music};
+use voxel_battle::bots::update_bots;
+use voxel_battle::controls::init_controls;
+use voxel_battle::replay::{load_replay, playback_status, record_tick, save_replay, start_playback, start_recording, update_playback};
+use voxel_battle::network::{
+    client_connect, client_disconnect, client_receive, client_send_input, open_client_socket, with_default_port,
+};
+use voxel_battle::config::{SCREEN_WIDTH, SCREEN_HEIGHT, TICK_RATE, MAX_FRAME_TIME, CONTROLS_PATH};
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    // Join a server passed as `--connect <host[:port]>` instead of playing locally
+    let mut network = arg_value(&args, "--connect").map(|address| {
+        let address = with_default_port(&address);
+        let socket = open_client_socket(&address).unwrap_or_else(|error| {
+            eprintln!("Failed to connect to {}: {}", address, error);
+            std::process::exit(1);
+        });
+        println!("Connecting to {}", address);
+        let mut client = ClientState::default();
+        client_connect(&mut client, &socket);
+        (client, socket)
+    });
+
+    // Watch a replay passed as `--replay <path>` instead of playing
+    let replay = arg_value(&args, "--replay").map(|path| load_replay(&path).unwrap_or_else(|error| {
+        eprintln!("Failed to load replay {}: {}", path, error);
+        std::process::exit(1);
+    }));
+
+    // Key and gamepad bindings, `--controls <path>` reads another file than the default one
+    let controls = init_controls(&arg_value(&args, "--controls").unwrap_or_else(|| CONTROLS_PATH.to_string()));
+
     unsafe {
         SetConfigFlags(ConfigFlags::FLAG_MSAA_4X_HINT as u32);
     }
@@ -21,20 +49,122 @@ fn main() {
         .build();
 
     let mut state = GameState::default();
-    
+    let mut render_state = RenderState::default();
+    let mut audio_state = AudioState::default();
+
     init(&mut state);
-    
+    apply_camera_args(&mut state, &args);
+
+    // The server decides the arena and runs the bots in network games
+    if network.is_none() {
+        apply_arena_args(&mut state, &args);
+        apply_bot_args(&mut state, &args);
+    }
+
+    init_render(&mut render_state);
+    if let Some(replay) = replay {
+        watch_replay(replay, &controls, &mut render_state, &mut rl, &thread);
+        return;
+    }
+    apply_audio_args(&mut audio_state.settings, &args);
+    init_audio(&mut audio_state);
+
+    // Record local play to `--record <path>`, saved when the game closes
+    let mut recording = match arg_value(&args, "--record") {
+        Some(_) if network.is_some() => {
+            eprintln!("Network games can't be recorded, only the server sees every player's input");
+            None
+        }
+        Some(path) => Some((path, start_recording(&mut state))),
+        None => None,
+    };
+
     // rl.set_target_fps(240);
-    
+
+    let fixed_delta = 1.0 / TICK_RATE as f32;
+    let mut accumulator = 0.0;
+    let mut pending_input = InputFrame::default();
     let mut last_update = Instant::now();
     while !rl.window_should_close() {
-        let dt = last_update.elapsed().as_secs_f32();
+        let frame_time = last_update.elapsed().as_secs_f32().min(MAX_FRAME_TIME);
         last_update = Instant::now();
+        accumulator += frame_time;
+
+        // Input
+        merge_input(&mut pending_input, collect_input(state.camera_state.camera, &controls));
 
-        // Logic 
-        update(&mut state, dt);
+        // Logic at a fixed rate, presses are consumed by the first tick that runs
+        while accumulator >= fixed_delta {
+            match network.as_mut() {
+                Some((client, socket)) => {
+                    if let Err(error) = client_receive(client, &mut state, socket, fixed_delta) {
+                        eprintln!("Disconnected: {}", error);
+                        return;
+                    }
+                    if pending_input.cycle_camera_mode {
+                        cycle_camera_mode(&mut state);
+                    }
+                    let input = local_player_input(&pending_input);
+                    client_send_input(client, socket, input);
+                    if let Some(id) = client.player_id {
+                        update_client(&mut state, id, &input, fixed_delta);
+                    }
+                }
+                None => {
+                    // Editor changes aren't part of replays, the recording ends where the editor opens
+                    if pending_input.toggle_editor {
+                        if let Some((path, replay)) = recording.take() {
+                            finish_recording(&replay, &path);
+                        }
+                    }
+                    if let Some((_, replay)) = recording.as_mut() {
+                        record_tick(replay, &pending_input);
+                    }
+                    update_bots(&mut state, &mut pending_input, fixed_delta);
+                    update(&mut state, &pending_input, fixed_delta);
+                    // Events only come from the local simulation, network clients don't receive them
+                    play_event_sounds(&audio_state, &state);
+                }
+            }
+            if pending_input.toggle_mute {
+                toggle_mute(&mut audio_state);
+            }
+            clear_pressed(&mut pending_input);
+            accumulator -= fixed_delta;
+        }
+
+        update_music(&mut audio_state, &state);
+
+        // Render between the previous and the current tick
+        render(&state, &mut render_state, accumulator / fixed_delta, &mut rl, &thread);
+    }
+
+    if let Some((_, socket)) = &network {
+        client_disconnect(socket);
+    }
+    close_audio(&mut audio_state);
+    if let Some((path, replay)) = &recording {
+        finish_recording(replay, path);
+    }
+}
+
+fn finish_recording(replay: &Replay, path: &str) {
+    match save_replay(replay, path) {
+        Ok(()) => println!("Saved a replay of {} ticks to {}", replay.ticks.len(), path),
+        Err(error) => eprintln!("Failed to save replay {}: {}", path, error),
+    }
+}
+
+// Re-simulate a recorded session with a free camera and playback controls
+fn watch_replay(replay: Replay, controls: &Controls, render_state: &mut RenderState, rl: &mut RaylibHandle, thread: &RaylibThread) {
+    let (mut playback, mut state) = start_playback(replay);
+    let mut last_update = Instant::now();
+    while !rl.window_should_close() {
+        let frame_time = last_update.elapsed().as_secs_f32().min(MAX_FRAME_TIME);
+        last_update = Instant::now();
 
-        // Render 
-        render(&state, &mut rl, &thread);
+        let alpha = update_playback(&mut playback, &mut state, &collect_replay_input(controls), frame_time);
+        render_state.replay_status = Some(playback_status(&playback));
+        render(&state, render_state, alpha, rl, thread);
     }
 }
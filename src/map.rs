@@ -0,0 +1,293 @@
+use raylib::prelude::*;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use crate::state::{BlockRegistry, GameState, LightSource, VoxelType, World};
+use crate::logic::{create_world, iter_voxels, get_voxel, light_type_name, parse_light_type, replace_world, set_voxel};
+use crate::blocks::{block_properties, find_block};
+use crate::format::{FileFormat, FormatError, parse_field, read_lines};
+use crate::config::{MAX_LIGHTS, MAX_WORLD_DIMENSION};
+
+pub const MAP_FORMAT: FileFormat = FileFormat {
+    kind: "map file",
+    header: "voxel_battle_map",
+    version: 2, // Version 2 added light types and more than one light
+};
+
+#[derive(Debug)]
+pub enum MapError {
+    Format(FormatError),
+    InvalidDimensions { width: i32, height: i32, depth: i32 },
+    OutOfBounds { line: usize, position: Vector3 },
+    MissingSize,
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::Format(error) => write!(f, "{}", error),
+            MapError::InvalidDimensions { width, height, depth } => write!(
+                f, "invalid world size {}x{}x{} (each side must be 1..={})", width, height, depth, MAX_WORLD_DIMENSION
+            ),
+            MapError::OutOfBounds { line, position } => write!(
+                f, "line {}: position ({}, {}, {}) is outside the world", line, position.x, position.y, position.z
+            ),
+            MapError::MissingSize => write!(f, "'size' must be declared before voxels and spawn points"),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+impl From<FormatError> for MapError {
+    fn from(error: FormatError) -> Self {
+        MapError::Format(error)
+    }
+}
+
+impl From<std::io::Error> for MapError {
+    fn from(error: std::io::Error) -> Self {
+        MapError::Format(FormatError::Io(error))
+    }
+}
+
+pub fn save_map(state: &GameState, path: &str) -> Result<(), MapError> {
+    if let Some(parent) = Path::new(path).parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    fs::write(path, serialize_map(&state.world, &state.lights, &state.blocks))?;
+    Ok(())
+}
+
+// Load a map file into the state. The state is left untouched if the file is invalid.
+pub fn load_map(state: &mut GameState, path: &str) -> Result<(), MapError> {
+    let text = fs::read_to_string(path)?;
+    let (world, lights) = parse_map(&text, &state.blocks)?;
+    replace_world(&mut state.world, world);
+    state.lights = lights;
+    Ok(())
+}
+
+// Voxels are stored by block name so maps survive changes to the block IDs
+pub fn serialize_map(world: &World, lights: &[LightSource], blocks: &BlockRegistry) -> String {
+    let mut out = String::new();
+    out.push_str(&format!("{} {}\n", MAP_FORMAT.header, MAP_FORMAT.version));
+    out.push_str(&format!("size {} {} {}\n", world.width, world.height, world.depth));
+    out.push_str(&format!("voxel_size {}\n", world.voxel_size));
+    for light in lights {
+        out.push_str(&format!(
+            "light {} {} {} {} {} {} {} {} {} {} {} {} {} {}\n",
+            light.position.x, light.position.y, light.position.z,
+            light.target.x, light.target.y, light.target.z,
+            light.color.r, light.color.g, light.color.b, light.color.a,
+            if light.enabled { "on" } else { "off" },
+            light_type_name(light.light_type), light.radius, light.cone_angle
+        ));
+    }
+    for spawn in &world.spawn_points {
+        out.push_str(&format!("spawn {} {} {}\n", spawn.x, spawn.y, spawn.z));
+    }
+    for voxel in iter_voxels(world) {
+        out.push_str(&format!(
+            "voxel {} {} {} {}\n",
+            voxel.position.x, voxel.position.y, voxel.position.z,
+            block_properties(blocks, voxel.voxel_type).name
+        ));
+    }
+    out
+}
+
+// Lights are "light <position> <target> <r g b a> <on|off> [<type> <radius> <cone angle>]", version 1 maps have
+// exactly one light without the optional fields and treat it as directional
+pub fn parse_map(text: &str, blocks: &BlockRegistry) -> Result<(World, Vec<LightSource>), MapError> {
+    let (version, lines) = read_lines(&MAP_FORMAT, text)?;
+
+    let mut world: Option<World> = None;
+    let mut voxel_size = 1.0;
+    let mut lights = Vec::new();
+
+    for (line, content) in lines {
+        let mut fields = content.split_whitespace();
+        let keyword = fields.next().unwrap_or("");
+        match keyword {
+            "size" => {
+                let width: i32 = parse_field(fields.next(), line, "width")?;
+                let height: i32 = parse_field(fields.next(), line, "height")?;
+                let depth: i32 = parse_field(fields.next(), line, "depth")?;
+                let valid = |value: i32| value > 0 && value <= MAX_WORLD_DIMENSION;
+                if !valid(width) || !valid(height) || !valid(depth) {
+                    return Err(MapError::InvalidDimensions { width, height, depth });
+                }
+                world = Some(create_world(width, height, depth, voxel_size));
+            }
+            "voxel_size" => {
+                voxel_size = parse_field(fields.next(), line, "voxel size")?;
+                if !voxel_size.is_finite() || voxel_size <= 0.0 {
+                    return Err(FormatError::Parse { line, message: "voxel size must be positive".to_string() }.into());
+                }
+                if let Some(world) = world.as_mut() {
+                    world.voxel_size = voxel_size;
+                }
+            }
+            "light" => {
+                let position = parse_vector(&mut fields, line)?;
+                let target = parse_vector(&mut fields, line)?;
+                let color = Color {
+                    r: parse_field(fields.next(), line, "light color")?,
+                    g: parse_field(fields.next(), line, "light color")?,
+                    b: parse_field(fields.next(), line, "light color")?,
+                    a: parse_field(fields.next(), line, "light color")?,
+                };
+                let enabled = match fields.next() {
+                    Some("on") => true,
+                    Some("off") => false,
+                    _ => return Err(FormatError::Parse { line, message: "light must end with 'on' or 'off'".to_string() }.into()),
+                };
+                let mut light = LightSource { position, target, color, enabled, ..LightSource::default() };
+                if let Some(name) = fields.next() {
+                    light.light_type = parse_light_type(name)
+                        .ok_or_else(|| FormatError::Parse { line, message: format!("unknown light type '{}'", name) })?;
+                    light.radius = parse_field(fields.next(), line, "light radius")?;
+                    light.cone_angle = parse_field(fields.next(), line, "light cone angle")?;
+                    if !(light.radius > 0.0 && light.radius.is_finite() && (0.0..=90.0).contains(&light.cone_angle)) {
+                        return Err(FormatError::Parse { line, message: "light radius must be positive and its cone 0 to 90 degrees".to_string() }.into());
+                    }
+                }
+                if lights.len() >= MAX_LIGHTS {
+                    return Err(FormatError::Parse { line, message: format!("a map can have at most {} lights", MAX_LIGHTS) }.into());
+                }
+                lights.push(light);
+            }
+            "spawn" => {
+                let world = world.as_mut().ok_or(MapError::MissingSize)?;
+                let position = parse_vector(&mut fields, line)?;
+                let inside = position.x >= 0.0 && position.x < world.width as f32 * world.voxel_size &&
+                    position.y >= 0.0 && position.y < world.height as f32 * world.voxel_size &&
+                    position.z >= 0.0 && position.z < world.depth as f32 * world.voxel_size;
+                if !inside {
+                    return Err(MapError::OutOfBounds { line, position });
+                }
+                world.spawn_points.push(position);
+            }
+            "voxel" => {
+                let world = world.as_mut().ok_or(MapError::MissingSize)?;
+                let x: i32 = parse_field(fields.next(), line, "voxel x")?;
+                let y: i32 = parse_field(fields.next(), line, "voxel y")?;
+                let z: i32 = parse_field(fields.next(), line, "voxel z")?;
+                if x < 0 || x >= world.width || y < 0 || y >= world.height || z < 0 || z >= world.depth {
+                    return Err(MapError::OutOfBounds { line, position: Vector3::new(x as f32, y as f32, z as f32) });
+                }
+                let name = fields.next().unwrap_or("");
+                let voxel_type = find_block(blocks, name)
+                    .filter(|voxel_type| *voxel_type != VoxelType::EMPTY)
+                    .ok_or_else(|| FormatError::Parse { line, message: format!("unknown block '{}'", name) })?;
+                if get_voxel(world, x, y, z) != VoxelType::EMPTY {
+                    return Err(FormatError::Parse { line, message: format!("voxel ({}, {}, {}) is defined twice", x, y, z) }.into());
+                }
+                set_voxel(world, x, y, z, voxel_type);
+            }
+            _ => {
+                return Err(FormatError::Parse { line, message: format!("unknown entry '{}'", keyword) }.into());
+            }
+        }
+
+        if fields.next().is_some() {
+            return Err(FormatError::Parse { line, message: "unexpected trailing values".to_string() }.into());
+        }
+    }
+
+    let world = world.ok_or(MapError::MissingSize)?;
+    // Version 1 maps without a light line used the default one
+    if version == 1 && lights.is_empty() {
+        lights.push(LightSource::default());
+    }
+    Ok((world, lights))
+}
+
+fn parse_vector<'a>(fields: &mut impl Iterator<Item = &'a str>, line: usize) -> Result<Vector3, FormatError> {
+    Ok(Vector3::new(
+        parse_field(fields.next(), line, "x coordinate")?,
+        parse_field(fields.next(), line, "y coordinate")?,
+        parse_field(fields.next(), line, "z coordinate")?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::LightType;
+
+    fn sample_world() -> (World, Vec<LightSource>) {
+        let mut world = create_world(6, 4, 5, 0.5);
+        for x in 0..6 {
+            for z in 0..5 {
+                set_voxel(&mut world, x, 0, z, VoxelType::GROUND);
+            }
+        }
+        set_voxel(&mut world, 2, 1, 3, VoxelType::WALL);
+        world.spawn_points.push(Vector3::new(0.5, 0.5, 1.0));
+        world.spawn_points.push(Vector3::new(2.0, 0.5, 1.5));
+        let spot = LightSource { light_type: LightType::Spot, radius: 12.0, cone_angle: 30.0, enabled: false, ..LightSource::default() };
+        (world, vec![LightSource::default(), spot])
+    }
+
+    fn parse(text: &str) -> Result<(World, Vec<LightSource>), MapError> {
+        parse_map(text, &BlockRegistry::default())
+    }
+
+    #[test]
+    fn saved_map_loads_back() {
+        let (world, lights) = sample_world();
+        let mut state = GameState::default();
+        replace_world(&mut state.world, world.clone());
+        state.lights = lights.clone();
+
+        let path = std::env::temp_dir().join(format!("voxel_battle_map_test_{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        save_map(&state, path).unwrap();
+        let mut loaded = GameState::default();
+        let result = load_map(&mut loaded, path);
+        fs::remove_file(path).unwrap();
+        result.unwrap();
+
+        assert_eq!(loaded.world.voxels, world.voxels);
+        assert_eq!(loaded.world.spawn_points, world.spawn_points);
+        assert_eq!((loaded.world.width, loaded.world.height, loaded.world.depth), (6, 4, 5));
+        assert_eq!(loaded.world.voxel_size, 0.5);
+        assert_eq!(loaded.lights, lights);
+    }
+
+    #[test]
+    fn out_of_bounds_entries_are_rejected() {
+        let text = "voxel_battle_map 2\nsize 4 4 4\nvoxel 4 0 0 ground\n";
+        assert!(matches!(parse(text), Err(MapError::OutOfBounds { line: 3, .. })));
+        let text = "voxel_battle_map 2\nsize 4 4 4\nspawn 1 -1 1\n";
+        assert!(matches!(parse(text), Err(MapError::OutOfBounds { line: 3, .. })));
+    }
+
+    #[test]
+    fn oversize_worlds_are_rejected() {
+        let text = format!("voxel_battle_map 2\nsize 4 {} 4\n", MAX_WORLD_DIMENSION + 1);
+        assert!(matches!(parse(&text), Err(MapError::InvalidDimensions { height, .. }) if height == MAX_WORLD_DIMENSION + 1));
+        assert!(matches!(parse("voxel_battle_map 2\nsize 0 4 4\n"), Err(MapError::InvalidDimensions { .. })));
+    }
+
+    #[test]
+    fn header_and_version_are_checked() {
+        assert!(matches!(parse("not_a_map 2\nsize 4 4 4\n"), Err(MapError::Format(FormatError::MissingHeader { .. }))));
+        assert!(matches!(
+            parse("voxel_battle_map 3\nsize 4 4 4\n"),
+            Err(MapError::Format(FormatError::UnsupportedVersion { version: 3, latest: 2, .. }))
+        ));
+    }
+
+    #[test]
+    fn missing_fields_are_rejected() {
+        assert!(matches!(parse("voxel_battle_map 2\nsize 4 4\n"), Err(MapError::Format(FormatError::Parse { line: 2, .. }))));
+        let text = "voxel_battle_map 2\nsize 4 4 4\nvoxel 1 0 1\n";
+        assert!(matches!(parse(text), Err(MapError::Format(FormatError::Parse { line: 3, .. }))));
+        assert!(matches!(parse("voxel_battle_map 2\nvoxel 1 0 1 ground\n"), Err(MapError::MissingSize)));
+    }
+}
@@ -0,0 +1,267 @@
+use crate::state::{BlockRegistry, VoxelType, World};
+use crate::logic::{chunk_dimensions, get_voxel};
+use crate::blocks::block_properties;
+use crate::config::CHUNK_SIZE;
+
+// Geometry of one chunk on the CPU, uploaded to the GPU by the renderer
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MeshData {
+    pub vertices: Vec<f32>, // x, y, z per vertex
+    pub normals: Vec<f32>, // x, y, z per vertex
+    pub colors: Vec<u8>, // r, g, b, a per vertex
+    pub indices: Vec<u16>, // Two triangles per quad
+}
+
+impl MeshData {
+    pub fn quad_count(&self) -> usize {
+        self.vertices.len() / 12
+    }
+}
+
+// Rectangle of equal faces within one slice, in cells along the slice's u and v axes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FaceRect {
+    pub voxel_type: VoxelType,
+    pub u: i32,
+    pub v: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+// See-through blocks are kept apart so they can be drawn after everything opaque.
+// Faces that don't fit the u16 indices of one mesh continue in the next one.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChunkMesh {
+    pub opaque: Vec<MeshData>,
+    pub translucent: Vec<MeshData>,
+}
+
+// Build the visible faces of a chunk, merging neighboring faces of the same block into larger quads.
+// `chunk` is the chunk coordinate, chunk (1, 0, 0) starts at voxel (CHUNK_SIZE, 0, 0).
+pub fn mesh_chunk(world: &World, blocks: &BlockRegistry, chunk: (i32, i32, i32)) -> ChunkMesh {
+    let mut mesh = ChunkMesh::default();
+    let (chunks_x, chunks_y, chunks_z) = chunk_dimensions(world);
+    if !(0..chunks_x).contains(&chunk.0) || !(0..chunks_y).contains(&chunk.1) || !(0..chunks_z).contains(&chunk.2) {
+        return mesh;
+    }
+
+    let origin = [chunk.0 * CHUNK_SIZE, chunk.1 * CHUNK_SIZE, chunk.2 * CHUNK_SIZE];
+    let limits = [world.width, world.height, world.depth];
+    // Chunks at the far edges of the world may be partial
+    let size = [
+        CHUNK_SIZE.min(limits[0] - origin[0]),
+        CHUNK_SIZE.min(limits[1] - origin[1]),
+        CHUNK_SIZE.min(limits[2] - origin[2]),
+    ];
+
+    // Sweep each axis in both directions, one slice of faces at a time
+    for axis in 0..3 {
+        let u = (axis + 1) % 3;
+        let v = (axis + 2) % 3;
+        for direction in [-1, 1] {
+            for slice in 0..size[axis] {
+                let mut mask = vec![None; (size[u] * size[v]) as usize];
+                for j in 0..size[v] {
+                    for i in 0..size[u] {
+                        let mut cell = [0; 3];
+                        cell[axis] = origin[axis] + slice;
+                        cell[u] = origin[u] + i;
+                        cell[v] = origin[v] + j;
+                        let mut neighbor = cell;
+                        neighbor[axis] += direction;
+
+                        let voxel_type = get_voxel(world, cell[0], cell[1], cell[2]);
+                        let neighbor_type = get_voxel(world, neighbor[0], neighbor[1], neighbor[2]);
+                        if is_face_visible(blocks, voxel_type, neighbor_type) {
+                            mask[(j * size[u] + i) as usize] = Some(voxel_type);
+                        }
+                    }
+                }
+
+                // Voxel (x, y, z) spans x - 0.5 to x + 0.5, the faces lie on the side facing `direction`
+                let plane = origin[axis] as f32 + slice as f32 + direction as f32 * 0.5;
+                for rect in merge_faces(&mut mask, size[u], size[v]) {
+                    let start_u = (origin[u] + rect.u) as f32 - 0.5;
+                    let start_v = (origin[v] + rect.v) as f32 - 0.5;
+                    let end_u = start_u + rect.width as f32;
+                    let end_v = start_v + rect.height as f32;
+                    let corners = [(start_u, start_v), (end_u, start_v), (end_u, end_v), (start_u, end_v)].map(|(corner_u, corner_v)| {
+                        let mut position = [0.0; 3];
+                        position[axis] = plane * world.voxel_size;
+                        position[u] = corner_u * world.voxel_size;
+                        position[v] = corner_v * world.voxel_size;
+                        position
+                    });
+                    let mut normal = [0.0; 3];
+                    normal[axis] = direction as f32;
+
+                    let color = block_properties(blocks, rect.voxel_type).color;
+                    let target = if color.a < 255 { &mut mesh.translucent } else { &mut mesh.opaque };
+                    push_quad(target, corners, normal, [color.r, color.g, color.b, color.a], direction < 0);
+                }
+            }
+        }
+    }
+    mesh
+}
+
+// A face is drawn unless the neighbor hides it, see-through neighbors only hide faces of their own block
+pub fn is_face_visible(blocks: &BlockRegistry, voxel_type: VoxelType, neighbor_type: VoxelType) -> bool {
+    if voxel_type == VoxelType::EMPTY || block_properties(blocks, voxel_type).color.a == 0 {
+        return false;
+    }
+    if neighbor_type == VoxelType::EMPTY {
+        return true;
+    }
+    block_properties(blocks, neighbor_type).color.a < 255 && neighbor_type != voxel_type
+}
+
+// Greedily grow rectangles of equal faces in a slice mask, first along u then along v.
+// The mask is cleared while merging.
+pub fn merge_faces(mask: &mut [Option<VoxelType>], size_u: i32, size_v: i32) -> Vec<FaceRect> {
+    let at = |i: i32, j: i32| (j * size_u + i) as usize;
+    let mut rects = Vec::new();
+    for j in 0..size_v {
+        let mut i = 0;
+        while i < size_u {
+            let Some(voxel_type) = mask[at(i, j)] else {
+                i += 1;
+                continue;
+            };
+
+            let mut width = 1;
+            while i + width < size_u && mask[at(i + width, j)] == Some(voxel_type) {
+                width += 1;
+            }
+            let mut height = 1;
+            while j + height < size_v && (i..i + width).all(|k| mask[at(k, j + height)] == Some(voxel_type)) {
+                height += 1;
+            }
+            for row in j..j + height {
+                for column in i..i + width {
+                    mask[at(column, row)] = None;
+                }
+            }
+
+            rects.push(FaceRect { voxel_type, u: i, v: j, width, height });
+            i += width;
+        }
+    }
+    rects
+}
+
+// Corners go counter-clockwise seen from the side the face looks at, `flip` reverses them for faces
+// swept along the negative direction of an axis
+fn push_quad(meshes: &mut Vec<MeshData>, corners: [[f32; 3]; 4], normal: [f32; 3], color: [u8; 4], flip: bool) {
+    // A chunk of see-through checkerboard has more vertices than u16 indices reach
+    if meshes.last().is_none_or(|mesh| mesh.vertices.len() / 3 + 4 > u16::MAX as usize + 1) {
+        meshes.push(MeshData::default());
+    }
+    let mesh = meshes.last_mut().unwrap();
+    let first = mesh.vertices.len() / 3;
+    debug_assert!(first + 3 <= u16::MAX as usize);
+    let first = first as u16;
+    for corner in corners {
+        mesh.vertices.extend(corner);
+        mesh.normals.extend(normal);
+        mesh.colors.extend(color);
+    }
+    let order: [u16; 6] = if flip { [0, 2, 1, 0, 3, 2] } else { [0, 1, 2, 0, 2, 3] };
+    mesh.indices.extend(order.iter().map(|index| first + index));
+}
+
+#[cfg(test)]
+mod tests {
+    use raylib::prelude::Color;
+    use super::*;
+    use crate::logic::{create_world, set_voxel};
+
+    const GLASS: VoxelType = VoxelType(3);
+
+    // Built-in ground and wall plus a see-through glass block
+    fn test_blocks() -> BlockRegistry {
+        let mut blocks = BlockRegistry::default();
+        let mut glass = blocks.blocks[VoxelType::WALL.0 as usize].clone();
+        glass.id = GLASS;
+        glass.name = "glass".to_string();
+        glass.color = Color::new(200, 220, 255, 100);
+        blocks.blocks.push(glass);
+        blocks
+    }
+
+    fn mesh_voxels(voxels: &[((i32, i32, i32), VoxelType)]) -> ChunkMesh {
+        let mut world = create_world(8, 8, 8, 1.0);
+        for ((x, y, z), voxel_type) in voxels {
+            set_voxel(&mut world, *x, *y, *z, *voxel_type);
+        }
+        mesh_chunk(&world, &test_blocks(), (0, 0, 0))
+    }
+
+    #[test]
+    fn single_voxel_has_six_quads() {
+        let mesh = mesh_voxels(&[((2, 2, 2), VoxelType::WALL)]);
+        assert_eq!(quad_count(&mesh.opaque), 6);
+        assert_eq!(quad_count(&mesh.translucent), 0);
+    }
+
+    #[test]
+    fn slab_merges_into_six_quads() {
+        let mesh = mesh_voxels(&[((2, 2, 2), VoxelType::WALL), ((3, 2, 2), VoxelType::WALL)]);
+        assert_eq!(quad_count(&mesh.opaque), 6);
+    }
+
+    #[test]
+    fn faces_between_solid_voxels_are_culled() {
+        let blocks = test_blocks();
+        assert!(!is_face_visible(&blocks, VoxelType::WALL, VoxelType::WALL));
+        assert!(!is_face_visible(&blocks, VoxelType::WALL, VoxelType::GROUND));
+
+        // Different blocks don't merge, each keeps its five outer faces
+        let mesh = mesh_voxels(&[((2, 2, 2), VoxelType::WALL), ((3, 2, 2), VoxelType::GROUND)]);
+        assert_eq!(quad_count(&mesh.opaque), 10);
+    }
+
+    #[test]
+    fn faces_next_to_translucent_neighbor_are_kept() {
+        let blocks = test_blocks();
+        assert!(is_face_visible(&blocks, VoxelType::WALL, GLASS));
+        assert!(!is_face_visible(&blocks, GLASS, VoxelType::WALL));
+        assert!(!is_face_visible(&blocks, GLASS, GLASS));
+
+        let mesh = mesh_voxels(&[((2, 2, 2), VoxelType::WALL), ((3, 2, 2), GLASS)]);
+        assert_eq!(quad_count(&mesh.opaque), 6);
+        assert_eq!(quad_count(&mesh.translucent), 5);
+    }
+
+    #[test]
+    fn full_chunk_splits_before_indices_overflow() {
+        let size = CHUNK_SIZE;
+        let mut world = create_world(size, size, size, 1.0);
+        // Alternating see-through blocks keep every face and merge none of them
+        let mut blocks = test_blocks();
+        let mut ice = blocks.blocks[GLASS.0 as usize].clone();
+        ice.id = VoxelType(4);
+        ice.name = "ice".to_string();
+        blocks.blocks.push(ice);
+        for y in 0..size {
+            for z in 0..size {
+                for x in 0..size {
+                    set_voxel(&mut world, x, y, z, if (x + y + z) % 2 == 0 { GLASS } else { VoxelType(4) });
+                }
+            }
+        }
+
+        let mesh = mesh_chunk(&world, &blocks, (0, 0, 0));
+        assert_eq!(quad_count(&mesh.translucent), (size * size * size * 6) as usize);
+        assert!(mesh.translucent.len() > 1);
+        for part in &mesh.translucent {
+            let vertex_count = part.vertices.len() / 3;
+            assert!(vertex_count <= u16::MAX as usize + 1);
+            assert!(part.indices.iter().all(|index| (*index as usize) < vertex_count));
+        }
+    }
+
+    fn quad_count(meshes: &[MeshData]) -> usize {
+        meshes.iter().map(MeshData::quad_count).sum()
+    }
+}
@@ -0,0 +1,348 @@
+use raylib::prelude::*;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use crate::state::{BlockRegistry, NavGrid, World};
+use crate::logic::{chunk_position, get_voxel};
+use crate::blocks::block_properties;
+use crate::config::{CHUNK_SIZE, NAV_MAX_STEP, NAV_SEARCH_LIMIT};
+
+// Cell holding the feet of a standing player, the block below it is the ground
+pub type NavNode = (i32, i32, i32);
+
+// Straight moves first so ties in path cost prefer them
+const DIRECTIONS: [(i32, i32); 8] = [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+// Entry of the open list, ordered so the binary heap pops the lowest estimate first
+#[derive(Debug, PartialEq)]
+struct OpenNode {
+    estimate: f32,
+    node: NavNode,
+}
+
+impl Eq for OpenNode {}
+
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate).then_with(|| other.node.cmp(&self.node))
+    }
+}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Bring the grid up to date with the world, only chunks whose version changed are recomputed.
+// A resized world or a new agent size rebuilds everything.
+// A cell depends on every voxel under the agent's footprint, so cells that far around a changed chunk are
+// recomputed as well. Chunks across an edge or corner may not have a new version of their own.
+pub fn update_navigation(nav: &mut NavGrid, world: &World, blocks: &BlockRegistry) {
+    let resized = nav.width != world.width || nav.height != world.height || nav.depth != world.depth
+        || nav.chunk_versions.len() != world.chunk_versions.len();
+    if resized {
+        nav.width = world.width;
+        nav.height = world.height;
+        nav.depth = world.depth;
+        nav.walkable = vec![false; world.voxels.len()];
+        nav.chunk_versions = vec![0; world.chunk_versions.len()];
+    }
+
+    let (reach_x, rows, reach_z) = footprint(world, nav.agent_size);
+    let cells = |chunk: i32, below: i32, above: i32, size: i32| {
+        (chunk * CHUNK_SIZE - below).max(0)..((chunk + 1) * CHUNK_SIZE + above).min(size)
+    };
+    for (index, version) in world.chunk_versions.iter().enumerate() {
+        if nav.chunk_versions[index] == *version {
+            continue;
+        }
+        nav.chunk_versions[index] = *version;
+
+        // Cells stand on the voxel below them and reach `rows` voxels up
+        let (chunk_x, chunk_y, chunk_z) = chunk_position(world, index);
+        for y in cells(chunk_y, rows - 1, 1, world.height) {
+            for z in cells(chunk_z, reach_z, reach_z, world.depth) {
+                for x in cells(chunk_x, reach_x, reach_x, world.width) {
+                    let cell = cell_index(nav, (x, y, z));
+                    nav.walkable[cell] = is_standable(world, blocks, nav.agent_size, (x, y, z));
+                }
+            }
+        }
+    }
+}
+
+// Rebuild the whole grid for a player of another size
+pub fn set_agent_size(nav: &mut NavGrid, size: Vector3) {
+    nav.agent_size = size;
+    nav.chunk_versions.clear();
+}
+
+// Whether a player of `size` fits with its feet in the cell, on top of a walkable block.
+// Players wider than a voxel need the cells around it clear as well.
+pub fn is_standable(world: &World, blocks: &BlockRegistry, size: Vector3, (x, y, z): NavNode) -> bool {
+    if !(0..world.width).contains(&x) || !(0..world.height).contains(&y) || !(0..world.depth).contains(&z) {
+        return false;
+    }
+    let ground = block_properties(blocks, get_voxel(world, x, y - 1, z));
+    if !ground.solid || !ground.walkable {
+        return false;
+    }
+
+    let (reach_x, rows, reach_z) = footprint(world, size);
+    for dy in 0..rows {
+        for dz in -reach_z..=reach_z {
+            for dx in -reach_x..=reach_x {
+                if block_properties(blocks, get_voxel(world, x + dx, y + dy, z + dz)).solid {
+                    return false;
+                }
+            }
+        }
+    }
+    true
+}
+
+// Cells a player reaches beyond its center cell along x and z, and the rows its body takes up
+fn footprint(world: &World, size: Vector3) -> (i32, i32, i32) {
+    let reach = |half: f32| (half / world.voxel_size - 0.5).ceil().max(0.0) as i32;
+    let rows = (size.y / world.voxel_size).ceil().max(1.0) as i32;
+    (reach(size.x / 2.0), rows, reach(size.z / 2.0))
+}
+
+pub fn is_walkable(nav: &NavGrid, (x, y, z): NavNode) -> bool {
+    (0..nav.width).contains(&x) && (0..nav.height).contains(&y) && (0..nav.depth).contains(&z)
+        && nav.walkable[cell_index(nav, (x, y, z))]
+}
+
+// Cell the feet of a player centered at `position` are in
+pub fn standing_cell(world: &World, size: Vector3, position: Vector3) -> NavNode {
+    let feet = position.y - size.y / 2.0;
+    // Feet resting exactly on a voxel's top belong to the cell above it
+    let row = (feet / world.voxel_size + 0.5 + 0.001).floor() as i32;
+    ((position.x / world.voxel_size).round() as i32, row, (position.z / world.voxel_size).round() as i32)
+}
+
+// Node of a player at `position`, looking a little further down while it is in the air
+pub fn node_at(nav: &NavGrid, world: &World, position: Vector3) -> Option<NavNode> {
+    let (x, y, z) = standing_cell(world, nav.agent_size, position);
+    (0..=NAV_MAX_STEP).map(|drop| (x, y - drop, z)).find(|node| is_walkable(nav, *node))
+}
+
+// Center of a player standing on the node
+pub fn node_position(nav: &NavGrid, world: &World, (x, y, z): NavNode) -> Vector3 {
+    let voxel_size = world.voxel_size;
+    Vector3::new(x as f32 * voxel_size, (y as f32 - 0.5) * voxel_size + nav.agent_size.y / 2.0, z as f32 * voxel_size)
+}
+
+// Nodes one move away with the cost of the move: the eight surrounding columns at the same height or up to
+// NAV_MAX_STEP higher or lower. Diagonal moves need both sides free so paths don't cut wall corners.
+pub fn neighbors(nav: &NavGrid, node: NavNode) -> Vec<(NavNode, f32)> {
+    let mut result = Vec::with_capacity(DIRECTIONS.len());
+    for (dx, dz) in DIRECTIONS {
+        let diagonal = dx != 0 && dz != 0;
+        if diagonal && (step(nav, node, dx, 0).is_none() || step(nav, node, 0, dz).is_none()) {
+            continue;
+        }
+        if let Some(next) = step(nav, node, dx, dz) {
+            result.push((next, if diagonal { std::f32::consts::SQRT_2 } else { 1.0 }));
+        }
+    }
+    result
+}
+
+// Walkable node in the neighboring column, level ground first, then climbing, then dropping
+fn step(nav: &NavGrid, (x, y, z): NavNode, dx: i32, dz: i32) -> Option<NavNode> {
+    let climbs = 1..=NAV_MAX_STEP;
+    let drops = (1..=NAV_MAX_STEP).map(|dy| -dy);
+    [0].into_iter().chain(climbs).chain(drops)
+        .map(|dy| (x + dx, y + dy, z + dz))
+        .find(|next| is_walkable(nav, *next))
+}
+
+// Cheapest path from `start` to `goal` including both, None when there is none or the search ran too long
+pub fn find_path(nav: &NavGrid, start: NavNode, goal: NavNode) -> Option<Vec<NavNode>> {
+    if !is_walkable(nav, start) || !is_walkable(nav, goal) {
+        return None;
+    }
+
+    let mut open = BinaryHeap::new();
+    open.push(OpenNode { estimate: heuristic(start, goal), node: start });
+    let mut costs: HashMap<NavNode, f32> = HashMap::from([(start, 0.0)]);
+    let mut came_from: HashMap<NavNode, NavNode> = HashMap::new();
+    let mut expanded = 0;
+
+    while let Some(OpenNode { node, .. }) = open.pop() {
+        if node == goal {
+            let mut path = vec![goal];
+            while let Some(previous) = came_from.get(path.last().unwrap()) {
+                path.push(*previous);
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        expanded += 1;
+        if expanded > NAV_SEARCH_LIMIT {
+            return None;
+        }
+
+        let cost = costs[&node];
+        for (next, move_cost) in neighbors(nav, node) {
+            let next_cost = cost + move_cost;
+            if costs.get(&next).is_some_and(|known| *known <= next_cost) {
+                continue;
+            }
+            costs.insert(next, next_cost);
+            came_from.insert(next, node);
+            open.push(OpenNode { estimate: next_cost + heuristic(next, goal), node: next });
+        }
+    }
+    None
+}
+
+// Cost of the cheapest move sequence on open ground, never more than the real path cost
+fn heuristic(from: NavNode, to: NavNode) -> f32 {
+    let dx = (from.0 - to.0).abs() as f32;
+    let dz = (from.2 - to.2).abs() as f32;
+    dx.max(dz) + (std::f32::consts::SQRT_2 - 1.0) * dx.min(dz)
+}
+
+// Flow field: path cost from every node that can reach one of the goals to the nearest of them.
+// Stepping to the neighbor with the lowest cost leads to a goal, moves work both ways so costs from the
+// goals are costs to them.
+pub fn distance_field(nav: &NavGrid, goals: &[NavNode]) -> HashMap<NavNode, f32> {
+    let mut costs: HashMap<NavNode, f32> = HashMap::new();
+    let mut open = BinaryHeap::new();
+    for goal in goals.iter().filter(|goal| is_walkable(nav, **goal)) {
+        costs.insert(*goal, 0.0);
+        open.push(OpenNode { estimate: 0.0, node: *goal });
+    }
+
+    while let Some(OpenNode { estimate: cost, node }) = open.pop() {
+        if costs.get(&node).is_some_and(|known| *known < cost) {
+            continue;
+        }
+        for (next, move_cost) in neighbors(nav, node) {
+            let next_cost = cost + move_cost;
+            if costs.get(&next).is_some_and(|known| *known <= next_cost) {
+                continue;
+            }
+            costs.insert(next, next_cost);
+            open.push(OpenNode { estimate: next_cost, node: next });
+        }
+    }
+    costs
+}
+
+// Groups of nodes connected by moves, largest first
+pub fn walkable_regions(nav: &NavGrid) -> Vec<Vec<NavNode>> {
+    let mut visited: HashSet<NavNode> = HashSet::new();
+    let mut regions = Vec::new();
+    for y in 0..nav.height {
+        for z in 0..nav.depth {
+            for x in 0..nav.width {
+                let seed = (x, y, z);
+                if !nav.walkable[cell_index(nav, seed)] || !visited.insert(seed) {
+                    continue;
+                }
+
+                let mut region = vec![seed];
+                let mut queue = VecDeque::from([seed]);
+                while let Some(node) = queue.pop_front() {
+                    for (next, _) in neighbors(nav, node) {
+                        if visited.insert(next) {
+                            region.push(next);
+                            queue.push_back(next);
+                        }
+                    }
+                }
+                regions.push(region);
+            }
+        }
+    }
+    regions.sort_by_key(|region| std::cmp::Reverse(region.len()));
+    regions
+}
+
+fn cell_index(nav: &NavGrid, (x, y, z): NavNode) -> usize {
+    ((y * nav.depth + z) * nav.width + x) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::{create_world, set_voxel};
+    use crate::state::VoxelType;
+
+    const PLAYER_SIZE: Vector3 = Vector3 { x: 0.8, y: 1.8, z: 0.8 };
+
+    // Flat ground one voxel thick across the whole world
+    fn flat_world(width: i32, height: i32, depth: i32) -> World {
+        let mut world = create_world(width, height, depth, 1.0);
+        for z in 0..depth {
+            for x in 0..width {
+                set_voxel(&mut world, x, 0, z, VoxelType::GROUND);
+            }
+        }
+        world
+    }
+
+    // Wall across the world at `x`, too high to climb
+    fn add_wall(world: &mut World, x: i32) {
+        for z in 0..world.depth {
+            for y in 1..world.height {
+                set_voxel(world, x, y, z, VoxelType::WALL);
+            }
+        }
+    }
+
+    fn build_nav(world: &World, size: Vector3) -> NavGrid {
+        let mut nav = NavGrid::default();
+        set_agent_size(&mut nav, size);
+        update_navigation(&mut nav, world, &BlockRegistry::default());
+        nav
+    }
+
+    #[test]
+    fn straight_path_on_open_ground() {
+        let world = flat_world(10, 4, 3);
+        let nav = build_nav(&world, PLAYER_SIZE);
+        let path = find_path(&nav, (0, 1, 1), (9, 1, 1)).expect("path across open ground");
+        let expected: Vec<NavNode> = (0..10).map(|x| (x, 1, 1)).collect();
+        assert_eq!(path, expected);
+    }
+
+    #[test]
+    fn blocked_path_is_none() {
+        let mut world = flat_world(10, 4, 3);
+        add_wall(&mut world, 5);
+        let nav = build_nav(&world, PLAYER_SIZE);
+        assert_eq!(find_path(&nav, (0, 1, 1), (9, 1, 1)), None);
+    }
+
+    #[test]
+    fn wall_splits_walkable_regions() {
+        let mut world = flat_world(10, 4, 3);
+        add_wall(&mut world, 4);
+        let nav = build_nav(&world, PLAYER_SIZE);
+        let regions = walkable_regions(&nav);
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions[0].len(), 5 * 3);
+        assert_eq!(regions[1].len(), 4 * 3);
+        assert!(regions[0].iter().all(|(x, _, _)| *x > 4));
+    }
+
+    #[test]
+    fn edit_across_chunk_corner_updates_wide_agents() {
+        let size = Vector3::new(2.6, 1.8, 2.6);
+        let mut world = flat_world(CHUNK_SIZE * 2, 4, CHUNK_SIZE * 2);
+        let mut nav = build_nav(&world, size);
+        let corner = (CHUNK_SIZE - 1, 1, CHUNK_SIZE - 1);
+        assert!(is_walkable(&nav, corner));
+
+        // The wall sits in the chunk diagonally across from the cell
+        set_voxel(&mut world, CHUNK_SIZE, 1, CHUNK_SIZE, VoxelType::WALL);
+        update_navigation(&mut nav, &world, &BlockRegistry::default());
+        assert!(!is_walkable(&nav, corner));
+        assert_eq!(nav.walkable, build_nav(&world, size).walkable);
+    }
+}
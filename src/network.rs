@@ -0,0 +1,1265 @@
+use raylib::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use crate::state::{
+    BlockProperties, BlockRegistry, ClientConnection, ClientState, GameState, InputFrame, LightSource, LightType, MatchPhase, MatchState, PlayerInput, PlayerInputFrame,
+    PlayerState, ServerState, VoxelType, World,
+};
+use crate::logic::{
+    add_player, remove_player, update, predict_player, create_world, replace_world, chunk_position, get_voxel, set_voxel,
+    is_valid_position,
+};
+use crate::bots::update_bots;
+use crate::config::{
+    CONNECTION_TIMEOUT, CONNECT_RETRY_INTERVAL, DEFAULT_SERVER_PORT, INPUT_REDUNDANCY, MAX_BUFFERED_INPUTS,
+    MAX_LIGHTS, MAX_WORLD_DIMENSION, PLAYER_COLORS, TICK_RATE, CHUNK_SIZE,
+};
+
+pub const PACKET_MAGIC: [u8; 2] = *b"VB";
+pub const PROTOCOL_VERSION: u8 = 9;
+const MAX_PACKET_SIZE: usize = 65507;
+const WORLD_PART_SIZE: usize = 1200; // World bytes per packet, small enough to pass common path MTUs unfragmented
+const MAX_WORLD_PARTS: usize = 4096; // Largest world a client downloads, about 4.9 MB after run-length encoding
+const MAX_DELTA_SIZE: usize = WORLD_PART_SIZE; // Edits that don't fit in one packet go out as a full world
+const DELTA_VOXEL_SIZE: usize = 7; // Three u16 coordinates and the block ID
+
+const MESSAGE_CONNECT: u8 = 1;
+const MESSAGE_INPUTS: u8 = 2;
+const MESSAGE_REQUEST_WORLD: u8 = 3;
+const MESSAGE_DISCONNECT: u8 = 4;
+const MESSAGE_WELCOME: u8 = 10;
+const MESSAGE_REJECTED: u8 = 11;
+const MESSAGE_WORLD_PART: u8 = 12;
+const MESSAGE_SNAPSHOT: u8 = 13;
+const MESSAGE_WORLD_DELTA: u8 = 14;
+
+// Cell of an edited voxel and what it holds now
+pub type VoxelChange = ((i32, i32, i32), VoxelType);
+
+#[derive(Debug)]
+pub enum NetworkError {
+    Io(std::io::Error),
+    Malformed(&'static str),
+    UnsupportedVersion(u8),
+    Rejected(String),
+    TimedOut,
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkError::Io(error) => write!(f, "i/o error: {}", error),
+            NetworkError::Malformed(reason) => write!(f, "malformed packet: {}", reason),
+            NetworkError::UnsupportedVersion(version) => write!(
+                f, "protocol version {} is not supported (expected {})", version, PROTOCOL_VERSION
+            ),
+            NetworkError::Rejected(reason) => write!(f, "rejected by server: {}", reason),
+            NetworkError::TimedOut => write!(f, "connection timed out"),
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {}
+
+impl From<std::io::Error> for NetworkError {
+    fn from(error: std::io::Error) -> Self {
+        NetworkError::Io(error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientMessage {
+    Connect,
+    Inputs(Vec<(u32, PlayerInputFrame)>), // Most recent inputs with their sequence numbers, oldest first
+    RequestWorld,
+    Disconnect,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerMessage {
+    Welcome { player_id: i32 },
+    Rejected(String),
+    WorldPart { version: u32, part: u16, part_count: u16, data: Vec<u8> }, // Slice of an encoded world, see encode_world_payload
+    // Voxels edited since `base_version` with the current spawn points and lights, applied only on top of that version
+    WorldDelta {
+        base_version: u32,
+        version: u32,
+        voxels: Vec<VoxelChange>,
+        spawn_points: Vec<Vector3>,
+        lights: Vec<LightSource>,
+    },
+    Snapshot { tick: u32, world_version: u32, last_input: u32, match_state: MatchState, players: Vec<(i32, PlayerState)> },
+}
+
+// Append the default port when the address has none
+pub fn with_default_port(address: &str) -> String {
+    if address.contains(':') {
+        address.to_string()
+    } else {
+        format!("{}:{}", address, DEFAULT_SERVER_PORT)
+    }
+}
+
+pub fn open_server_socket(address: &str) -> Result<UdpSocket, NetworkError> {
+    let socket = UdpSocket::bind(address)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+pub fn open_client_socket(server_address: &str) -> Result<UdpSocket, NetworkError> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect(server_address)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+// Handle every packet waiting on the server socket
+pub fn server_receive(server: &mut ServerState, state: &mut GameState, socket: &UdpSocket) {
+    let mut buffer = vec![0u8; MAX_PACKET_SIZE];
+    loop {
+        let (size, address) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+            // A client that vanished can surface as a reset, it is dropped by the timeout
+            Err(error) if error.kind() == ErrorKind::ConnectionReset => continue,
+            Err(error) => {
+                eprintln!("Failed to receive packet: {}", error);
+                break;
+            }
+        };
+
+        let message = match decode_client_message(&buffer[..size]) {
+            Ok(message) => message,
+            Err(error) => {
+                eprintln!("Ignoring packet from {}: {}", address, error);
+                continue;
+            }
+        };
+
+        if let Some(client) = server.clients.get_mut(&address) {
+            client.last_seen = server.time;
+        }
+
+        match message {
+            ClientMessage::Connect => accept_client(server, state, socket, address),
+            ClientMessage::Inputs(inputs) => {
+                if let Some(client) = server.clients.get_mut(&address) {
+                    for (sequence, input) in inputs {
+                        if sequence > client.last_received_input {
+                            client.pending_inputs.push_back((sequence, input));
+                            client.last_received_input = sequence;
+                        }
+                    }
+                    // Drop the oldest inputs rather than let the client fall further behind
+                    while client.pending_inputs.len() > MAX_BUFFERED_INPUTS {
+                        client.pending_inputs.pop_front();
+                    }
+                }
+            }
+            ClientMessage::RequestWorld => {
+                if server.clients.contains_key(&address) {
+                    send_world(server, state, socket, address);
+                }
+            }
+            ClientMessage::Disconnect => {
+                if let Some(client) = server.clients.remove(&address) {
+                    remove_player(state, client.player_id);
+                    println!("Client {} (player {}) disconnected", address, client.player_id);
+                }
+            }
+        }
+    }
+}
+
+fn accept_client(server: &mut ServerState, state: &mut GameState, socket: &UdpSocket, address: SocketAddr) {
+    let player_id = match server.clients.get(&address) {
+        // Repeated connect because the welcome got lost, answer again
+        Some(client) => client.player_id,
+        None => {
+            let taken: Vec<i32> = server.clients.values().map(|client| client.player_id).collect();
+            let free = (0..PLAYER_COLORS.len() as i32).find(|id| !taken.contains(id));
+            let Some(player_id) = free.filter(|id| add_player(state, *id)) else {
+                send_message(socket, address, &encode_server_message(&ServerMessage::Rejected("server is full".to_string())));
+                return;
+            };
+
+            server.clients.insert(address, ClientConnection {
+                player_id,
+                pending_inputs: Default::default(),
+                last_received_input: 0,
+                last_applied_input: 0,
+                held_input: PlayerInputFrame::default(),
+                last_seen: server.time,
+            });
+            println!("Client {} connected as player {}", address, player_id);
+            player_id
+        }
+    };
+
+    send_message(socket, address, &encode_server_message(&ServerMessage::Welcome { player_id }));
+    send_world(server, state, socket, address);
+}
+
+// The world is too big for one datagram, it goes out in numbered parts the client puts back together
+fn send_world(server: &ServerState, state: &GameState, socket: &UdpSocket, address: SocketAddr) {
+    let payload = encode_world_payload(&state.world, &state.lights, &state.blocks);
+    let parts: Vec<&[u8]> = payload.chunks(WORLD_PART_SIZE).collect();
+    if parts.len() > MAX_WORLD_PARTS {
+        eprintln!("World of {} bytes is too large to send to {}", payload.len(), address);
+        let reason = format!("world too large to send ({} bytes)", payload.len());
+        send_message(socket, address, &encode_server_message(&ServerMessage::Rejected(reason)));
+        return;
+    }
+    for (part, data) in parts.iter().enumerate() {
+        let message = ServerMessage::WorldPart {
+            version: server.world_version,
+            part: part as u16,
+            part_count: parts.len() as u16,
+            data: data.to_vec(),
+        };
+        send_message(socket, address, &encode_server_message(&message));
+    }
+}
+
+// Simulate one tick with the inputs of every client and broadcast the result
+pub fn server_tick(server: &mut ServerState, state: &mut GameState, socket: &UdpSocket, delta: f32) {
+    server.time += delta;
+
+    // Drop clients that went silent
+    let timed_out: Vec<SocketAddr> = server.clients.iter()
+        .filter(|(_, client)| server.time - client.last_seen > CONNECTION_TIMEOUT)
+        .map(|(address, _)| *address)
+        .collect();
+    for address in timed_out {
+        if let Some(client) = server.clients.remove(&address) {
+            remove_player(state, client.player_id);
+            println!("Client {} (player {}) timed out", address, client.player_id);
+        }
+    }
+
+    // One queued input per client and tick, a reused input must not repeat its presses
+    let mut frame = InputFrame::default();
+    for client in server.clients.values_mut() {
+        let input = match client.pending_inputs.pop_front() {
+            Some((sequence, input)) => {
+                client.last_applied_input = sequence;
+                client.held_input = input;
+                input
+            }
+            None => {
+                client.held_input.dash_pressed = false;
+                client.held_input.join_pressed = false;
+                client.held_input
+            }
+        };
+        frame.players.insert(client.player_id, input);
+    }
+
+    update_bots(state, &mut frame, delta);
+    update(state, &frame, delta);
+    server.tick += 1;
+
+    // Send the world again whenever it changed, edited voxels show up in the chunk versions.
+    // Edits go out as the changed voxels, a replaced world or an edit too big for one packet in full.
+    let world_changed = match &server.sent_world {
+        Some((world, lights, blocks)) => {
+            world.chunk_versions != state.world.chunk_versions || world.spawn_points != state.world.spawn_points
+                || *lights != state.lights || *blocks != state.blocks
+        }
+        None => true,
+    };
+    if world_changed {
+        let delta = server.sent_world.as_ref()
+            .and_then(|(world, _, blocks)| changed_voxels(world, blocks, &state.world, &state.blocks))
+            .map(|voxels| encode_server_message(&ServerMessage::WorldDelta {
+                base_version: server.world_version,
+                version: server.world_version + 1,
+                voxels,
+                spawn_points: state.world.spawn_points.clone(),
+                lights: state.lights.clone(),
+            }))
+            .filter(|bytes| bytes.len() <= MAX_DELTA_SIZE);
+        server.world_version += 1;
+        server.sent_world = Some((state.world.clone(), state.lights.clone(), state.blocks.clone()));
+        for address in server.clients.keys() {
+            match &delta {
+                Some(bytes) => send_message(socket, *address, bytes),
+                None => send_world(server, state, socket, *address),
+            }
+        }
+    }
+
+    let mut players: Vec<(i32, PlayerState)> = state.players.iter()
+        .map(|(id, player)| (*id, *player))
+        .collect();
+    players.sort_by_key(|(id, _)| *id);
+    for (address, client) in &server.clients {
+        let snapshot = ServerMessage::Snapshot {
+            tick: server.tick,
+            world_version: server.world_version,
+            last_input: client.last_applied_input,
+            match_state: state.match_state.clone(),
+            players: players.clone(),
+        };
+        send_message(socket, *address, &encode_server_message(&snapshot));
+    }
+}
+
+pub fn client_connect(client: &mut ClientState, socket: &UdpSocket) {
+    client.time_since_request = 0.0;
+    send_client_message(socket, &ClientMessage::Connect);
+}
+
+pub fn client_disconnect(socket: &UdpSocket) {
+    send_client_message(socket, &ClientMessage::Disconnect);
+}
+
+// Handle every packet from the server and retry the handshake or world download when needed
+pub fn client_receive(client: &mut ClientState, state: &mut GameState, socket: &UdpSocket, delta: f32) -> Result<(), NetworkError> {
+    client.time_since_packet += delta;
+    client.time_since_request += delta;
+
+    let mut buffer = vec![0u8; MAX_PACKET_SIZE];
+    let mut needs_world = false;
+    loop {
+        let size = match socket.recv(&mut buffer) {
+            Ok(size) => size,
+            Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+            // No server listening (yet), keep retrying until the timeout
+            Err(error) if error.kind() == ErrorKind::ConnectionRefused => break,
+            Err(error) => return Err(error.into()),
+        };
+
+        let message = match decode_server_message(&buffer[..size]) {
+            Ok(message) => message,
+            Err(error) => {
+                eprintln!("Ignoring packet from server: {}", error);
+                continue;
+            }
+        };
+        client.time_since_packet = 0.0;
+
+        match message {
+            ServerMessage::Welcome { player_id } => {
+                if client.player_id.is_none() {
+                    println!("Connected as player {}", player_id);
+                }
+                client.player_id = Some(player_id);
+                state.player_inputs.clear();
+                state.player_inputs.insert(player_id, PlayerInput {
+                    movement: Vector2::zero(),
+                    movement_speed: 5.0, // Same speed as on the server
+                });
+            }
+            ServerMessage::Rejected(reason) => return Err(NetworkError::Rejected(reason)),
+            ServerMessage::WorldPart { version, part, part_count, data } => {
+                receive_world_part(client, state, version, part, part_count, data);
+            }
+            ServerMessage::WorldDelta { base_version, version, voxels, spawn_points, lights } => {
+                apply_world_delta(client, state, base_version, version, voxels, spawn_points, lights);
+            }
+            ServerMessage::Snapshot { tick, world_version, last_input, match_state, players } => {
+                // Ignore snapshots that arrive out of order
+                if tick <= client.last_snapshot_tick {
+                    continue;
+                }
+                client.last_snapshot_tick = tick;
+                needs_world = client.world_version != Some(world_version);
+                state.match_state = match_state;
+                apply_snapshot(client, state, last_input, players);
+            }
+        }
+    }
+
+    if client.time_since_packet > CONNECTION_TIMEOUT {
+        return Err(NetworkError::TimedOut);
+    }
+    if client.time_since_request >= CONNECT_RETRY_INTERVAL {
+        if client.player_id.is_none() {
+            client_connect(client, socket);
+        } else if needs_world {
+            client.time_since_request = 0.0;
+            send_client_message(socket, &ClientMessage::RequestWorld);
+        }
+    }
+    Ok(())
+}
+
+// Collect the parts of a world version, parts kept from earlier attempts count so retries after packet loss
+// only have to fill the gaps
+fn receive_world_part(client: &mut ClientState, state: &mut GameState, version: u32, part: u16, part_count: u16, data: Vec<u8>) {
+    if client.world_version.is_some_and(|current| current >= version) {
+        return;
+    }
+    match &client.world_download {
+        Some((download_version, _)) if *download_version > version => return,
+        Some((download_version, parts)) if *download_version == version && parts.len() == part_count as usize => {}
+        _ => client.world_download = Some((version, vec![None; part_count as usize])),
+    }
+    let Some((_, parts)) = client.world_download.as_mut() else {
+        return;
+    };
+    parts[part as usize] = Some(data);
+    if parts.iter().any(Option::is_none) {
+        return;
+    }
+
+    let payload: Vec<u8> = parts.iter().flatten().flatten().copied().collect();
+    client.world_download = None;
+    match decode_world_payload(&payload) {
+        Ok((world, lights, blocks)) => {
+            replace_world(&mut state.world, world);
+            state.lights = lights;
+            state.blocks = blocks;
+            state.arena = None;
+            client.world_version = Some(version);
+        }
+        Err(error) => eprintln!("Ignoring world from server: {}", error),
+    }
+}
+
+// Voxels that differ between two versions of the same world, None when the world was replaced by another one
+fn changed_voxels(
+    previous: &World, previous_blocks: &BlockRegistry, world: &World, blocks: &BlockRegistry,
+) -> Option<Vec<VoxelChange>> {
+    let same_size = (previous.width, previous.height, previous.depth) == (world.width, world.height, world.depth);
+    if !same_size || previous.voxel_size != world.voxel_size || previous_blocks != blocks {
+        return None;
+    }
+
+    // Only chunks with a new version can hold changes
+    let mut voxels = Vec::new();
+    for (index, version) in world.chunk_versions.iter().enumerate() {
+        if previous.chunk_versions[index] == *version {
+            continue;
+        }
+        let (chunk_x, chunk_y, chunk_z) = chunk_position(world, index);
+        for y in chunk_y * CHUNK_SIZE..((chunk_y + 1) * CHUNK_SIZE).min(world.height) {
+            for z in chunk_z * CHUNK_SIZE..((chunk_z + 1) * CHUNK_SIZE).min(world.depth) {
+                for x in chunk_x * CHUNK_SIZE..((chunk_x + 1) * CHUNK_SIZE).min(world.width) {
+                    let voxel_type = get_voxel(world, x, y, z);
+                    if get_voxel(previous, x, y, z) != voxel_type {
+                        voxels.push(((x, y, z), voxel_type));
+                    }
+                }
+            }
+        }
+        if voxels.len() * DELTA_VOXEL_SIZE > MAX_DELTA_SIZE {
+            return None;
+        }
+    }
+    Some(voxels)
+}
+
+// Apply edits made on top of the world version the client has. Clients on another version ignore them
+// and download the full world once a snapshot shows they are behind.
+fn apply_world_delta(
+    client: &mut ClientState,
+    state: &mut GameState,
+    base_version: u32,
+    version: u32,
+    voxels: Vec<VoxelChange>,
+    spawn_points: Vec<Vector3>,
+    lights: Vec<LightSource>,
+) {
+    if client.world_version != Some(base_version) {
+        return;
+    }
+    let valid = voxels.iter().all(|((x, y, z), voxel_type)| {
+        is_valid_position(&state.world, *x, *y, *z) && (voxel_type.0 as usize) < state.blocks.blocks.len()
+    });
+    if !valid {
+        eprintln!("Ignoring world delta from server: voxel outside the world or unknown block");
+        return;
+    }
+    for ((x, y, z), voxel_type) in voxels {
+        set_voxel(&mut state.world, x, y, z, voxel_type);
+    }
+    state.world.spawn_points = spawn_points;
+    state.lights = lights;
+    client.world_version = Some(version);
+}
+
+// Take the server state and replay the local inputs the server has not simulated yet
+fn apply_snapshot(client: &mut ClientState, state: &mut GameState, last_input: u32, players: Vec<(i32, PlayerState)>) {
+    let local_id = client.player_id;
+    let mut updated = HashMap::new();
+    for (id, mut player) in players {
+        // Interpolate from where the player is drawn right now
+        player.previous_position = match state.players.get(&id) {
+            Some(current) if Some(id) == local_id => current.previous_position,
+            Some(current) => current.position,
+            None => player.position,
+        };
+        updated.insert(id, player);
+    }
+    state.players = updated;
+
+    client.input_history.retain(|(sequence, _)| *sequence > last_input);
+    if let Some(id) = local_id {
+        let delta = 1.0 / TICK_RATE as f32;
+        for (_, input) in &client.input_history {
+            predict_player(state, id, input, delta);
+        }
+    }
+}
+
+// Send the input of this tick, together with a few earlier ones in case packets were lost
+pub fn client_send_input(client: &mut ClientState, socket: &UdpSocket, input: PlayerInputFrame) {
+    if client.player_id.is_none() {
+        return;
+    }
+
+    client.next_input_sequence += 1;
+    client.input_history.push_back((client.next_input_sequence, input));
+
+    let skip = client.input_history.len().saturating_sub(INPUT_REDUNDANCY);
+    let inputs: Vec<(u32, PlayerInputFrame)> = client.input_history.iter().skip(skip).copied().collect();
+    send_client_message(socket, &ClientMessage::Inputs(inputs));
+}
+
+fn send_client_message(socket: &UdpSocket, message: &ClientMessage) {
+    if let Err(error) = socket.send(&encode_client_message(message)) {
+        // Refused means the server is not up yet, the retry and timeout logic handles it
+        if error.kind() != ErrorKind::ConnectionRefused {
+            eprintln!("Failed to send packet: {}", error);
+        }
+    }
+}
+
+fn send_message(socket: &UdpSocket, address: SocketAddr, bytes: &[u8]) {
+    if bytes.len() > MAX_PACKET_SIZE {
+        eprintln!("Not sending a packet of {} bytes to {}, the limit is {}", bytes.len(), address, MAX_PACKET_SIZE);
+        return;
+    }
+    if let Err(error) = socket.send_to(bytes, address) {
+        eprintln!("Failed to send packet to {}: {}", address, error);
+    }
+}
+
+pub fn encode_client_message(message: &ClientMessage) -> Vec<u8> {
+    let mut out = Vec::new();
+    match message {
+        ClientMessage::Connect => write_header(&mut out, MESSAGE_CONNECT),
+        ClientMessage::Inputs(inputs) => {
+            write_header(&mut out, MESSAGE_INPUTS);
+            write_u8(&mut out, inputs.len() as u8);
+            for (sequence, input) in inputs {
+                write_u32(&mut out, *sequence);
+                write_f32(&mut out, input.movement.x);
+                write_f32(&mut out, input.movement.y);
+                write_bool(&mut out, input.dash_pressed);
+                write_bool(&mut out, input.join_pressed);
+            }
+        }
+        ClientMessage::RequestWorld => write_header(&mut out, MESSAGE_REQUEST_WORLD),
+        ClientMessage::Disconnect => write_header(&mut out, MESSAGE_DISCONNECT),
+    }
+    out
+}
+
+pub fn decode_client_message(mut data: &[u8]) -> Result<ClientMessage, NetworkError> {
+    let data = &mut data;
+    let message = match read_header(data)? {
+        MESSAGE_CONNECT => ClientMessage::Connect,
+        MESSAGE_INPUTS => {
+            let count = read_u8(data)?;
+            let mut inputs = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                let sequence = read_u32(data)?;
+                let movement = Vector2::new(read_f32(data)?, read_f32(data)?);
+                if !movement.x.is_finite() || !movement.y.is_finite() || movement.length() > 1.5 {
+                    return Err(NetworkError::Malformed("invalid movement"));
+                }
+                inputs.push((sequence, PlayerInputFrame {
+                    movement,
+                    dash_pressed: read_bool(data)?,
+                    join_pressed: read_bool(data)?,
+                }));
+            }
+            ClientMessage::Inputs(inputs)
+        }
+        MESSAGE_REQUEST_WORLD => ClientMessage::RequestWorld,
+        MESSAGE_DISCONNECT => ClientMessage::Disconnect,
+        _ => return Err(NetworkError::Malformed("unknown message type")),
+    };
+    expect_end(data)?;
+    Ok(message)
+}
+
+pub fn encode_server_message(message: &ServerMessage) -> Vec<u8> {
+    let mut out = Vec::new();
+    match message {
+        ServerMessage::Welcome { player_id } => {
+            write_header(&mut out, MESSAGE_WELCOME);
+            write_i32(&mut out, *player_id);
+        }
+        ServerMessage::Rejected(reason) => {
+            write_header(&mut out, MESSAGE_REJECTED);
+            write_string(&mut out, reason);
+        }
+        ServerMessage::WorldPart { version, part, part_count, data } => {
+            write_header(&mut out, MESSAGE_WORLD_PART);
+            write_u32(&mut out, *version);
+            write_u16(&mut out, *part);
+            write_u16(&mut out, *part_count);
+            write_u16(&mut out, data.len() as u16);
+            out.extend_from_slice(data);
+        }
+        ServerMessage::WorldDelta { base_version, version, voxels, spawn_points, lights } => {
+            write_header(&mut out, MESSAGE_WORLD_DELTA);
+            write_u32(&mut out, *base_version);
+            write_u32(&mut out, *version);
+            write_u16(&mut out, voxels.len() as u16);
+            for ((x, y, z), voxel_type) in voxels {
+                write_u16(&mut out, *x as u16);
+                write_u16(&mut out, *y as u16);
+                write_u16(&mut out, *z as u16);
+                write_u8(&mut out, voxel_type.0);
+            }
+            write_u32(&mut out, spawn_points.len() as u32);
+            for spawn in spawn_points {
+                write_vector3(&mut out, *spawn);
+            }
+            write_u8(&mut out, lights.len() as u8);
+            for light in lights {
+                write_light(&mut out, light);
+            }
+        }
+        ServerMessage::Snapshot { tick, world_version, last_input, match_state, players } => {
+            write_header(&mut out, MESSAGE_SNAPSHOT);
+            write_u32(&mut out, *tick);
+            write_u32(&mut out, *world_version);
+            write_u32(&mut out, *last_input);
+            write_match_state(&mut out, match_state);
+            write_u8(&mut out, players.len() as u8);
+            for (id, player) in players {
+                write_i32(&mut out, *id);
+                write_player(&mut out, player);
+            }
+        }
+    }
+    out
+}
+
+pub fn decode_server_message(mut data: &[u8]) -> Result<ServerMessage, NetworkError> {
+    let data = &mut data;
+    let message = match read_header(data)? {
+        MESSAGE_WELCOME => ServerMessage::Welcome { player_id: read_i32(data)? },
+        MESSAGE_REJECTED => ServerMessage::Rejected(read_string(data)?),
+        MESSAGE_WORLD_PART => {
+            let version = read_u32(data)?;
+            let part = read_u16(data)?;
+            let part_count = read_u16(data)?;
+            if part_count == 0 || part_count as usize > MAX_WORLD_PARTS || part >= part_count {
+                return Err(NetworkError::Malformed("invalid world part"));
+            }
+            let length = read_u16(data)? as usize;
+            if length > WORLD_PART_SIZE {
+                return Err(NetworkError::Malformed("world part too long"));
+            }
+            let bytes = read_bytes(data, length)?.to_vec();
+            ServerMessage::WorldPart { version, part, part_count, data: bytes }
+        }
+        MESSAGE_WORLD_DELTA => {
+            let base_version = read_u32(data)?;
+            let version = read_u32(data)?;
+            let voxel_count = read_u16(data)?;
+            let mut voxels = Vec::with_capacity(voxel_count as usize);
+            for _ in 0..voxel_count {
+                let position = (read_u16(data)? as i32, read_u16(data)? as i32, read_u16(data)? as i32);
+                voxels.push((position, VoxelType(read_u8(data)?)));
+            }
+            let spawn_count = read_u32(data)? as usize;
+            if spawn_count > data.len() / 12 {
+                return Err(NetworkError::Malformed("too many spawn points"));
+            }
+            let spawn_points = (0..spawn_count).map(|_| read_vector3(data)).collect::<Result<Vec<_>, _>>()?;
+            let light_count = read_u8(data)? as usize;
+            if light_count > MAX_LIGHTS {
+                return Err(NetworkError::Malformed("too many lights"));
+            }
+            let lights = (0..light_count).map(|_| read_light(data)).collect::<Result<Vec<_>, _>>()?;
+            ServerMessage::WorldDelta { base_version, version, voxels, spawn_points, lights }
+        }
+        MESSAGE_SNAPSHOT => {
+            let tick = read_u32(data)?;
+            let world_version = read_u32(data)?;
+            let last_input = read_u32(data)?;
+            let match_state = read_match_state(data)?;
+            let count = read_u8(data)?;
+            let mut players = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                let id = read_i32(data)?;
+                players.push((id, read_player(data)?));
+            }
+            ServerMessage::Snapshot { tick, world_version, last_input, match_state, players }
+        }
+        _ => return Err(NetworkError::Malformed("unknown message type")),
+    };
+    expect_end(data)?;
+    Ok(message)
+}
+
+// Blocks, world and lights as one byte string, split into WorldPart messages for sending
+fn encode_world_payload(world: &World, lights: &[LightSource], blocks: &BlockRegistry) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_blocks(&mut out, blocks);
+    write_world(&mut out, world);
+    write_u8(&mut out, lights.len() as u8);
+    for light in lights {
+        write_light(&mut out, light);
+    }
+    out
+}
+
+fn decode_world_payload(mut data: &[u8]) -> Result<(World, Vec<LightSource>, BlockRegistry), NetworkError> {
+    let data = &mut data;
+    let blocks = read_blocks(data)?;
+    let world = read_world(data, &blocks)?;
+    let count = read_u8(data)? as usize;
+    if count > MAX_LIGHTS {
+        return Err(NetworkError::Malformed("too many lights"));
+    }
+    let lights = (0..count).map(|_| read_light(data)).collect::<Result<Vec<_>, _>>()?;
+    expect_end(data)?;
+    Ok((world, lights, blocks))
+}
+
+// Voxels are sent as runs of (length, type) in grid order
+pub fn write_world(out: &mut Vec<u8>, world: &World) {
+    write_i32(out, world.width);
+    write_i32(out, world.height);
+    write_i32(out, world.depth);
+    write_f32(out, world.voxel_size);
+    write_u32(out, world.spawn_points.len() as u32);
+    for spawn in &world.spawn_points {
+        write_vector3(out, *spawn);
+    }
+
+    let mut runs: Vec<(u32, VoxelType)> = Vec::new();
+    for voxel_type in &world.voxels {
+        match runs.last_mut() {
+            Some((length, run_type)) if run_type == voxel_type => *length += 1,
+            _ => runs.push((1, *voxel_type)),
+        }
+    }
+    write_u32(out, runs.len() as u32);
+    for (length, voxel_type) in runs {
+        write_u32(out, length);
+        write_u8(out, voxel_type.0);
+    }
+}
+
+pub fn read_world(data: &mut &[u8], blocks: &BlockRegistry) -> Result<World, NetworkError> {
+    let width = read_i32(data)?;
+    let height = read_i32(data)?;
+    let depth = read_i32(data)?;
+    let valid = |value: i32| value > 0 && value <= MAX_WORLD_DIMENSION;
+    if !valid(width) || !valid(height) || !valid(depth) {
+        return Err(NetworkError::Malformed("invalid world size"));
+    }
+    let voxel_size = read_f32(data)?;
+    if !voxel_size.is_finite() || voxel_size <= 0.0 {
+        return Err(NetworkError::Malformed("invalid voxel size"));
+    }
+
+    let mut world = create_world(width, height, depth, voxel_size);
+    // Each spawn point takes 12 bytes, a count the rest of the payload can't hold is corrupt
+    let spawn_count = read_u32(data)? as usize;
+    if spawn_count > data.len() / 12 {
+        return Err(NetworkError::Malformed("too many spawn points"));
+    }
+    for _ in 0..spawn_count {
+        world.spawn_points.push(read_vector3(data)?);
+    }
+
+    let run_count = read_u32(data)?;
+    let mut index = 0;
+    for _ in 0..run_count {
+        let length = read_u32(data)? as usize;
+        let voxel_type = VoxelType(read_u8(data)?);
+        if voxel_type.0 as usize >= blocks.blocks.len() {
+            return Err(NetworkError::Malformed("unknown block"));
+        }
+        if length > world.voxels.len() - index {
+            return Err(NetworkError::Malformed("too many voxels"));
+        }
+        world.voxels[index..index + length].fill(voxel_type);
+        index += length;
+    }
+    if index != world.voxels.len() {
+        return Err(NetworkError::Malformed("missing voxels"));
+    }
+    Ok(world)
+}
+
+fn write_player(out: &mut Vec<u8>, player: &PlayerState) {
+    write_vector3(out, player.position);
+    write_vector3(out, player.size);
+    write_color(out, player.color);
+    write_color(out, player.original_color);
+    write_vector3(out, player.velocity);
+    write_vector3(out, player.acceleration);
+    write_bool(out, player.is_dashing);
+    write_f32(out, player.dash_cooldown);
+    write_vector3(out, player.dash_direction);
+    write_vector3(out, player.pre_dash_velocity);
+    write_bool(out, player.is_ready);
+    write_f32(out, player.health);
+    write_bool(out, player.is_alive);
+    write_f32(out, player.hit_cooldown);
+    write_bool(out, player.is_grounded);
+    write_u8(out, player.ground_block.0);
+}
+
+fn read_player(data: &mut &[u8]) -> Result<PlayerState, NetworkError> {
+    let position = read_vector3(data)?;
+    Ok(PlayerState {
+        position,
+        size: read_vector3(data)?,
+        color: read_color(data)?,
+        original_color: read_color(data)?,
+        velocity: read_vector3(data)?,
+        acceleration: read_vector3(data)?,
+        is_dashing: read_bool(data)?,
+        dash_cooldown: read_f32(data)?,
+        dash_direction: read_vector3(data)?,
+        pre_dash_velocity: read_vector3(data)?,
+        is_ready: read_bool(data)?,
+        health: read_f32(data)?,
+        is_alive: read_bool(data)?,
+        hit_cooldown: read_f32(data)?,
+        is_grounded: read_bool(data)?,
+        ground_block: VoxelType(read_u8(data)?),
+        previous_position: position,
+    })
+}
+
+// Round wins are sent sorted by player ID
+fn write_match_state(out: &mut Vec<u8>, match_state: &MatchState) {
+    write_u8(out, match_phase_id(match_state.phase));
+    write_f32(out, match_state.phase_timer);
+    write_u32(out, match_state.round);
+    write_u32(out, match_state.rounds_to_win);
+    let mut round_wins: Vec<(&i32, &u32)> = match_state.round_wins.iter().collect();
+    round_wins.sort();
+    write_u8(out, round_wins.len() as u8);
+    for (id, wins) in round_wins {
+        write_i32(out, *id);
+        write_u32(out, *wins);
+    }
+    write_optional_id(out, match_state.round_winner);
+    write_optional_id(out, match_state.match_winner);
+}
+
+fn read_match_state(data: &mut &[u8]) -> Result<MatchState, NetworkError> {
+    let phase = match_phase_from_id(read_u8(data)?).ok_or(NetworkError::Malformed("unknown match phase"))?;
+    let phase_timer = read_f32(data)?;
+    let round = read_u32(data)?;
+    let rounds_to_win = read_u32(data)?;
+    let count = read_u8(data)?;
+    let mut round_wins = HashMap::new();
+    for _ in 0..count {
+        let id = read_i32(data)?;
+        round_wins.insert(id, read_u32(data)?);
+    }
+    Ok(MatchState {
+        phase,
+        phase_timer,
+        round,
+        rounds_to_win,
+        round_wins,
+        round_winner: read_optional_id(data)?,
+        match_winner: read_optional_id(data)?,
+    })
+}
+
+fn write_optional_id(out: &mut Vec<u8>, id: Option<i32>) {
+    write_bool(out, id.is_some());
+    write_i32(out, id.unwrap_or_default());
+}
+
+fn read_optional_id(data: &mut &[u8]) -> Result<Option<i32>, NetworkError> {
+    let is_some = read_bool(data)?;
+    let id = read_i32(data)?;
+    Ok(is_some.then_some(id))
+}
+
+fn match_phase_id(phase: MatchPhase) -> u8 {
+    match phase {
+        MatchPhase::Lobby => 0,
+        MatchPhase::Countdown => 1,
+        MatchPhase::Playing => 2,
+        MatchPhase::RoundOver => 3,
+        MatchPhase::MatchOver => 4,
+    }
+}
+
+fn match_phase_from_id(id: u8) -> Option<MatchPhase> {
+    match id {
+        0 => Some(MatchPhase::Lobby),
+        1 => Some(MatchPhase::Countdown),
+        2 => Some(MatchPhase::Playing),
+        3 => Some(MatchPhase::RoundOver),
+        4 => Some(MatchPhase::MatchOver),
+        _ => None,
+    }
+}
+
+// Blocks after empty space, in ID order
+pub fn write_blocks(out: &mut Vec<u8>, blocks: &BlockRegistry) {
+    write_u8(out, (blocks.blocks.len() - 1) as u8);
+    for block in &blocks.blocks[1..] {
+        write_string(out, &block.name);
+        write_color(out, block.color);
+        write_bool(out, block.solid);
+        write_bool(out, block.walkable);
+        write_bool(out, block.destructible);
+        write_f32(out, block.hit_points);
+        write_f32(out, block.friction);
+        write_f32(out, block.bounce);
+        write_f32(out, block.damage);
+    }
+}
+
+pub fn read_blocks(data: &mut &[u8]) -> Result<BlockRegistry, NetworkError> {
+    let count = read_u8(data)?;
+    if count < VoxelType::WALL.0 {
+        return Err(NetworkError::Malformed("missing terrain blocks"));
+    }
+    let mut blocks = BlockRegistry { blocks: vec![BlockRegistry::default().blocks[0].clone()] };
+    for id in 1..=count {
+        blocks.blocks.push(BlockProperties {
+            id: VoxelType(id),
+            name: read_string(data)?,
+            color: read_color(data)?,
+            solid: read_bool(data)?,
+            walkable: read_bool(data)?,
+            destructible: read_bool(data)?,
+            hit_points: read_f32(data)?,
+            friction: read_f32(data)?,
+            bounce: read_f32(data)?,
+            damage: read_f32(data)?,
+        });
+    }
+    Ok(blocks)
+}
+
+pub fn write_light(out: &mut Vec<u8>, light: &LightSource) {
+    write_u8(out, light.light_type as u8);
+    write_vector3(out, light.position);
+    write_vector3(out, light.target);
+    write_color(out, light.color);
+    write_bool(out, light.enabled);
+    write_f32(out, light.radius);
+    write_f32(out, light.cone_angle);
+}
+
+pub fn read_light(data: &mut &[u8]) -> Result<LightSource, NetworkError> {
+    let light_type = match read_u8(data)? {
+        0 => LightType::Directional,
+        1 => LightType::Point,
+        2 => LightType::Spot,
+        _ => return Err(NetworkError::Malformed("unknown light type")),
+    };
+    Ok(LightSource {
+        light_type,
+        position: read_vector3(data)?,
+        target: read_vector3(data)?,
+        color: read_color(data)?,
+        enabled: read_bool(data)?,
+        radius: read_f32(data)?,
+        cone_angle: read_f32(data)?,
+    })
+}
+
+fn write_header(out: &mut Vec<u8>, message_type: u8) {
+    out.extend_from_slice(&PACKET_MAGIC);
+    write_u8(out, PROTOCOL_VERSION);
+    write_u8(out, message_type);
+}
+
+fn read_header(data: &mut &[u8]) -> Result<u8, NetworkError> {
+    if read_bytes(data, 2)? != PACKET_MAGIC {
+        return Err(NetworkError::Malformed("not a voxel battle packet"));
+    }
+    let version = read_u8(data)?;
+    if version != PROTOCOL_VERSION {
+        return Err(NetworkError::UnsupportedVersion(version));
+    }
+    read_u8(data)
+}
+
+pub fn expect_end(data: &[u8]) -> Result<(), NetworkError> {
+    if data.is_empty() {
+        Ok(())
+    } else {
+        Err(NetworkError::Malformed("unexpected trailing bytes"))
+    }
+}
+
+// Little-endian primitives, shared with the replay format
+pub fn write_u8(out: &mut Vec<u8>, value: u8) {
+    out.push(value);
+}
+
+pub fn write_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn write_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn write_i32(out: &mut Vec<u8>, value: i32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn write_f32(out: &mut Vec<u8>, value: f32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn write_bool(out: &mut Vec<u8>, value: bool) {
+    out.push(value as u8);
+}
+
+fn write_vector3(out: &mut Vec<u8>, value: Vector3) {
+    write_f32(out, value.x);
+    write_f32(out, value.y);
+    write_f32(out, value.z);
+}
+
+fn write_color(out: &mut Vec<u8>, color: Color) {
+    out.extend_from_slice(&[color.r, color.g, color.b, color.a]);
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    let bytes = &value.as_bytes()[..value.len().min(u8::MAX as usize)];
+    write_u8(out, bytes.len() as u8);
+    out.extend_from_slice(bytes);
+}
+
+pub fn read_bytes<'a>(data: &mut &'a [u8], count: usize) -> Result<&'a [u8], NetworkError> {
+    if data.len() < count {
+        return Err(NetworkError::Malformed("truncated packet"));
+    }
+    let (bytes, rest) = data.split_at(count);
+    *data = rest;
+    Ok(bytes)
+}
+
+pub fn read_u8(data: &mut &[u8]) -> Result<u8, NetworkError> {
+    Ok(read_bytes(data, 1)?[0])
+}
+
+pub fn read_u16(data: &mut &[u8]) -> Result<u16, NetworkError> {
+    let bytes = read_bytes(data, 2)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+pub fn read_u32(data: &mut &[u8]) -> Result<u32, NetworkError> {
+    let bytes = read_bytes(data, 4)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+pub fn read_i32(data: &mut &[u8]) -> Result<i32, NetworkError> {
+    Ok(read_u32(data)? as i32)
+}
+
+pub fn read_f32(data: &mut &[u8]) -> Result<f32, NetworkError> {
+    Ok(f32::from_bits(read_u32(data)?))
+}
+
+pub fn read_bool(data: &mut &[u8]) -> Result<bool, NetworkError> {
+    match read_u8(data)? {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(NetworkError::Malformed("invalid boolean")),
+    }
+}
+
+fn read_vector3(data: &mut &[u8]) -> Result<Vector3, NetworkError> {
+    Ok(Vector3::new(read_f32(data)?, read_f32(data)?, read_f32(data)?))
+}
+
+fn read_color(data: &mut &[u8]) -> Result<Color, NetworkError> {
+    let bytes = read_bytes(data, 4)?;
+    Ok(Color { r: bytes[0], g: bytes[1], b: bytes[2], a: bytes[3] })
+}
+
+fn read_string(data: &mut &[u8]) -> Result<String, NetworkError> {
+    let length = read_u8(data)? as usize;
+    let bytes = read_bytes(data, length)?;
+    String::from_utf8(bytes.to_vec()).map_err(|_| NetworkError::Malformed("invalid text"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::generation::generate_arena;
+    use crate::state::{ArenaPreset, ArenaSettings};
+
+    fn arena() -> World {
+        generate_arena(25, 12, 25, 1.0, ArenaSettings { seed: 7, preset: ArenaPreset::Pillars }).unwrap()
+    }
+
+    #[test]
+    fn client_messages_round_trip() {
+        let input = PlayerInputFrame { movement: Vector2::new(0.6, -0.8), dash_pressed: true, join_pressed: false };
+        let messages = [
+            ClientMessage::Connect,
+            ClientMessage::Inputs(vec![(41, PlayerInputFrame::default()), (42, input)]),
+            ClientMessage::RequestWorld,
+            ClientMessage::Disconnect,
+        ];
+        for message in messages {
+            let decoded = decode_client_message(&encode_client_message(&message)).unwrap();
+            assert_eq!(decoded, message);
+        }
+    }
+
+    #[test]
+    fn server_messages_round_trip() {
+        let mut match_state = MatchState { phase: MatchPhase::Playing, round: 2, round_winner: Some(3), ..Default::default() };
+        match_state.round_wins.insert(3, 1);
+        // The previous position isn't sent, clients start interpolating from the received one
+        let position = Vector3::new(4.0, 2.0, 5.0);
+        let player = PlayerState { position, previous_position: position, health: 40.0, ..Default::default() };
+        let messages = [
+            ServerMessage::Welcome { player_id: 3 },
+            ServerMessage::Rejected("server is full".to_string()),
+            ServerMessage::WorldPart { version: 5, part: 1, part_count: 3, data: vec![1, 2, 3] },
+            ServerMessage::WorldDelta {
+                base_version: 5,
+                version: 6,
+                voxels: vec![((1, 2, 3), VoxelType::WALL), ((4, 0, 4), VoxelType::EMPTY)],
+                spawn_points: vec![Vector3::new(2.0, 1.0, 2.0)],
+                lights: vec![LightSource::default()],
+            },
+            ServerMessage::Snapshot { tick: 9, world_version: 6, last_input: 42, match_state, players: vec![(3, player)] },
+        ];
+        for message in messages {
+            let decoded = decode_server_message(&encode_server_message(&message)).unwrap();
+            assert_eq!(decoded, message);
+        }
+    }
+
+    #[test]
+    fn world_payload_round_trips() {
+        let world = arena();
+        let lights = vec![LightSource::default()];
+        let blocks = BlockRegistry::default();
+        let (decoded, decoded_lights, decoded_blocks) = decode_world_payload(&encode_world_payload(&world, &lights, &blocks)).unwrap();
+        assert_eq!((decoded.voxels, decoded.spawn_points), (world.voxels, world.spawn_points));
+        assert_eq!((decoded_lights, decoded_blocks), (lights, blocks));
+    }
+
+    #[test]
+    fn edits_become_deltas_and_replaced_worlds_do_not() {
+        let blocks = BlockRegistry::default();
+        let previous = arena();
+        let mut world = previous.clone();
+        set_voxel(&mut world, 3, 6, 4, VoxelType::WALL);
+        set_voxel(&mut world, 20, 6, 20, VoxelType::WALL);
+        let voxels = changed_voxels(&previous, &blocks, &world, &blocks).unwrap();
+        assert_eq!(voxels, vec![((3, 6, 4), VoxelType::WALL), ((20, 6, 20), VoxelType::WALL)]);
+
+        let resized = generate_arena(30, 12, 25, 1.0, ArenaSettings { seed: 7, preset: ArenaPreset::Pillars }).unwrap();
+        assert_eq!(changed_voxels(&previous, &blocks, &resized, &blocks), None);
+        // Same size, but too many changes for one packet
+        let flattened = create_world(25, 12, 25, 1.0);
+        assert_eq!(changed_voxels(&previous, &blocks, &flattened, &blocks), None);
+    }
+
+    // A server and a client talking over real sockets on the loopback interface
+    struct Loopback {
+        server: ServerState,
+        server_state: GameState,
+        server_socket: UdpSocket,
+        client: ClientState,
+        client_state: GameState,
+        client_socket: UdpSocket,
+    }
+
+    impl Loopback {
+        fn connect() -> Loopback {
+            let server_socket = open_server_socket("127.0.0.1:0").unwrap();
+            let client_socket = open_client_socket(&server_socket.local_addr().unwrap().to_string()).unwrap();
+            let mut server_state = GameState::default();
+            replace_world(&mut server_state.world, arena());
+            let mut loopback = Loopback {
+                server: ServerState::default(),
+                server_state,
+                server_socket,
+                client: ClientState::default(),
+                client_state: GameState::default(),
+                client_socket,
+            };
+            client_connect(&mut loopback.client, &loopback.client_socket);
+            loopback.run_until(|loopback| loopback.client.world_version == Some(loopback.server.world_version));
+            loopback
+        }
+
+        fn tick(&mut self) {
+            let delta = 1.0 / TICK_RATE as f32;
+            server_receive(&mut self.server, &mut self.server_state, &self.server_socket);
+            server_tick(&mut self.server, &mut self.server_state, &self.server_socket, delta);
+            std::thread::sleep(Duration::from_millis(2));
+            client_receive(&mut self.client, &mut self.client_state, &self.client_socket, delta).unwrap();
+        }
+
+        fn run_until(&mut self, done: impl Fn(&Loopback) -> bool) {
+            for _ in 0..500 {
+                self.tick();
+                if done(self) {
+                    return;
+                }
+            }
+            panic!("loopback did not settle");
+        }
+
+        // Run the server for one tick and return what it sent, without handing it to the client
+        fn server_messages(&mut self) -> Vec<ServerMessage> {
+            server_tick(&mut self.server, &mut self.server_state, &self.server_socket, 1.0 / TICK_RATE as f32);
+            std::thread::sleep(Duration::from_millis(20));
+            let mut buffer = vec![0u8; MAX_PACKET_SIZE];
+            let mut messages = Vec::new();
+            while let Ok(size) = self.client_socket.recv(&mut buffer) {
+                messages.push(decode_server_message(&buffer[..size]).unwrap());
+            }
+            messages
+        }
+    }
+
+    #[test]
+    fn client_joins_and_follows_edits_over_loopback() {
+        let mut loopback = Loopback::connect();
+        let player_id = loopback.client.player_id.expect("welcomed");
+        assert!(loopback.server_state.players.contains_key(&player_id));
+        assert_eq!(loopback.client_state.world.voxels, loopback.server_state.world.voxels);
+        assert_eq!(loopback.client_state.world.spawn_points, loopback.server_state.world.spawn_points);
+
+        // A broken voxel goes out as a delta the client applies to its copy
+        set_voxel(&mut loopback.server_state.world, 12, 0, 12, VoxelType::EMPTY);
+        loopback.run_until(|loopback| loopback.client.world_version == Some(loopback.server.world_version));
+        assert_eq!(get_voxel(&loopback.client_state.world, 12, 0, 12), VoxelType::EMPTY);
+        assert_eq!(loopback.client_state.world.voxels, loopback.server_state.world.voxels);
+
+        set_voxel(&mut loopback.server_state.world, 12, 5, 12, VoxelType::WALL);
+        let messages = loopback.server_messages();
+        assert!(messages.iter().any(|message| matches!(
+            message, ServerMessage::WorldDelta { voxels, .. } if *voxels == vec![((12, 5, 12), VoxelType::WALL)]
+        )));
+        assert!(!messages.iter().any(|message| matches!(message, ServerMessage::WorldPart { .. })));
+
+        // A loaded map is sent in full
+        replace_world(&mut loopback.server_state.world, create_world(20, 8, 20, 1.0));
+        let messages = loopback.server_messages();
+        assert!(messages.iter().any(|message| matches!(message, ServerMessage::WorldPart { .. })));
+        assert!(!messages.iter().any(|message| matches!(message, ServerMessage::WorldDelta { .. })));
+    }
+}
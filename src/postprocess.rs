@@ -0,0 +1,95 @@
+use std::fs;
+use crate::state::{PostEffect, PostEffectKind};
+use crate::format::{FileFormat, FormatError, read_lines};
+
+pub const POST_PROCESS_FORMAT: FileFormat = FileFormat {
+    kind: "post-processing file",
+    header: "voxel_battle_postprocess",
+    version: 1,
+};
+
+pub fn load_post_effects(path: &str) -> Result<Vec<PostEffect>, FormatError> {
+    parse_post_effects(&fs::read_to_string(path)?)
+}
+
+// One effect per line: "effect <name> [off] [uniform=v1[,v2[,v3[,v4]]]]", applied in file order.
+// The same effect may appear more than once, e.g. to blur twice.
+pub fn parse_post_effects(text: &str) -> Result<Vec<PostEffect>, FormatError> {
+    let (_, lines) = read_lines(&POST_PROCESS_FORMAT, text)?;
+
+    let mut effects = Vec::new();
+    for (line, content) in lines {
+        let mut fields = content.split_whitespace();
+        let keyword = fields.next().unwrap_or("");
+        if keyword != "effect" {
+            return Err(FormatError::Parse { line, message: format!("unknown entry '{}'", keyword) });
+        }
+
+        let name = fields.next().ok_or_else(|| FormatError::Parse { line, message: "missing effect name".to_string() })?;
+        let kind = parse_post_effect_kind(name).ok_or_else(|| FormatError::Parse {
+            line,
+            message: format!("unknown effect '{}'", name),
+        })?;
+
+        let mut effect = PostEffect { kind, enabled: true, uniforms: Vec::new() };
+        for field in fields {
+            match field.split_once('=') {
+                None if field == "off" => effect.enabled = false,
+                None => return Err(FormatError::Parse { line, message: format!("unknown flag '{}'", field) }),
+                Some((uniform, value)) => {
+                    let values = parse_uniform(uniform, value, line)?;
+                    effect.uniforms.push((uniform.to_string(), values));
+                }
+            }
+        }
+        effects.push(effect);
+    }
+    Ok(effects)
+}
+
+// Name used in post-processing files, also the name of the effect's fragment shader
+pub fn post_effect_name(kind: PostEffectKind) -> &'static str {
+    match kind {
+        PostEffectKind::Outline => "outline",
+        PostEffectKind::Bloom => "bloom",
+        PostEffectKind::Fog => "fog",
+        PostEffectKind::Posterization => "posterization",
+        PostEffectKind::Scanlines => "scanlines",
+        PostEffectKind::Pixelizer => "pixelizer",
+    }
+}
+
+pub fn parse_post_effect_kind(name: &str) -> Option<PostEffectKind> {
+    [
+        PostEffectKind::Outline,
+        PostEffectKind::Bloom,
+        PostEffectKind::Fog,
+        PostEffectKind::Posterization,
+        PostEffectKind::Scanlines,
+        PostEffectKind::Pixelizer,
+    ].into_iter().find(|kind| post_effect_name(*kind) == name)
+}
+
+// Comma separated floats for a GLSL identifier, one value for a float up to four for a vec4
+fn parse_uniform(uniform: &str, value: &str, line: usize) -> Result<Vec<f32>, FormatError> {
+    let is_identifier = uniform.chars().next().is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && uniform.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !is_identifier {
+        return Err(FormatError::Parse { line, message: format!("invalid uniform name '{}'", uniform) });
+    }
+
+    let mut values = Vec::new();
+    for number in value.split(',') {
+        match number.parse::<f32>() {
+            Ok(number) if number.is_finite() => values.push(number),
+            _ => return Err(FormatError::Parse {
+                line,
+                message: format!("invalid value '{}' for {}", number, uniform),
+            }),
+        }
+    }
+    if values.len() > 4 {
+        return Err(FormatError::Parse { line, message: format!("{} has more than four values", uniform) });
+    }
+    Ok(values)
+}
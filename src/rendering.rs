@@ -1,77 +1,308 @@
 use raylib::prelude::*;
-use crate::state::{GameState, VoxelType, ShaderType};
-use crate::config::{VOXEL_SIZE, DEBUG_COLOR, GRID_COLOR, LIGHT_COLOR};
+use crate::state::{ChunkMeshes, GameState, LightSource, LightType, ManagedShader, MatchPhase, PostEffect, PostTargets, RenderState, ShaderType};
+use crate::config::{
+    POST_PROCESS_PATH, AMBIENT_LIGHT, SHADER_RELOAD_INTERVAL, SHADOW_MAP_RESOLUTION, SHADOW_MAP_SLOT, VOXEL_SIZE,
+    DEBUG_COLOR, GRID_COLOR, LIGHT_COLOR, PLAYER_MAX_HEALTH, MIN_PLAYERS_TO_START, MAX_LIGHTS, PLAYER_GLOW_INTENSITY,
+    PLAYER_GLOW_RADIUS, DASH_FLASH_INTENSITY,
+};
+use crate::logic::{brush_preview, camera_mode_name, chunk_position, editor_tool_name, iter_voxels, light_type_name};
+use crate::meshing::{mesh_chunk, MeshData};
+use crate::generation::arena_preset_name;
+use crate::bots::bot_difficulty_name;
+use crate::postprocess::{load_post_effects, post_effect_name};
+use crate::shaders::{
+    load_managed_shader, reload_shader, set_uniform_floats, set_uniform_int, set_uniform_matrix, set_uniform_texture, shader_path,
+};
 
-pub fn render(state: &GameState, rl: &mut RaylibHandle, thread: &RaylibThread) {
-    let mut d = rl.begin_drawing(thread);
-    
-    d.clear_background(Color::BLACK);
-    
-    // Apply outline shader if available
-    if let Some(outline_shader) = state.shaders.get(&ShaderType::Outline) {
+// Load GPU resources, requires an open window
+pub fn init_render(render_state: &mut RenderState) {
+    // Basic lighting shader, its light is updated every frame in `render`
+    let lighting = load_managed_shader(Some(&shader_path("lighting.vs")), &shader_path("lighting.fs"));
+
+    // Chunk meshes are drawn through a material, its shader is swapped for the pass being drawn
+    let mut material = unsafe { ffi::LoadMaterialDefault() };
+    material.shader = lighting.shader;
+    render_state.voxel_material = Some(material);
+    render_state.shaders.insert(ShaderType::Lighting, lighting);
+
+    init_shadows(render_state);
+    init_post_effects(render_state);
+}
+
+// Load the shadow mapping shader and its depth target, without the target the scene falls back to the lighting shader
+fn init_shadows(render_state: &mut RenderState) {
+    let Some(shadow_map) = load_shadow_map(SHADOW_MAP_RESOLUTION) else {
+        eprintln!("Failed to create the shadow map, drawing the scene without shadows");
+        return;
+    };
+    let shader = load_managed_shader(Some(&shader_path("shadowmap.vs")), &shader_path("shadowmap.fs"));
+    render_state.shaders.insert(ShaderType::Shadowmap, shader);
+    render_state.shadow_map = Some(shadow_map);
+}
+
+// Framebuffer with only a depth texture attached, the shadow pass has no use for color
+fn load_shadow_map(resolution: i32) -> Option<ffi::RenderTexture> {
+    unsafe {
+        let mut target: ffi::RenderTexture = std::mem::zeroed();
+        target.id = ffi::rlLoadFramebuffer();
+        if target.id == 0 {
+            return None;
+        }
+        target.texture.width = resolution;
+        target.texture.height = resolution;
+
+        ffi::rlEnableFramebuffer(target.id);
+        target.depth.id = ffi::rlLoadTextureDepth(resolution, resolution, false);
+        target.depth.width = resolution;
+        target.depth.height = resolution;
+        target.depth.mipmaps = 1;
+        ffi::rlFramebufferAttach(
+            target.id,
+            target.depth.id,
+            ffi::rlFramebufferAttachType::RL_ATTACHMENT_DEPTH as i32,
+            ffi::rlFramebufferAttachTextureType::RL_ATTACHMENT_TEXTURE2D as i32,
+            0,
+        );
+        let complete = ffi::rlFramebufferComplete(target.id);
+        ffi::rlDisableFramebuffer();
+
+        if !complete {
+            ffi::rlUnloadFramebuffer(target.id);
+            return None;
+        }
+        Some(target)
+    }
+}
+
+// Load the shaders of the enabled effects in the post-processing file
+fn init_post_effects(render_state: &mut RenderState) {
+    let effects = match load_post_effects(POST_PROCESS_PATH) {
+        Ok(effects) => effects,
+        Err(error) => {
+            eprintln!("Failed to load post-processing from {}, drawing the scene without effects: {}", POST_PROCESS_PATH, error);
+            return;
+        }
+    };
+
+    for effect in effects.into_iter().filter(|effect| effect.enabled) {
+        // Effects only need a fragment shader, raylib's default vertex shader draws the screen quad
+        let shader = load_managed_shader(None, &shader_path(&format!("{}.fs", post_effect_name(effect.kind))));
+        render_state.post_effects.push((effect, shader));
+    }
+}
+
+// Recompile shaders whose files were saved since the last check, a shader that fails keeps its last good version
+fn reload_changed_shaders(render_state: &mut RenderState) {
+    let now = unsafe { ffi::GetTime() };
+    if now - render_state.shader_check_time < SHADER_RELOAD_INTERVAL {
+        return;
+    }
+    render_state.shader_check_time = now;
+
+    let shaders = render_state.shaders.values_mut().chain(render_state.post_effects.iter_mut().map(|(_, shader)| shader));
+    for shader in shaders {
+        match reload_shader(shader) {
+            Ok(true) => println!("Reloaded shader {}", shader.fs_path),
+            Ok(false) => {}
+            Err(error) => eprintln!("Failed to reload shader, keeping the last good version: {}", error),
+        }
+    }
+}
+
+// Post-processing targets matching the screen, None when no effect is enabled or they could not be created
+fn update_post_targets(render_state: &mut RenderState, width: i32, height: i32) -> Option<PostTargets> {
+    if render_state.post_effects.is_empty() {
+        return None;
+    }
+    if let Some(targets) = render_state.post_targets {
+        if targets.scene.texture.width == width && targets.scene.texture.height == height {
+            return Some(targets);
+        }
+        unsafe {
+            for target in [targets.scene, targets.ping_pong[0], targets.ping_pong[1]] {
+                ffi::UnloadRenderTexture(target);
+            }
+        }
+        render_state.post_targets = None;
+    }
+
+    let Some(scene) = load_scene_target(width, height) else {
+        eprintln!("Failed to create the post-processing target, drawing the scene without effects");
+        for (_, managed) in render_state.post_effects.drain(..) {
+            unsafe {
+                ffi::UnloadShader(managed.shader);
+            }
+        }
+        return None;
+    };
+    let ping_pong = unsafe { [ffi::LoadRenderTexture(width, height), ffi::LoadRenderTexture(width, height)] };
+    render_state.post_targets = Some(PostTargets { scene, ping_pong });
+    render_state.post_targets
+}
+
+// Like LoadRenderTexture but with a depth texture instead of a renderbuffer, so effects such as fog can read depth
+fn load_scene_target(width: i32, height: i32) -> Option<ffi::RenderTexture> {
+    unsafe {
+        let mut target: ffi::RenderTexture = std::mem::zeroed();
+        target.id = ffi::rlLoadFramebuffer();
+        if target.id == 0 {
+            return None;
+        }
+
+        ffi::rlEnableFramebuffer(target.id);
+        let format = ffi::PixelFormat::PIXELFORMAT_UNCOMPRESSED_R8G8B8A8 as i32;
+        target.texture.id = ffi::rlLoadTexture(std::ptr::null(), width, height, format, 1);
+        target.texture.width = width;
+        target.texture.height = height;
+        target.texture.format = format;
+        target.texture.mipmaps = 1;
+        target.depth.id = ffi::rlLoadTextureDepth(width, height, false);
+        target.depth.width = width;
+        target.depth.height = height;
+        target.depth.mipmaps = 1;
+        ffi::rlFramebufferAttach(
+            target.id,
+            target.texture.id,
+            ffi::rlFramebufferAttachType::RL_ATTACHMENT_COLOR_CHANNEL0 as i32,
+            ffi::rlFramebufferAttachTextureType::RL_ATTACHMENT_TEXTURE2D as i32,
+            0,
+        );
+        ffi::rlFramebufferAttach(
+            target.id,
+            target.depth.id,
+            ffi::rlFramebufferAttachType::RL_ATTACHMENT_DEPTH as i32,
+            ffi::rlFramebufferAttachTextureType::RL_ATTACHMENT_TEXTURE2D as i32,
+            0,
+        );
+        let complete = ffi::rlFramebufferComplete(target.id);
+        ffi::rlDisableFramebuffer();
+
+        if !complete {
+            ffi::UnloadRenderTexture(target);
+            return None;
+        }
+        Some(target)
+    }
+}
+
+// Run the scene texture through the effects in order, each reads the previous result and the last one draws to the screen
+fn apply_post_effects(render_state: &mut RenderState, targets: PostTargets, d: &mut RaylibDrawHandle) {
+    let width = targets.scene.texture.width as f32;
+    let height = targets.scene.texture.height as f32;
+    // Render textures are stored upside down, a negative source height flips them back
+    let source_rect = ffi::Rectangle { x: 0.0, y: 0.0, width, height: -height };
+    let last = render_state.post_effects.len() - 1;
+
+    let mut source = targets.scene.texture;
+    for (index, (effect, shader)) in render_state.post_effects.iter_mut().enumerate() {
+        let target = targets.ping_pong[index % 2];
+        if index < last {
+            unsafe {
+                ffi::BeginTextureMode(target);
+            }
+            d.clear_background(Color::BLANK);
+        }
+
+        set_post_effect_uniforms(effect, shader, targets);
         unsafe {
-            // Set outline shader uniforms
-            let texture_size = std::ffi::CString::new("textureSize").unwrap();
-            let texture_size_loc = ffi::GetShaderLocation(*outline_shader, texture_size.as_ptr());
-            let size = [d.get_screen_width() as f32, d.get_screen_height() as f32];
-            ffi::SetShaderValue(*outline_shader, texture_size_loc, size.as_ptr() as *const std::ffi::c_void, ffi::ShaderUniformDataType::SHADER_UNIFORM_VEC2 as i32);
-            
-            let outline_size = std::ffi::CString::new("outlineSize").unwrap();
-            let outline_size_loc = ffi::GetShaderLocation(*outline_shader, outline_size.as_ptr());
-            let size = [2.0f32];
-            ffi::SetShaderValue(*outline_shader, outline_size_loc, size.as_ptr() as *const std::ffi::c_void, ffi::ShaderUniformDataType::SHADER_UNIFORM_FLOAT as i32);
-            
-            let outline_color = std::ffi::CString::new("outlineColor").unwrap();
-            let outline_color_loc = ffi::GetShaderLocation(*outline_shader, outline_color.as_ptr());
-            let color = [1.0f32, 0.0f32, 0.0f32, 1.0f32]; // Red outline
-            ffi::SetShaderValue(*outline_shader, outline_color_loc, color.as_ptr() as *const std::ffi::c_void, ffi::ShaderUniformDataType::SHADER_UNIFORM_VEC4 as i32);
-            
-            ffi::BeginShaderMode(*outline_shader);
+            ffi::BeginShaderMode(shader.shader);
+            ffi::DrawTextureRec(source, source_rect, ffi::Vector2 { x: 0.0, y: 0.0 }, Color::WHITE.into());
+            ffi::EndShaderMode();
+        }
+
+        if index < last {
+            unsafe {
+                ffi::EndTextureMode();
+            }
+            source = target.texture;
         }
     }
+}
+
+// Values every effect may use, then the effect's own parameters from the post-processing file
+fn set_post_effect_uniforms(effect: &PostEffect, shader: &mut ManagedShader, targets: PostTargets) {
+    let size = [targets.scene.texture.width as f32, targets.scene.texture.height as f32];
+    set_uniform_floats(shader, "textureSize", &size);
+    set_uniform_floats(shader, "time", &[unsafe { ffi::GetTime() } as f32]);
+
+    // The depth texture holds the 3D pass, drawn with the current clip planes
+    set_uniform_texture(shader, "depthTexture", targets.scene.depth);
+    set_uniform_floats(shader, "nearPlane", &[unsafe { ffi::rlGetCullDistanceNear() } as f32]);
+    set_uniform_floats(shader, "farPlane", &[unsafe { ffi::rlGetCullDistanceFar() } as f32]);
+
+    for (name, values) in &effect.uniforms {
+        set_uniform_floats(shader, name, values);
+    }
+}
+
+// Draw the state, `alpha` is how far the frame is between the previous and the current simulation tick
+pub fn render(state: &GameState, render_state: &mut RenderState, alpha: f32, rl: &mut RaylibHandle, thread: &RaylibThread) {
+    update_chunk_meshes(render_state, state);
+    reload_changed_shaders(render_state);
+
+    let mut d = rl.begin_drawing(thread);
+
+    // The first enabled directional light casts shadows, the others only light the scene
+    let shadow_caster = shadow_light(state);
+    let light_view_projection = shadow_caster
+        .and_then(|index| draw_shadow_map(state, render_state, &state.lights[index], alpha, &mut d));
     
-    {
-        let mut d3 = d.begin_mode3D(state.camera_state.camera);
-        
-        // Update shader uniforms
-        if let Some(shader) = state.shaders.get(&ShaderType::Lighting) {
+    // With post effects the scene goes to a texture first, cleared transparent so the outline effect can find edges
+    let screen_width = d.get_screen_width();
+    let screen_height = d.get_screen_height();
+    let post_targets = update_post_targets(render_state, screen_width, screen_height);
+    match post_targets {
+        Some(targets) => {
             unsafe {
-                // Update light position
-                let light_position = std::ffi::CString::new("lights[0].position").unwrap();
-                let pos_loc = ffi::GetShaderLocation(*shader, light_position.as_ptr());
-                let pos = [state.light_source.position.x, state.light_source.position.y, state.light_source.position.z];
-                ffi::SetShaderValue(*shader, pos_loc, pos.as_ptr() as *const std::ffi::c_void, ffi::ShaderUniformDataType::SHADER_UNIFORM_VEC3 as i32);
-                
-                // Update light target
-                let light_target = std::ffi::CString::new("lights[0].target").unwrap();
-                let target_loc = ffi::GetShaderLocation(*shader, light_target.as_ptr());
-                let target = [state.light_source.target.x, state.light_source.target.y, state.light_source.target.z];
-                ffi::SetShaderValue(*shader, target_loc, target.as_ptr() as *const std::ffi::c_void, ffi::ShaderUniformDataType::SHADER_UNIFORM_VEC3 as i32);
-                
-                // Update light color
-                let light_color = std::ffi::CString::new("lights[0].color").unwrap();
-                let color_loc = ffi::GetShaderLocation(*shader, light_color.as_ptr());
-                let color = [
-                    state.light_source.color.r as f32 / 255.0,
-                    state.light_source.color.g as f32 / 255.0,
-                    state.light_source.color.b as f32 / 255.0,
-                    state.light_source.color.a as f32 / 255.0
-                ];
-                ffi::SetShaderValue(*shader, color_loc, color.as_ptr() as *const std::ffi::c_void, ffi::ShaderUniformDataType::SHADER_UNIFORM_VEC4 as i32);
-                
-                // Update light enabled state
-                let light_enabled = std::ffi::CString::new("lights[0].enabled").unwrap();
-                let enabled_loc = ffi::GetShaderLocation(*shader, light_enabled.as_ptr());
-                let enabled = [if state.light_source.enabled { 1i32 } else { 0i32 }];
-                ffi::SetShaderValue(*shader, enabled_loc, enabled.as_ptr() as *const std::ffi::c_void, ffi::ShaderUniformDataType::SHADER_UNIFORM_INT as i32);
+                ffi::BeginTextureMode(targets.scene);
             }
+            d.clear_background(Color::BLANK);
+        }
+        None => d.clear_background(Color::BLACK),
+    }
+    
+    // Shadows replace the plain lighting shader when the shadow pass is available
+    let scene_shader_type = match (shadow_caster, light_view_projection, render_state.shadow_map) {
+        (Some(caster), Some(light_view_projection), Some(shadow_map)) => {
+            let shader = render_state.shaders.get_mut(&ShaderType::Shadowmap).unwrap();
+            update_shadow_uniforms(&state.lights[caster], shader, shadow_map, light_view_projection);
+            set_light_uniforms(shader, &frame_lights(state, alpha, Some(caster)));
+            ShaderType::Shadowmap
+        }
+        _ => {
+            let shader = render_state.shaders.get_mut(&ShaderType::Lighting).unwrap();
+            set_uniform_floats(shader, "ambient", &AMBIENT_LIGHT);
+            set_light_uniforms(shader, &frame_lights(state, alpha, None));
+            ShaderType::Lighting
+        }
+    };
+
+    // The scene is drawn once per split-screen pane, or once over the whole screen
+    let views = frame_views(state, alpha, screen_width, screen_height);
+    let screen_size = (screen_width, screen_height);
+    let target_size = match post_targets {
+        Some(targets) => (targets.scene.texture.width, targets.scene.texture.height),
+        None => unsafe { (ffi::GetRenderWidth(), ffi::GetRenderHeight()) },
+    };
+    let split = views.len() > 1;
+    for (camera, pane, _) in &views {
+        // Uniforms only reach the GPU with the next batch, the previous pane was flushed when its 3D mode ended
+        let shader = render_state.shaders.get_mut(&scene_shader_type).unwrap();
+        set_uniform_floats(shader, "viewPos", &[camera.position.x, camera.position.y, camera.position.z]);
+        let scene_shader = shader.shader;
+        if split {
+            set_pane_viewport(*pane, screen_size, target_size);
+        }
+
+        let mut d3 = d.begin_mode3D(*camera);
+        if split {
+            set_pane_projection(camera, *pane);
         }
-        
         unsafe {
-            ffi::BeginShaderMode(state.shaders[&ShaderType::Lighting]);
+            ffi::BeginShaderMode(scene_shader);
         }
-        draw_players(state, &mut d3);
-        draw_voxels(state, &mut d3);
+        draw_players(state, alpha, &mut d3);
+        draw_chunk_meshes(render_state, scene_shader);
         unsafe {
             ffi::EndShaderMode();
         }
@@ -79,34 +310,30 @@ pub fn render(state: &GameState, rl: &mut RaylibHandle, thread: &RaylibThread) {
         // Draw debug elements after shader mode to make them independent of lighting
         if state.editor.active {
             draw_debug_bounding_boxes(state, &mut d3);
-            draw_light_source(state, &mut d3);
+            draw_light_sources(state, &mut d3);
             draw_world_grid(state, &mut d3);
+            draw_spawn_points(state, &mut d3);
             draw_hovered_voxel(state, &mut d3);
         }
     }
-    
-    // End outline shader if it was applied
-    if state.shaders.get(&ShaderType::Outline).is_some() {
+    if split {
         unsafe {
-            ffi::EndShaderMode();
+            ffi::rlViewport(0, 0, target_size.0, target_size.1);
         }
     }
     
-    let screen_width = d.get_screen_width();
-    let screen_height = d.get_screen_height();
+    if let Some(targets) = post_targets {
+        unsafe {
+            ffi::EndTextureMode();
+        }
+        d.clear_background(Color::BLACK);
+        apply_post_effects(render_state, targets, &mut d);
+    }
     
-    // Draw welcome message if no players are ready
-    if state.players.is_empty() {
-        let text = "Press SPACE (keyboard) or move stick (gamepad) to join the game";
-        let text_width = unsafe { ffi::MeasureText(text.as_ptr() as *const i8, 20) };
-        d.draw_text(
-            text,
-            (screen_width - text_width) / 2,
-            screen_height / 2,
-            20,
-            Color::WHITE
-        );
+    if split {
+        draw_pane_labels(state, &views, &mut d);
     }
+    draw_match_hud(state, &mut d);
     
     // Draw FPS
     let fps = d.get_fps();
@@ -118,30 +345,57 @@ pub fn render(state: &GameState, rl: &mut RaylibHandle, thread: &RaylibThread) {
         
         for (id, player) in &state.players {
             let debug_text = format!(
-                "Player {}: Pos({:.1}, {:.1}, {:.1})",
-                id, player.position.x, player.position.y, player.position.z
+                "Player {}: Pos({:.1}, {:.1}, {:.1}) HP {:.0}{}",
+                id, player.position.x, player.position.y, player.position.z, player.health,
+                if player.is_alive { "" } else { " (eliminated)" }
             );
             d.draw_text(&debug_text, 10, y_offset, 20, Color::GREEN);
             y_offset += 30;
         }
         
         // Draw voxel count
-        let voxel_count = state.world.voxels.len();
+        let voxel_count = iter_voxels(&state.world).count();
         d.draw_text(&format!("Voxels: {}", voxel_count), 10, y_offset, 20, Color::GREEN);
         y_offset += 30;
         
-        // Draw light source info
-        let light_text = format!(
-            "Light: Pos({:.1}, {:.1}, {:.1}) {}",
-            state.light_source.position.x,
-            state.light_source.position.y,
-            state.light_source.position.z,
-            if state.light_source.enabled { "ON" } else { "OFF" }
-        );
+        // Draw the selected light, L / Tab / keypad arrows / Page Up / Down / T edit the lights
+        let light_text = match state.editor.selected_light.and_then(|index| state.lights.get(index).map(|light| (index, light))) {
+            Some((index, light)) => format!(
+                "Light {}/{}: {} Pos({:.1}, {:.1}, {:.1}) {}",
+                index + 1,
+                state.lights.len(),
+                light_type_name(light.light_type),
+                light.position.x,
+                light.position.y,
+                light.position.z,
+                if light.enabled { "ON" } else { "OFF" }
+            ),
+            None => format!("Lights: {}/{} (Tab selects, L adds or deletes)", state.lights.len(), MAX_LIGHTS),
+        };
         d.draw_text(&light_text, 10, y_offset, 20, Color::YELLOW);
+        y_offset += 30;
+
+        // Draw map file and last editor action result
+        d.draw_text(&format!("Map: {}", state.editor.map_path), 10, y_offset, 20, Color::GREEN);
+        y_offset += 30;
+        d.draw_text(&format!("Tool: {}", editor_tool_name(state.editor.tool)), 10, y_offset, 20, Color::GREEN);
+        y_offset += 30;
+        let history_text = format!("History: {} undo, {} redo", state.editor.undo_stack.len(), state.editor.redo_stack.len());
+        d.draw_text(&history_text, 10, y_offset, 20, Color::GREEN);
+        y_offset += 30;
+        if let Some(arena) = state.arena {
+            let arena_text = format!("Arena: {} (seed {})", arena_preset_name(arena.preset), arena.seed);
+            d.draw_text(&arena_text, 10, y_offset, 20, Color::GREEN);
+            y_offset += 30;
+        }
+        if let Some(message) = &state.editor.status_message {
+            d.draw_text(message, 10, y_offset, 20, Color::YELLOW);
+        }
+
+        draw_block_palette(state, &mut d);
 
         // Draw all controls in bottom left
-        let mut control_y = screen_height - 200;
+        let mut control_y = screen_height - 410;
         
         // Camera controls
         d.draw_text("Camera Controls:", 10, control_y, 20, Color::WHITE);
@@ -157,54 +411,423 @@ pub fn render(state: &GameState, rl: &mut RaylibHandle, thread: &RaylibThread) {
         d.draw_text("Right click to switch build/remove mode", 10, control_y, 20, Color::WHITE);
         control_y += 25;
         d.draw_text("Left click to place/remove voxel", 10, control_y, 20, Color::WHITE);
+        control_y += 25;
+        d.draw_text("P to toggle spawn point on hovered cell", 10, control_y, 20, Color::WHITE);
+        control_y += 25;
+        d.draw_text("1-5 - Single, box, hollow box, line, flood fill", 10, control_y, 20, Color::WHITE);
+        control_y += 25;
+        d.draw_text("Q / E - Previous / next block", 10, control_y, 20, Color::WHITE);
+        control_y += 25;
+        d.draw_text("Ctrl+Z - Undo, Ctrl+Y - Redo", 10, control_y, 20, Color::WHITE);
+        control_y += 35;
+
+        // Map controls
+        d.draw_text("Map Controls:", 10, control_y, 20, Color::WHITE);
+        control_y += 25;
+        d.draw_text("F5 - Save map, F9 - Load map", 10, control_y, 20, Color::WHITE);
+        control_y += 25;
+        d.draw_text("F6 - Next arena seed, F7 - Next arena preset", 10, control_y, 20, Color::WHITE);
+        control_y += 25;
+        d.draw_text("F8 - Check that the arena is connected", 10, control_y, 20, Color::WHITE);
         control_y += 35;
 
         // General controls
         d.draw_text("Press M to toggle debug mode", 10, control_y, 20, Color::WHITE);
+    } else if let Some(status) = &render_state.replay_status {
+        // Replays are watched, not played
+        d.draw_text(status, 10, screen_height - 85, 20, Color::YELLOW);
+        d.draw_text("Space - Pause, Left / Right - Seek, Up / Down - Speed, Home - Restart", 10, screen_height - 60, 20, Color::WHITE);
+        d.draw_text("WASD - Move camera, Middle Mouse Button - Rotate, Mouse Wheel - Zoom", 10, screen_height - 35, 20, Color::WHITE);
     } else {
         // Draw controls in bottom left when not in debug mode
-        d.draw_text("Move player by WASD or Gamepad", 10, screen_height - 60, 20, Color::WHITE);
+        let camera_text = format!("C - Camera: {}", camera_mode_name(state.camera_state.mode));
+        d.draw_text(&camera_text, 10, screen_height - 135, 20, Color::WHITE);
+        if state.match_state.phase == MatchPhase::Lobby {
+            let settings = &state.bot_settings;
+            let bot_text = format!(
+                "B - Fill with bots: {}, V - Bot difficulty: {}",
+                if settings.fill_lobby { "on" } else { "off" },
+                bot_difficulty_name(settings.difficulty)
+            );
+            d.draw_text(&bot_text, 10, screen_height - 160, 20, Color::WHITE);
+        }
+        d.draw_text("Move player by WASD, arrow keys or Gamepad", 10, screen_height - 60, 20, Color::WHITE);
+        d.draw_text("Ready up with Space / Enter (keyboard) or A (gamepad)", 10, screen_height - 110, 20, Color::WHITE);
         d.draw_text("Dash with Shift (keyboard) or Right Trigger (gamepad)", 10, screen_height - 85, 20, Color::WHITE);
-        d.draw_text("Press M to toggle debug mode", 10, screen_height - 35, 20, Color::WHITE);
+        d.draw_text("Press M to toggle debug mode, N to mute sound", 10, screen_height - 35, 20, Color::WHITE);
     }
 }
 
-fn draw_players(state: &GameState, d: &mut RaylibMode3D<RaylibDrawHandle>) {
-    for player in state.players.values() {
-        // Draw solid cube with current color
+// Phase banner in the middle of the screen and per-player ready state or round wins in the top right
+fn draw_match_hud(state: &GameState, d: &mut RaylibDrawHandle) {
+    let screen_width = d.get_screen_width();
+    let screen_height = d.get_screen_height();
+    let match_state = &state.match_state;
+
+    let ready_count = state.players.values().filter(|player| player.is_ready).count();
+    let banner = match match_state.phase {
+        MatchPhase::Lobby if state.players.is_empty() => {
+            "Press SPACE / ENTER (keyboard) or move stick (gamepad) to join the game".to_string()
+        }
+        MatchPhase::Lobby if state.players.len() < MIN_PLAYERS_TO_START => {
+            format!("Waiting for players ({}/{})", state.players.len(), MIN_PLAYERS_TO_START)
+        }
+        MatchPhase::Lobby => format!("SPACE / A to toggle ready ({}/{} ready)", ready_count, state.players.len()),
+        MatchPhase::Countdown => format!("Round {} starts in {}", match_state.round, match_state.phase_timer.ceil() as i32),
+        MatchPhase::Playing => String::new(),
+        MatchPhase::RoundOver => match match_state.round_winner {
+            Some(id) => format!("Player {} wins round {}", id, match_state.round),
+            None => format!("Round {} is a draw", match_state.round),
+        },
+        MatchPhase::MatchOver => match match_state.match_winner {
+            Some(id) => format!("Player {} wins the match!", id),
+            None => "Match over".to_string(),
+        },
+    };
+    if !banner.is_empty() {
+        let text_width = d.measure_text(&banner, 20);
+        d.draw_text(&banner, (screen_width - text_width) / 2, screen_height / 2, 20, Color::WHITE);
+    }
+
+    let mut ids: Vec<&i32> = state.players.keys().collect();
+    ids.sort();
+    let mut y_offset = 10;
+    for id in ids {
+        let player = &state.players[id];
+        let name = if state.bots.contains_key(id) { "Bot" } else { "Player" };
+        let text = if match_state.phase == MatchPhase::Lobby {
+            format!("{} {}: {}", name, id, if player.is_ready { "ready" } else { "not ready" })
+        } else {
+            let wins = match_state.round_wins.get(id).copied().unwrap_or(0);
+            format!("{} {}: {}/{} rounds", name, id, wins, match_state.rounds_to_win)
+        };
+        let text_width = d.measure_text(&text, 20);
+        d.draw_text(&text, screen_width - text_width - 10, y_offset, 20, player.original_color);
+        y_offset += 25;
+    }
+}
+
+// One swatch per block along the top of the screen, the selected block is outlined and named
+fn draw_block_palette(state: &GameState, d: &mut RaylibDrawHandle) {
+    let swatch_size = 24;
+    let spacing = 6;
+    let palette = &state.blocks.blocks[1..];
+    let width = palette.len() as i32 * (swatch_size + spacing) - spacing;
+    let mut x = (d.get_screen_width() - width) / 2;
+    let y = 10;
+    for block in palette {
+        d.draw_rectangle(x, y, swatch_size, swatch_size, block.color);
+        if block.id == state.editor.selected_block {
+            d.draw_rectangle_lines(x - 2, y - 2, swatch_size + 4, swatch_size + 4, Color::YELLOW);
+            let name_width = d.measure_text(&block.name, 20);
+            d.draw_text(&block.name, x + (swatch_size - name_width) / 2, y + swatch_size + 6, 20, Color::YELLOW);
+        }
+        x += swatch_size + spacing;
+    }
+}
+
+fn interpolate_camera(previous: Camera3D, mut camera: Camera3D, alpha: f32) -> Camera3D {
+    camera.position = previous.position.lerp(camera.position, alpha);
+    camera.target = previous.target.lerp(camera.target, alpha);
+    camera
+}
+
+// Cameras to draw with their pane in screen coordinates and the player they follow: one per player while the
+// screen is split, otherwise the shared camera over the whole screen
+fn frame_views(state: &GameState, alpha: f32, width: i32, height: i32) -> Vec<(Camera3D, Rectangle, Option<i32>)> {
+    let camera_state = &state.camera_state;
+    if !camera_state.split || state.editor.active || camera_state.player_cameras.len() < 2 {
+        let camera = interpolate_camera(camera_state.previous_camera, camera_state.camera, alpha);
+        return vec![(camera, Rectangle::new(0.0, 0.0, width as f32, height as f32), None)];
+    }
+
+    let panes = pane_layout(camera_state.player_cameras.len(), width, height);
+    camera_state.player_cameras.iter().zip(panes)
+        .map(|(view, pane)| (interpolate_camera(view.previous_camera, view.camera, alpha), pane, Some(view.player_id)))
+        .collect()
+}
+
+// Two panes side by side, three or four in quarters of the screen
+fn pane_layout(count: usize, width: i32, height: i32) -> Vec<Rectangle> {
+    let (columns, rows) = if count <= 2 { (count.max(1), 1) } else { (2, 2) };
+    let pane_width = width as f32 / columns as f32;
+    let pane_height = height as f32 / rows as f32;
+    (0..count)
+        .map(|index| Rectangle::new(
+            (index % columns) as f32 * pane_width,
+            (index / columns) as f32 * pane_height,
+            pane_width,
+            pane_height,
+        ))
+        .collect()
+}
+
+// Limit drawing to a pane of the framebuffer, which may have more pixels than the screen on high DPI displays
+fn set_pane_viewport(pane: Rectangle, screen_size: (i32, i32), target_size: (i32, i32)) {
+    let scale_x = target_size.0 as f32 / screen_size.0 as f32;
+    let scale_y = target_size.1 as f32 / screen_size.1 as f32;
+    // OpenGL counts viewport rows from the bottom
+    let bottom = screen_size.1 as f32 - pane.y - pane.height;
+    unsafe {
+        ffi::rlViewport(
+            (pane.x * scale_x).round() as i32,
+            (bottom * scale_y).round() as i32,
+            (pane.width * scale_x).round() as i32,
+            (pane.height * scale_y).round() as i32,
+        );
+    }
+}
+
+// BeginMode3D sets up the projection for the aspect ratio of the whole framebuffer, replace it with the pane's
+fn set_pane_projection(camera: &Camera3D, pane: Rectangle) {
+    unsafe {
+        let near = ffi::rlGetCullDistanceNear();
+        let far = ffi::rlGetCullDistanceFar();
+        let top = near * (camera.fovy as f64 / 2.0).to_radians().tan();
+        let right = top * (pane.width / pane.height) as f64;
+        ffi::rlMatrixMode(ffi::RL_PROJECTION as i32);
+        ffi::rlLoadIdentity();
+        ffi::rlFrustum(-right, right, -top, top, near, far);
+        ffi::rlMatrixMode(ffi::RL_MODELVIEW as i32);
+    }
+}
+
+// Name of the followed player in the corner of every pane, with lines between the panes
+fn draw_pane_labels(state: &GameState, views: &[(Camera3D, Rectangle, Option<i32>)], d: &mut RaylibDrawHandle) {
+    for (_, pane, player_id) in views {
+        d.draw_rectangle_lines_ex(*pane, 2.0, Color::BLACK);
+        let Some(id) = player_id else {
+            continue;
+        };
+        let color = state.players.get(id).map_or(Color::WHITE, |player| player.original_color);
+        d.draw_text(&format!("Player {}", id), pane.x as i32 + 10, pane.y as i32 + 40, 20, color);
+    }
+}
+
+// Index of the light casting shadows, the first enabled directional light
+fn shadow_light(state: &GameState) -> Option<usize> {
+    state.lights.iter().position(|light| light.enabled && light.light_type == LightType::Directional)
+}
+
+// Render the depth of the scene as seen from the light, returns the light's view-projection matrix
+fn draw_shadow_map(state: &GameState, render_state: &RenderState, light: &LightSource, alpha: f32, d: &mut RaylibDrawHandle) -> Option<Matrix> {
+    let shadow_map = render_state.shadow_map?;
+    unsafe {
+        ffi::BeginTextureMode(shadow_map);
+    }
+    d.clear_background(Color::WHITE);
+
+    let (view, projection) = {
+        let mut d3 = d.begin_mode3D(light_camera(state, light));
+        let matrices = unsafe { (Matrix::from(ffi::rlGetMatrixModelview()), Matrix::from(ffi::rlGetMatrixProjection())) };
+        // Only depth is written, the default shader is enough and keeps the shadow map from sampling itself
+        let depth_shader = unsafe { ffi::Shader { id: ffi::rlGetShaderIdDefault(), locs: ffi::rlGetShaderLocsDefault() } };
+        draw_players(state, alpha, &mut d3);
+        draw_chunk_meshes(render_state, depth_shader);
+        matrices
+    };
+
+    unsafe {
+        ffi::EndTextureMode();
+    }
+    Some(view * projection)
+}
+
+// Orthographic camera looking along the light direction at the middle of the world, pulled back far
+// enough that the whole world lies in front of it
+fn light_camera(state: &GameState, light: &LightSource) -> Camera3D {
+    let world = &state.world;
+    let extent = Vector3::new(world.width as f32, world.height as f32, world.depth as f32) * world.voxel_size;
+    let reach = extent.length();
+    // Voxels are centered on their coordinates, so the world starts half a voxel below zero
+    let center = (extent - Vector3::one() * world.voxel_size) * 0.5;
+    let direction = (light.target - light.position).normalized();
+    let up = if direction.x.abs() < 0.001 && direction.z.abs() < 0.001 { Vector3::forward() } else { Vector3::up() };
+    Camera3D::orthographic(center - direction * reach, center, up, reach)
+}
+
+// Lights for this frame with their brightness: the enabled map lights except `skip`, then a glow following
+// every living player while shader slots are left
+fn frame_lights(state: &GameState, alpha: f32, skip: Option<usize>) -> Vec<(LightSource, f32)> {
+    let mut lights: Vec<(LightSource, f32)> = state.lights.iter().enumerate()
+        .filter(|(index, light)| light.enabled && Some(*index) != skip)
+        .map(|(_, light)| (*light, 1.0))
+        .collect();
+
+    if PLAYER_GLOW_INTENSITY > 0.0 {
+        // By ID so players keep their slot from frame to frame
+        let mut ids: Vec<i32> = state.players.keys().copied().collect();
+        ids.sort();
+        for id in ids {
+            let player = &state.players[&id];
+            if !player.is_alive {
+                continue;
+            }
+            let glow = LightSource {
+                light_type: LightType::Point,
+                position: player.previous_position.lerp(player.position, alpha),
+                color: player.original_color,
+                radius: PLAYER_GLOW_RADIUS,
+                ..LightSource::default()
+            };
+            let intensity = if player.is_dashing { DASH_FLASH_INTENSITY } else { PLAYER_GLOW_INTENSITY };
+            lights.push((glow, intensity));
+        }
+    }
+
+    lights.truncate(MAX_LIGHTS);
+    lights
+}
+
+// Fill the shader's light array by index, slots without a light are disabled
+fn set_light_uniforms(shader: &mut ManagedShader, lights: &[(LightSource, f32)]) {
+    for index in 0..MAX_LIGHTS {
+        let field = |name: &str| format!("lights[{}].{}", index, name);
+        let Some((light, intensity)) = lights.get(index) else {
+            set_uniform_int(shader, &field("enabled"), 0);
+            continue;
+        };
+        set_uniform_int(shader, &field("enabled"), 1);
+        set_uniform_int(shader, &field("type"), light.light_type as i32);
+        set_uniform_floats(shader, &field("position"), &[light.position.x, light.position.y, light.position.z]);
+        set_uniform_floats(shader, &field("target"), &[light.target.x, light.target.y, light.target.z]);
+        let color = [
+            light.color.r as f32 / 255.0 * intensity,
+            light.color.g as f32 / 255.0 * intensity,
+            light.color.b as f32 / 255.0 * intensity,
+            light.color.a as f32 / 255.0
+        ];
+        set_uniform_floats(shader, &field("color"), &color);
+        set_uniform_floats(shader, &field("radius"), &[light.radius]);
+        set_uniform_floats(shader, &field("cutoff"), &[light.cone_angle.to_radians().cos()]);
+    }
+}
+
+// The shadow caster is passed on its own, the remaining lights go through `set_light_uniforms`
+fn update_shadow_uniforms(light: &LightSource, shader: &mut ManagedShader, shadow_map: ffi::RenderTexture, light_view_projection: Matrix) {
+    let direction = (light.target - light.position).normalized();
+    set_uniform_floats(shader, "ambient", &AMBIENT_LIGHT);
+    set_uniform_floats(shader, "lightDir", &[direction.x, direction.y, direction.z]);
+    let color = [
+        light.color.r as f32 / 255.0,
+        light.color.g as f32 / 255.0,
+        light.color.b as f32 / 255.0,
+        light.color.a as f32 / 255.0
+    ];
+    set_uniform_floats(shader, "lightColor", &color);
+    set_uniform_matrix(shader, "lightVP", light_view_projection);
+    set_uniform_int(shader, "shadowMapResolution", SHADOW_MAP_RESOLUTION);
+
+    // Bind the depth texture to its own unit, materials only use the low ones
+    set_uniform_int(shader, "shadowMap", SHADOW_MAP_SLOT);
+    unsafe {
+        ffi::rlActiveTextureSlot(SHADOW_MAP_SLOT);
+        ffi::rlEnableTexture(shadow_map.depth.id);
+        ffi::rlActiveTextureSlot(0);
+    }
+}
+
+fn draw_players(state: &GameState, alpha: f32, d: &mut RaylibMode3D<RaylibDrawHandle>) {
+    for player in state.players.values().filter(|player| player.is_alive) {
+        let position = player.previous_position.lerp(player.position, alpha);
+
+        // Draw solid cube with current color between the last two simulated positions
         d.draw_cube(
-            player.position,
+            position,
             player.size.x,
             player.size.y,
             player.size.z,
             player.color,
         );
+
+        // Draw health bar above the player, shrinking from full width and fading to red
+        let fraction = (player.health / PLAYER_MAX_HEALTH).clamp(0.0, 1.0);
+        let bar_width = 0.8;
+        let bar_position = Vector3::new(
+            position.x - bar_width * (1.0 - fraction) / 2.0,
+            position.y + player.size.y / 2.0 + 0.3,
+            position.z,
+        );
+        let bar_color = Color {
+            r: (255.0 * (1.0 - fraction)) as u8,
+            g: (255.0 * fraction) as u8,
+            b: 0,
+            a: 255,
+        };
+        d.draw_cube(bar_position, bar_width * fraction, 0.08, 0.08, bar_color);
     }
 }
 
-fn draw_voxels(state: &GameState, d: &mut RaylibMode3D<RaylibDrawHandle>) {
-    // Draw all voxels
-    for voxel in &state.world.voxels {
-        let position = Vector3::new(
-            voxel.position.x * VOXEL_SIZE,
-            voxel.position.y * VOXEL_SIZE,
-            voxel.position.z * VOXEL_SIZE,
-        );
-        let color = match voxel.voxel_type {
-            VoxelType::Empty => Color::BLANK,
-            VoxelType::Ground => Color::GRAY,
-            VoxelType::Wall => Color::DARKGRAY
+// Remesh the chunks whose version changed since their meshes were built, a resized world rebuilds everything
+fn update_chunk_meshes(render_state: &mut RenderState, state: &GameState) {
+    let versions = &state.world.chunk_versions;
+    if render_state.chunk_meshes.len() != versions.len() {
+        for meshes in render_state.chunk_meshes.drain(..) {
+            unload_chunk_meshes(meshes);
+        }
+        render_state.chunk_meshes.resize(versions.len(), ChunkMeshes::default());
+    }
+
+    for (index, version) in versions.iter().enumerate() {
+        if render_state.chunk_meshes[index].version == *version {
+            continue;
+        }
+        unload_chunk_meshes(std::mem::take(&mut render_state.chunk_meshes[index]));
+        let mesh = mesh_chunk(&state.world, &state.blocks, chunk_position(&state.world, index));
+        render_state.chunk_meshes[index] = ChunkMeshes {
+            version: *version,
+            opaque: mesh.opaque.iter().filter_map(upload_mesh).collect(),
+            translucent: mesh.translucent.iter().filter_map(upload_mesh).collect(),
         };
+    }
+}
 
-        // Draw solid cube for non-empty voxels
-        if voxel.voxel_type != VoxelType::Empty {
-            d.draw_cube(
-                position,
-                VOXEL_SIZE,
-                VOXEL_SIZE,
-                VOXEL_SIZE,
-                color,
-            );
+fn upload_mesh(data: &MeshData) -> Option<ffi::Mesh> {
+    if data.indices.is_empty() {
+        return None;
+    }
+    let vertex_count = data.vertices.len() / 3;
+    unsafe {
+        let mut mesh: ffi::Mesh = std::mem::zeroed();
+        mesh.vertexCount = vertex_count as i32;
+        mesh.triangleCount = (data.indices.len() / 3) as i32;
+        mesh.vertices = copy_to_raylib(&data.vertices);
+        mesh.texcoords = copy_to_raylib(&vec![0.0f32; vertex_count * 2]);
+        mesh.normals = copy_to_raylib(&data.normals);
+        mesh.colors = copy_to_raylib(&data.colors);
+        mesh.indices = copy_to_raylib(&data.indices);
+        ffi::UploadMesh(&mut mesh, false);
+        Some(mesh)
+    }
+}
+
+// UnloadMesh frees the vertex arrays with raylib's allocator, so they have to be allocated by it too
+unsafe fn copy_to_raylib<T: Copy>(values: &[T]) -> *mut T {
+    let pointer = ffi::MemAlloc(std::mem::size_of_val(values) as u32) as *mut T;
+    std::ptr::copy_nonoverlapping(values.as_ptr(), pointer, values.len());
+    pointer
+}
+
+fn unload_chunk_meshes(meshes: ChunkMeshes) {
+    for mesh in meshes.opaque.into_iter().chain(meshes.translucent) {
+        unsafe {
+            ffi::UnloadMesh(mesh);
+        }
+    }
+}
+
+// Opaque chunks first so see-through blocks blend over everything behind them
+fn draw_chunk_meshes(render_state: &RenderState, shader: ffi::Shader) {
+    let Some(mut material) = render_state.voxel_material else {
+        return;
+    };
+    material.shader = shader;
+    let transform: ffi::Matrix = Matrix::identity().into();
+    let opaque = render_state.chunk_meshes.iter().flat_map(|meshes| meshes.opaque.iter());
+    let translucent = render_state.chunk_meshes.iter().flat_map(|meshes| meshes.translucent.iter());
+    for mesh in opaque.chain(translucent) {
+        unsafe {
+            ffi::DrawMesh(*mesh, material, transform);
         }
     }
 }
@@ -264,27 +887,37 @@ fn draw_debug_bounding_boxes(state: &GameState, d: &mut RaylibMode3D<RaylibDrawH
     }
 }
 
-fn draw_light_source(state: &GameState, d: &mut RaylibMode3D<RaylibDrawHandle>) {
-    if state.light_source.enabled {
-        // Draw a small sphere at the light position
-        d.draw_sphere(state.light_source.position, 0.5, LIGHT_COLOR);
-        
-        // Draw a wireframe sphere around it
-        d.draw_sphere_wires(state.light_source.position, 0.5, 8, 8, Color::YELLOW);
-        
-        // Draw a line to show light direction (from position to target)
-        unsafe {
-            ffi::DrawLine3D(
-                ffi::Vector3 { x: state.light_source.position.x, y: state.light_source.position.y, z: state.light_source.position.z },
-                ffi::Vector3 { x: state.light_source.target.x, y: state.light_source.target.y, z: state.light_source.target.z },
-                ffi::Color { r: LIGHT_COLOR.r, g: LIGHT_COLOR.g, b: LIGHT_COLOR.b, a: LIGHT_COLOR.a }
-            );
+// Every map light, the one selected in the editor drawn larger
+fn draw_light_sources(state: &GameState, d: &mut RaylibMode3D<RaylibDrawHandle>) {
+    for (index, light) in state.lights.iter().enumerate() {
+        let selected = state.editor.selected_light == Some(index);
+        let size = if selected { 0.7 } else { 0.5 };
+
+        // Disabled lights are only drawn as a wireframe
+        if light.enabled {
+            d.draw_sphere(light.position, size, light.color);
+        }
+        d.draw_sphere_wires(light.position, size, 8, 8, if selected { Color::ORANGE } else { Color::YELLOW });
+
+        // Draw a line to show where directional and spot lights shine
+        if light.light_type != LightType::Point {
+            d.draw_line_3D(light.position, light.target, LIGHT_COLOR);
         }
     }
 }
 
+fn draw_spawn_points(state: &GameState, d: &mut RaylibMode3D<RaylibDrawHandle>) {
+    for spawn in &state.world.spawn_points {
+        // Draw a flat marker where the player's feet will be
+        let base = Vector3::new(spawn.x, spawn.y - state.world.voxel_size / 2.0, spawn.z);
+        d.draw_cylinder_wires(base, 0.4, 0.4, 0.05, 12, Color::SKYBLUE);
+        d.draw_sphere(Vector3::new(spawn.x, spawn.y, spawn.z), 0.1, Color::SKYBLUE);
+    }
+}
+
+// Outline every cell the current tool would change
 fn draw_hovered_voxel(state: &GameState, d: &mut RaylibMode3D<RaylibDrawHandle>) {
-    if let Some((x, y, z)) = state.editor.hovered_voxel {
+    for (x, y, z) in brush_preview(state) {
         let voxel_size = state.world.voxel_size;
         let position = Vector3::new(
             x as f32 * voxel_size,
@@ -0,0 +1,521 @@
+use raylib::prelude::*;
+use std::fmt;
+use std::fs;
+use crate::state::{BotDifficulty, GameState, InputFrame, PlayerInputFrame, Replay, ReplayInputFrame, ReplayPlayback};
+use crate::logic::{continue_chunk_versions, init, replace_world, seed_simulation, update};
+use crate::bots::{restore_bot, update_bots};
+use crate::network::{
+    NetworkError, expect_end, read_blocks, read_bool, read_bytes, read_f32, read_i32, read_light, read_u32, read_u8, read_world,
+    write_blocks, write_bool, write_f32, write_i32, write_light, write_u32, write_u8, write_world,
+};
+use crate::format::{FileFormat, FormatError, check_version, missing_header};
+use crate::config::{
+    FIRST_BOT_ID, MAX_LIGHTS, REPLAY_CAMERA_SPEED, REPLAY_CHECKPOINT_INTERVAL, REPLAY_MAX_SPEED, REPLAY_MAX_TICKS, REPLAY_MIN_SPEED,
+    REPLAY_SEEK_TICKS, TICK_RATE,
+};
+
+pub const REPLAY_FORMAT: FileFormat = FileFormat {
+    kind: "replay file",
+    header: "VBRP",
+    version: 2, // Version 2 stores the player ID of every starting bot
+};
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Format(FormatError),
+    Malformed(&'static str),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Format(error) => write!(f, "{}", error),
+            ReplayError::Malformed(reason) => write!(f, "corrupt replay: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<FormatError> for ReplayError {
+    fn from(error: FormatError) -> Self {
+        ReplayError::Format(error)
+    }
+}
+
+impl From<std::io::Error> for ReplayError {
+    fn from(error: std::io::Error) -> Self {
+        ReplayError::Format(FormatError::Io(error))
+    }
+}
+
+// The shared readers only fail on malformed data
+impl From<NetworkError> for ReplayError {
+    fn from(error: NetworkError) -> Self {
+        match error {
+            NetworkError::Malformed(reason) => ReplayError::Malformed(reason),
+            _ => ReplayError::Malformed("unreadable data"),
+        }
+    }
+}
+
+// Start recording a session, to be called before its first tick. The simulation is reseeded so the
+// recorded seed is where its random choices start from.
+pub fn start_recording(state: &mut GameState) -> Replay {
+    seed_simulation(state, rand::random());
+    let mut bot_ids: Vec<i32> = state.bots.keys().copied().collect();
+    bot_ids.sort();
+    Replay {
+        seed: state.rng.seed,
+        blocks: state.blocks.clone(),
+        world: state.world.clone(),
+        lights: state.lights.clone(),
+        bot_settings: state.bot_settings,
+        bots: bot_ids.iter().map(|id| (*id, state.bots[id].difficulty)).collect(),
+        ticks: Vec::new(),
+    }
+}
+
+// Keep the part of a tick's input the simulation reads, to be called before `update_bots`.
+// Bots are left out, playback runs them again.
+pub fn record_tick(replay: &mut Replay, input: &InputFrame) {
+    replay.ticks.push(InputFrame {
+        players: input.players.iter()
+            .filter(|(id, _)| **id < FIRST_BOT_ID)
+            .map(|(id, frame)| (*id, *frame))
+            .collect(),
+        toggle_bot_fill: input.toggle_bot_fill,
+        cycle_bot_difficulty: input.cycle_bot_difficulty,
+        ..InputFrame::default()
+    });
+}
+
+pub fn save_replay(replay: &Replay, path: &str) -> Result<(), ReplayError> {
+    fs::write(path, encode_replay(replay))?;
+    Ok(())
+}
+
+pub fn load_replay(path: &str) -> Result<Replay, ReplayError> {
+    decode_replay(&fs::read(path)?)
+}
+
+// Header, starting state, then the ticks as runs of identical input since held keys and idle devices
+// repeat for long stretches
+pub fn encode_replay(replay: &Replay) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(REPLAY_FORMAT.header.as_bytes());
+    write_u32(&mut out, REPLAY_FORMAT.version);
+    out.extend_from_slice(&replay.seed.to_le_bytes());
+    write_blocks(&mut out, &replay.blocks);
+    write_world(&mut out, &replay.world);
+    write_u8(&mut out, replay.lights.len() as u8);
+    for light in &replay.lights {
+        write_light(&mut out, light);
+    }
+    write_bool(&mut out, replay.bot_settings.fill_lobby);
+    write_u32(&mut out, replay.bot_settings.lobby_size as u32);
+    write_u8(&mut out, bot_difficulty_id(replay.bot_settings.difficulty));
+    write_u8(&mut out, replay.bots.len() as u8);
+    for (id, difficulty) in &replay.bots {
+        write_i32(&mut out, *id);
+        write_u8(&mut out, bot_difficulty_id(*difficulty));
+    }
+
+    let mut runs: Vec<(u32, Vec<u8>)> = Vec::new();
+    for tick in &replay.ticks {
+        let bytes = encode_tick(tick);
+        match runs.last_mut() {
+            Some((length, run)) if *run == bytes => *length += 1,
+            _ => runs.push((1, bytes)),
+        }
+    }
+    write_u32(&mut out, runs.len() as u32);
+    for (length, bytes) in runs {
+        write_u32(&mut out, length);
+        out.extend_from_slice(&bytes);
+    }
+    out
+}
+
+pub fn decode_replay(mut data: &[u8]) -> Result<Replay, ReplayError> {
+    let data = &mut data;
+    let header = REPLAY_FORMAT.header.as_bytes();
+    if data.len() < header.len() || read_bytes(data, header.len())? != header {
+        return Err(missing_header(&REPLAY_FORMAT).into());
+    }
+    let version = read_u32(data)?;
+    check_version(&REPLAY_FORMAT, version)?;
+
+    let seed_bytes = read_bytes(data, 8)?;
+    let seed = u64::from_le_bytes(seed_bytes.try_into().unwrap());
+    let blocks = read_blocks(data)?;
+    let world = read_world(data, &blocks)?;
+    let light_count = read_u8(data)? as usize;
+    if light_count > MAX_LIGHTS {
+        return Err(ReplayError::Malformed("too many lights"));
+    }
+    let lights = (0..light_count).map(|_| read_light(data)).collect::<Result<Vec<_>, _>>()?;
+
+    let mut replay = Replay { seed, blocks, world, lights, ..Replay::default() };
+    replay.bot_settings.fill_lobby = read_bool(data)?;
+    replay.bot_settings.lobby_size = read_u32(data)? as usize;
+    replay.bot_settings.difficulty = read_bot_difficulty(data)?;
+    // Version 1 replays only have difficulties, their bots took the lowest free IDs in order
+    let bot_count = read_u8(data)?;
+    for index in 0..bot_count {
+        let id = if version == 1 { FIRST_BOT_ID + index as i32 } else { read_i32(data)? };
+        if id < FIRST_BOT_ID || replay.bots.last().is_some_and(|(previous, _)| id <= *previous) {
+            return Err(ReplayError::Malformed("invalid bot"));
+        }
+        replay.bots.push((id, read_bot_difficulty(data)?));
+    }
+
+    let run_count = read_u32(data)?;
+    for _ in 0..run_count {
+        let length = read_u32(data)? as usize;
+        if length > REPLAY_MAX_TICKS - replay.ticks.len() {
+            return Err(ReplayError::Malformed("too many ticks"));
+        }
+        let tick = decode_tick(data)?;
+        replay.ticks.extend(std::iter::repeat_n(tick, length));
+    }
+    expect_end(data)?;
+    Ok(replay)
+}
+
+// Devices in ID order so equal input always encodes to equal bytes
+fn encode_tick(tick: &InputFrame) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_bool(&mut out, tick.toggle_bot_fill);
+    write_bool(&mut out, tick.cycle_bot_difficulty);
+    let mut ids: Vec<i32> = tick.players.keys().copied().collect();
+    ids.sort();
+    write_u8(&mut out, ids.len() as u8);
+    for id in ids {
+        let frame = tick.players[&id];
+        write_i32(&mut out, id);
+        write_f32(&mut out, frame.movement.x);
+        write_f32(&mut out, frame.movement.y);
+        write_bool(&mut out, frame.dash_pressed);
+        write_bool(&mut out, frame.join_pressed);
+    }
+    out
+}
+
+fn decode_tick(data: &mut &[u8]) -> Result<InputFrame, ReplayError> {
+    let mut tick = InputFrame {
+        toggle_bot_fill: read_bool(data)?,
+        cycle_bot_difficulty: read_bool(data)?,
+        ..InputFrame::default()
+    };
+    let count = read_u8(data)?;
+    for _ in 0..count {
+        let id = read_i32(data)?;
+        if !(0..FIRST_BOT_ID).contains(&id) {
+            return Err(ReplayError::Malformed("invalid device"));
+        }
+        let movement = Vector2::new(read_f32(data)?, read_f32(data)?);
+        if !movement.x.is_finite() || !movement.y.is_finite() || movement.length() > 1.5 {
+            return Err(ReplayError::Malformed("invalid movement"));
+        }
+        tick.players.insert(id, PlayerInputFrame {
+            movement,
+            dash_pressed: read_bool(data)?,
+            join_pressed: read_bool(data)?,
+        });
+    }
+    Ok(tick)
+}
+
+fn bot_difficulty_id(difficulty: BotDifficulty) -> u8 {
+    match difficulty {
+        BotDifficulty::Easy => 0,
+        BotDifficulty::Normal => 1,
+        BotDifficulty::Hard => 2,
+    }
+}
+
+fn read_bot_difficulty(data: &mut &[u8]) -> Result<BotDifficulty, ReplayError> {
+    match read_u8(data)? {
+        0 => Ok(BotDifficulty::Easy),
+        1 => Ok(BotDifficulty::Normal),
+        2 => Ok(BotDifficulty::Hard),
+        _ => Err(ReplayError::Malformed("unknown bot difficulty")),
+    }
+}
+
+// Simulation state the replay starts from
+pub fn replay_start_state(replay: &Replay) -> GameState {
+    let mut state = GameState::default();
+    init(&mut state);
+    state.blocks = replay.blocks.clone();
+    replace_world(&mut state.world, replay.world.clone());
+    state.lights = replay.lights.clone();
+    state.bot_settings = replay.bot_settings;
+    seed_simulation(&mut state, replay.seed);
+    for (id, difficulty) in &replay.bots {
+        restore_bot(&mut state, *id, *difficulty);
+    }
+    state
+}
+
+// Watch a replay from the start, with the free camera circling the middle of the world
+pub fn start_playback(replay: Replay) -> (ReplayPlayback, GameState) {
+    let state = replay_start_state(&replay);
+    let mut playback = ReplayPlayback {
+        replay,
+        speed: 1.0,
+        checkpoints: vec![state.clone()],
+        ..ReplayPlayback::default()
+    };
+    playback.camera.center = Vector3::new(
+        state.world.width as f32 * state.world.voxel_size / 2.0,
+        0.0,
+        state.world.depth as f32 * state.world.voxel_size / 2.0,
+    );
+    (playback, state)
+}
+
+// Run the next recorded tick, returns false at the end of the replay
+pub fn step_playback(playback: &mut ReplayPlayback, state: &mut GameState) -> bool {
+    let Some(tick) = playback.replay.ticks.get(playback.tick) else {
+        return false;
+    };
+    let mut input = tick.clone();
+    let delta = 1.0 / TICK_RATE as f32;
+    update_bots(state, &mut input, delta);
+    update(state, &input, delta);
+    playback.tick += 1;
+
+    // Checkpoints are taken the first time a tick is reached
+    if playback.tick.is_multiple_of(REPLAY_CHECKPOINT_INTERVAL) && playback.checkpoints.len() == playback.tick / REPLAY_CHECKPOINT_INTERVAL {
+        playback.checkpoints.push(state.clone());
+    }
+    true
+}
+
+// Jump to `target` ticks into the replay. Going back, or far enough ahead to pass a checkpoint, restarts
+// from the closest checkpoint before the target and simulates the rest.
+pub fn seek_playback(playback: &mut ReplayPlayback, state: &mut GameState, target: usize) {
+    let target = target.min(playback.replay.ticks.len());
+    let checkpoint = (target / REPLAY_CHECKPOINT_INTERVAL).min(playback.checkpoints.len() - 1);
+    let checkpoint_tick = checkpoint * REPLAY_CHECKPOINT_INTERVAL;
+    if target < playback.tick || checkpoint_tick > playback.tick {
+        let next_version = state.world.next_chunk_version;
+        *state = playback.checkpoints[checkpoint].clone();
+        continue_chunk_versions(&mut state.world, next_version);
+        playback.tick = checkpoint_tick;
+    }
+    while playback.tick < target && step_playback(playback, state) {}
+    playback.accumulator = 0.0;
+}
+
+// Handle the playback keys, move the free camera and run as many ticks as the frame time calls for at the
+// current speed. Returns how far the display is between the last tick and the next, for interpolation.
+pub fn update_playback(playback: &mut ReplayPlayback, state: &mut GameState, input: &ReplayInputFrame, frame_time: f32) -> f32 {
+    if input.toggle_pause {
+        playback.paused = !playback.paused;
+    }
+    if input.speed_step != 0 {
+        playback.speed = (playback.speed * 2.0_f32.powi(input.speed_step)).clamp(REPLAY_MIN_SPEED, REPLAY_MAX_SPEED);
+    }
+    if input.restart {
+        seek_playback(playback, state, 0);
+    } else if input.seek_step != 0 {
+        let target = playback.tick as i64 + input.seek_step as i64 * REPLAY_SEEK_TICKS as i64;
+        seek_playback(playback, state, target.max(0) as usize);
+    }
+
+    let fixed_delta = 1.0 / TICK_RATE as f32;
+    if !playback.paused {
+        playback.accumulator += frame_time * playback.speed;
+        while playback.accumulator >= fixed_delta {
+            playback.accumulator -= fixed_delta;
+            if !step_playback(playback, state) {
+                playback.paused = true;
+                playback.accumulator = 0.0;
+            }
+        }
+    }
+
+    // The simulation moves its own cameras every tick, the free camera replaces them for drawing
+    let camera = update_replay_camera(playback, state.camera_state.camera, input, frame_time);
+    state.camera_state.split = false;
+    state.camera_state.camera = camera;
+    state.camera_state.previous_camera = camera;
+    playback.accumulator / fixed_delta
+}
+
+// Orbit camera like the editor's, WASD moves the point it circles around along the ground
+fn update_replay_camera(playback: &mut ReplayPlayback, mut camera: Camera3D, input: &ReplayInputFrame, frame_time: f32) -> Camera3D {
+    let orbit = &mut playback.camera;
+    if input.zoom != 0.0 {
+        orbit.distance = (orbit.distance * (1.0 - input.zoom * 0.1)).clamp(5.0, 50.0);
+    }
+    if input.rotate_down {
+        orbit.rotation.x -= input.mouse_delta.x * 0.01;
+        orbit.rotation.y = (orbit.rotation.y + input.mouse_delta.y * 0.01).clamp(-1.5, 1.5);
+    }
+
+    // Up on the screen moves away from the camera
+    let (yaw, pitch) = (orbit.rotation.x, orbit.rotation.y);
+    let forward = Vector3::new(-yaw.sin(), 0.0, -yaw.cos());
+    let right = Vector3::new(yaw.cos(), 0.0, -yaw.sin());
+    orbit.center += (right * input.pan.x - forward * input.pan.y) * REPLAY_CAMERA_SPEED * frame_time;
+
+    camera.target = orbit.center;
+    camera.position = orbit.center + Vector3::new(
+        orbit.distance * pitch.cos() * yaw.sin(),
+        orbit.distance * pitch.sin(),
+        orbit.distance * pitch.cos() * yaw.cos(),
+    );
+    camera
+}
+
+// Time into the replay, its length, speed and whether it is paused
+pub fn playback_status(playback: &ReplayPlayback) -> String {
+    let clock = |ticks: usize| {
+        let seconds = ticks / TICK_RATE as usize;
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    };
+    format!(
+        "Replay {} / {}, speed {}x{}",
+        clock(playback.tick),
+        clock(playback.replay.ticks.len()),
+        playback.speed,
+        if playback.paused { ", paused" } else { "" }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bots::{add_bot, remove_bot};
+
+    const SESSION_TICKS: usize = REPLAY_CHECKPOINT_INTERVAL + 150;
+
+    // Two players joining and running in circles, with the lobby filled by bots
+    fn session_input(tick: usize) -> InputFrame {
+        let mut input = InputFrame { toggle_bot_fill: tick == 1, ..InputFrame::default() };
+        let time = tick as f32 / TICK_RATE as f32;
+        for id in [0, 1] {
+            let angle = time * 0.7 + id as f32 * 1.3;
+            input.players.insert(id, PlayerInputFrame {
+                movement: Vector2::new(angle.cos(), angle.sin()),
+                dash_pressed: (tick + id as usize * 17).is_multiple_of(90),
+                join_pressed: tick == 0 || tick == 2,
+            });
+        }
+        input
+    }
+
+    // Simulate a session the way the game loop does while recording
+    fn record_session() -> (Replay, GameState) {
+        let mut state = GameState::default();
+        init(&mut state);
+        record_session_from(state)
+    }
+
+    fn record_session_from(mut state: GameState) -> (Replay, GameState) {
+        let mut replay = start_recording(&mut state);
+        let delta = 1.0 / TICK_RATE as f32;
+        for tick in 0..SESSION_TICKS {
+            let mut input = session_input(tick);
+            record_tick(&mut replay, &input);
+            update_bots(&mut state, &mut input, delta);
+            update(&mut state, &input, delta);
+        }
+        (replay, state)
+    }
+
+    fn assert_same_simulation(played: &GameState, recorded: &GameState) {
+        assert_eq!(played.players, recorded.players);
+        assert_eq!(played.match_state, recorded.match_state);
+        assert_eq!(played.world.voxels, recorded.world.voxels);
+    }
+
+    #[test]
+    fn encoding_round_trips() {
+        let (replay, _) = record_session();
+        let decoded = decode_replay(&encode_replay(&replay)).unwrap();
+        assert_eq!(decoded.seed, replay.seed);
+        assert_eq!(decoded.blocks, replay.blocks);
+        assert_eq!(decoded.world.voxels, replay.world.voxels);
+        assert_eq!(decoded.world.spawn_points, replay.world.spawn_points);
+        assert_eq!(decoded.lights, replay.lights);
+        assert_eq!(decoded.bot_settings, replay.bot_settings);
+        assert_eq!(decoded.bots, replay.bots);
+        assert_eq!(decoded.ticks.len(), replay.ticks.len());
+        for (decoded_tick, tick) in decoded.ticks.iter().zip(&replay.ticks) {
+            assert_eq!(encode_tick(decoded_tick), encode_tick(tick));
+        }
+    }
+
+    #[test]
+    fn truncated_replay_is_rejected() {
+        let (replay, _) = record_session();
+        let bytes = encode_replay(&replay);
+        assert!(matches!(decode_replay(&bytes[..bytes.len() - 1]), Err(ReplayError::Malformed(_))));
+        assert!(matches!(decode_replay(b"VBR"), Err(ReplayError::Format(FormatError::MissingHeader { .. }))));
+    }
+
+    #[test]
+    fn starting_bots_keep_their_ids() {
+        // Removing a bot leaves a gap the next one added would fill
+        let mut state = GameState::default();
+        init(&mut state);
+        for difficulty in [BotDifficulty::Easy, BotDifficulty::Normal, BotDifficulty::Hard] {
+            add_bot(&mut state, difficulty);
+        }
+        remove_bot(&mut state, FIRST_BOT_ID + 1);
+        let (replay, recorded) = record_session_from(state);
+        assert_eq!(replay.bots, vec![(FIRST_BOT_ID, BotDifficulty::Easy), (FIRST_BOT_ID + 2, BotDifficulty::Hard)]);
+
+        let decoded = decode_replay(&encode_replay(&replay)).unwrap();
+        assert_eq!(decoded.bots, replay.bots);
+        let (mut playback, mut state) = start_playback(decoded);
+        let mut ids: Vec<i32> = state.bots.keys().copied().collect();
+        ids.sort();
+        assert_eq!(ids, vec![FIRST_BOT_ID, FIRST_BOT_ID + 2]);
+        assert_eq!(state.bots[&(FIRST_BOT_ID + 2)].difficulty, BotDifficulty::Hard);
+        while step_playback(&mut playback, &mut state) {}
+        assert_same_simulation(&state, &recorded);
+    }
+
+    #[test]
+    fn version_1_bots_take_the_first_ids() {
+        let replay = Replay {
+            bots: vec![(FIRST_BOT_ID, BotDifficulty::Hard), (FIRST_BOT_ID + 1, BotDifficulty::Easy)],
+            ..Replay::default()
+        };
+        // A version 1 file lists only the difficulties, right before the run count
+        let mut bytes = encode_replay(&Replay { bots: Vec::new(), ..replay.clone() });
+        let runs = bytes.split_off(bytes.len() - 5);
+        bytes[4..8].copy_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&[2, bot_difficulty_id(BotDifficulty::Hard), bot_difficulty_id(BotDifficulty::Easy)]);
+        bytes.extend_from_slice(&runs[1..]);
+        assert_eq!(decode_replay(&bytes).unwrap().bots, replay.bots);
+
+        // Later versions need bot IDs in increasing order and out of the device range
+        let mut swapped = replay.clone();
+        swapped.bots.reverse();
+        assert!(matches!(decode_replay(&encode_replay(&swapped)), Err(ReplayError::Malformed("invalid bot"))));
+        let device = Replay { bots: vec![(FIRST_BOT_ID - 1, BotDifficulty::Normal)], ..Replay::default() };
+        assert!(matches!(decode_replay(&encode_replay(&device)), Err(ReplayError::Malformed("invalid bot"))));
+    }
+
+    #[test]
+    fn playback_matches_recorded_session() {
+        let (replay, recorded) = record_session();
+        let (mut playback, mut state) = start_playback(decode_replay(&encode_replay(&replay)).unwrap());
+        while step_playback(&mut playback, &mut state) {}
+        assert_eq!(playback.tick, SESSION_TICKS);
+        assert_same_simulation(&state, &recorded);
+
+        // Seeking back restarts from a checkpoint and has to arrive at the same end
+        seek_playback(&mut playback, &mut state, 100);
+        assert_eq!(playback.tick, 100);
+        seek_playback(&mut playback, &mut state, SESSION_TICKS);
+        assert_same_simulation(&state, &recorded);
+    }
+}
@@ -0,0 +1,145 @@
+use raylib::prelude::*;
+use raylib::ffi::ShaderLocationIndex::SHADER_LOC_VECTOR_VIEW;
+use raylib::ffi::ShaderUniformDataType;
+use std::collections::HashMap;
+use std::ffi::{c_void, CString};
+use std::fmt;
+use std::fs;
+use std::time::SystemTime;
+use crate::state::ManagedShader;
+use crate::config::GLSL_VERSION;
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Io { path: String, error: std::io::Error },
+    Compile { path: String }, // The compiler output is in raylib's log
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderError::Io { path, error } => write!(f, "{}: {}", path, error),
+            ShaderError::Compile { path } => write!(f, "{} failed to compile or link, see the shader log above", path),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+// Path of a file in the shader directory for the GLSL version in use
+pub fn shader_path(file: &str) -> String {
+    format!("resources/shaders/glsl{}/{}", GLSL_VERSION, file)
+}
+
+// Compile a shader from its files, falling back to raylib's default shader when that fails so the paths are
+// still watched and a fixed file gets picked up by `reload_shader`
+pub fn load_managed_shader(vs_path: Option<&str>, fs_path: &str) -> ManagedShader {
+    let modified = modified_time(vs_path, fs_path);
+    let shader = compile_shader(vs_path, fs_path).unwrap_or_else(|error| {
+        eprintln!("Failed to load shader, using the default shader until it is fixed: {}", error);
+        unsafe { ffi::Shader { id: ffi::rlGetShaderIdDefault(), locs: ffi::rlGetShaderLocsDefault() } }
+    });
+    ManagedShader {
+        shader,
+        vs_path: vs_path.map(str::to_string),
+        fs_path: fs_path.to_string(),
+        modified,
+        locations: HashMap::new(),
+    }
+}
+
+// Recompile the shader if its files changed since they were last compiled, returns whether it was replaced.
+// On failure the last good version keeps running and the files are retried once they change again.
+pub fn reload_shader(managed: &mut ManagedShader) -> Result<bool, ShaderError> {
+    let modified = modified_time(managed.vs_path.as_deref(), &managed.fs_path);
+    if modified == managed.modified {
+        return Ok(false);
+    }
+    managed.modified = modified;
+
+    let shader = compile_shader(managed.vs_path.as_deref(), &managed.fs_path)?;
+    unsafe {
+        // Leaves raylib's default shader alone
+        ffi::UnloadShader(managed.shader);
+    }
+    managed.shader = shader;
+    managed.locations.clear();
+    Ok(true)
+}
+
+// Location of a uniform, queried from the shader only the first time it is asked for
+pub fn uniform_location(managed: &mut ManagedShader, name: &str) -> i32 {
+    if let Some(location) = managed.locations.get(name) {
+        return *location;
+    }
+    let c_name = CString::new(name).unwrap();
+    let location = unsafe { ffi::GetShaderLocation(managed.shader, c_name.as_ptr()) };
+    managed.locations.insert(name.to_string(), location);
+    location
+}
+
+// One to four floats, set as a float up to a vec4
+pub fn set_uniform_floats(managed: &mut ManagedShader, name: &str, values: &[f32]) {
+    let uniform_type = match values.len() {
+        1 => ShaderUniformDataType::SHADER_UNIFORM_FLOAT,
+        2 => ShaderUniformDataType::SHADER_UNIFORM_VEC2,
+        3 => ShaderUniformDataType::SHADER_UNIFORM_VEC3,
+        _ => ShaderUniformDataType::SHADER_UNIFORM_VEC4,
+    };
+    let location = uniform_location(managed, name);
+    unsafe {
+        ffi::SetShaderValue(managed.shader, location, values.as_ptr() as *const c_void, uniform_type as i32);
+    }
+}
+
+pub fn set_uniform_int(managed: &mut ManagedShader, name: &str, value: i32) {
+    let location = uniform_location(managed, name);
+    let value = [value];
+    unsafe {
+        ffi::SetShaderValue(managed.shader, location, value.as_ptr() as *const c_void, ShaderUniformDataType::SHADER_UNIFORM_INT as i32);
+    }
+}
+
+pub fn set_uniform_matrix(managed: &mut ManagedShader, name: &str, matrix: Matrix) {
+    let location = uniform_location(managed, name);
+    unsafe {
+        ffi::SetShaderValueMatrix(managed.shader, location, matrix.into());
+    }
+}
+
+// Bound to a free texture unit when the next batch is drawn with this shader
+pub fn set_uniform_texture(managed: &mut ManagedShader, name: &str, texture: ffi::Texture) {
+    let location = uniform_location(managed, name);
+    unsafe {
+        ffi::SetShaderValueTexture(managed.shader, location, texture);
+    }
+}
+
+fn compile_shader(vs_path: Option<&str>, fs_path: &str) -> Result<ffi::Shader, ShaderError> {
+    let read = |path: &str| fs::read_to_string(path).map_err(|error| ShaderError::Io { path: path.to_string(), error });
+    let compile_error = || ShaderError::Compile { path: fs_path.to_string() };
+    let vs_code = vs_path.map(read).transpose()?.map(CString::new).transpose().map_err(|_| compile_error())?;
+    let fs_code = CString::new(read(fs_path)?).map_err(|_| compile_error())?;
+
+    let shader = unsafe {
+        ffi::LoadShaderFromMemory(vs_code.as_ref().map_or(std::ptr::null(), |code| code.as_ptr()), fs_code.as_ptr())
+    };
+    // raylib hands out its default shader when linking fails
+    if !unsafe { ffi::IsShaderValid(shader) } || shader.id == unsafe { ffi::rlGetShaderIdDefault() } {
+        return Err(compile_error());
+    }
+
+    // Shaders lighting with the camera position keep it in raylib's view location
+    let view_pos = CString::new("viewPos").unwrap();
+    unsafe {
+        *shader.locs.offset(SHADER_LOC_VECTOR_VIEW as isize) = ffi::GetShaderLocation(shader, view_pos.as_ptr());
+    }
+    Ok(shader)
+}
+
+// Newest modification time of the shader's files, None if none of them can be read
+fn modified_time(vs_path: Option<&str>, fs_path: &str) -> Option<SystemTime> {
+    vs_path.into_iter().chain([fs_path])
+        .filter_map(|path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok())
+        .max()
+}
@@ -1,11 +1,66 @@
 use raylib::prelude::*;
-use std::collections::HashMap;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::time::SystemTime;
+use crate::config::{
+    CHUNK_SIZE, DEFAULT_MAP_PATH, DEFAULT_BLOCK_HIT_POINTS, DEFAULT_LIGHT_RADIUS, DEFAULT_SPOT_CONE_ANGLE, PLAYER_MAX_HEALTH, ROUNDS_TO_WIN, BOT_LOBBY_SIZE,
+    MASTER_VOLUME, EFFECTS_VOLUME, MUSIC_VOLUME,
+};
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum VoxelType {
-    Empty,
-    Ground,
-    Wall,
+// ID of a block in the block registry, 0 is always empty space
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct VoxelType(pub u8);
+
+impl VoxelType {
+    pub const EMPTY: VoxelType = VoxelType(0);
+    pub const GROUND: VoxelType = VoxelType(1); // Floor of new worlds and terrain of generated arenas
+    pub const WALL: VoxelType = VoxelType(2); // Obstacles of generated arenas
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockProperties {
+    pub id: VoxelType,
+    pub name: String, // Single word, used in map files and the editor palette
+    pub color: Color, // Alpha below 255 draws the block see-through
+    pub solid: bool, // Blocks players, non-solid blocks are decoration
+    pub walkable: bool, // Players can step up onto it
+    pub destructible: bool, // Can be broken during a match
+    pub hit_points: f32, // Damage a destructible block takes before it breaks
+    pub friction: f32, // Grip multiplier while standing on the block, below 1 is slippery
+    pub bounce: f32, // Fraction of the fall speed returned as an upward bounce on landing
+    pub damage: f32, // Health lost per second while standing on the block
+}
+
+// Every block the world can contain, indexed by ID
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockRegistry {
+    pub blocks: Vec<BlockProperties>,
+}
+
+impl Default for BlockRegistry {
+    fn default() -> Self {
+        let block = |id: u8, name: &str, color: Color, solid: bool, walkable: bool, destructible: bool| BlockProperties {
+            id: VoxelType(id),
+            name: name.to_string(),
+            color,
+            solid,
+            walkable,
+            destructible,
+            hit_points: DEFAULT_BLOCK_HIT_POINTS,
+            friction: 1.0,
+            bounce: 0.0,
+            damage: 0.0,
+        };
+        Self {
+            blocks: vec![
+                block(0, "empty", Color::BLANK, false, false, false),
+                block(1, "ground", Color::GRAY, true, true, false),
+                block(2, "wall", Color::DARKGRAY, true, false, true),
+            ],
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -14,33 +69,109 @@ pub struct Voxel {
     pub voxel_type: VoxelType,
 }
 
+// Something that happened during a simulation tick, for systems that react to gameplay
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GameEvent {
+    VoxelBroken { position: (i32, i32, i32), voxel_type: VoxelType, player_id: i32 }, // A dash broke a destructible voxel
+    PlayerJoined { player_id: i32, position: Vector3 },
+    DashStarted { player_id: i32, position: Vector3 },
+    WallBumped { player_id: i32, position: Vector3, speed: f32 }, // A player ran into a voxel it couldn't step onto
+    VoxelPlaced { position: (i32, i32, i32), voxel_type: VoxelType }, // Editor edits, undo and redo included
+    VoxelRemoved { position: (i32, i32, i32), voxel_type: VoxelType },
+    PlayerEliminated { player_id: i32, position: Vector3 },
+    RoundStarted { round: u32 }, // The countdown ended and players can move
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ShaderType {
     Lighting,
-    Outline,
+    Shadowmap,
 }
 
+// Shader compiled from files on disk, recompiled when they change
 #[derive(Debug, Clone)]
+pub struct ManagedShader {
+    pub shader: ffi::Shader, // Last version that compiled, raylib's default shader if none did
+    pub vs_path: Option<String>, // None uses raylib's default vertex shader
+    pub fs_path: String,
+    pub modified: Option<SystemTime>, // Newest modification time of the files when they were last compiled
+    pub locations: HashMap<String, i32>, // Uniform locations looked up so far, cleared on reload
+}
+
+// Screen effects backed by the shader of the same name in resources/shaders
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PostEffectKind {
+    Outline,
+    Bloom,
+    Fog,
+    Posterization,
+    Scanlines,
+    Pixelizer,
+}
+
+// One step of the post-processing chain
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostEffect {
+    pub kind: PostEffectKind,
+    pub enabled: bool,
+    pub uniforms: Vec<(String, Vec<f32>)>, // Shader parameters with one to four values, set as float to vec4
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct World {
     pub width: i32,
     pub height: i32,
     pub depth: i32,
-    pub voxels: Vec<Voxel>,
+    pub voxels: Vec<VoxelType>, // Dense grid, one cell per (x, y, z), x varies fastest
     pub voxel_size: f32,
+    pub spawn_points: Vec<Vector3>, // Player spawn positions in world units
+    pub chunk_versions: Vec<u64>, // Changes whenever a voxel in or next to the chunk changes, one per render chunk
+    pub next_chunk_version: u64, // Given to the next changed chunk, replaced worlds continue from the old one's
 }
 
+// Empty 25x25x25 world, its chunks start at version 1 so meshes and navigation (at 0) get built
 impl Default for World {
     fn default() -> Self {
+        let size = 25;
+        let chunks = (size + CHUNK_SIZE - 1) / CHUNK_SIZE;
         Self {
-            width: 25,
-            height: 25,
-            depth: 25,
-            voxels: Vec::new(),
+            width: size,
+            height: size,
+            depth: size,
+            voxels: vec![VoxelType::EMPTY; (size * size * size) as usize],
             voxel_size: 1.0,
+            spawn_points: Vec::new(),
+            chunk_versions: vec![1; (chunks * chunks * chunks) as usize],
+            next_chunk_version: 2,
         }
     }
 }
 
+// Cells a player can stand in, kept in step with the world chunk by chunk
+#[derive(Debug, Clone, Default)]
+pub struct NavGrid {
+    pub width: i32,
+    pub height: i32,
+    pub depth: i32,
+    pub agent_size: Vector3, // Size of the player the grid is built for
+    pub walkable: Vec<bool>, // Indexed like World::voxels, true where a player's feet fit in the cell on walkable ground
+    pub chunk_versions: Vec<u64>, // World chunk versions the cells were computed from
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArenaPreset {
+    Flat,
+    Hills,
+    Maze,
+    Pillars,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArenaSettings {
+    pub seed: u32,
+    pub preset: ArenaPreset,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct PlayerState {
     pub position: Vector3,
@@ -53,7 +184,13 @@ pub struct PlayerState {
     pub dash_cooldown: f32, // Time remaining before next dash
     pub dash_direction: Vector3, // Direction of the current dash
     pub pre_dash_velocity: Vector3, // Velocity before starting a dash
-    pub is_ready: bool, // Whether the player is ready to spawn
+    pub is_ready: bool, // Whether the player is ready to start the match
+    pub previous_position: Vector3, // Position before the last simulation tick, used for render interpolation
+    pub health: f32,
+    pub is_alive: bool, // False once eliminated
+    pub hit_cooldown: f32, // Time remaining before the player can take another dash hit
+    pub is_grounded: bool, // Whether the player stands on a voxel
+    pub ground_block: VoxelType, // Block the player last landed on
 }
 
 impl Default for PlayerState {
@@ -70,25 +207,79 @@ impl Default for PlayerState {
             dash_direction: Vector3::zero(),
             pre_dash_velocity: Vector3::zero(),
             is_ready: false, // Players start not ready
+            previous_position: Vector3 { x: 25.0, y: 1.0, z: 25.0 },
+            health: PLAYER_MAX_HEALTH,
+            is_alive: true,
+            hit_cooldown: 0.0,
+            is_grounded: false,
+            ground_block: VoxelType::EMPTY,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchPhase {
+    Lobby, // Players join and toggle ready
+    Countdown, // Round about to start, players are frozen at their spawns
+    Playing,
+    RoundOver, // Showing the round winner
+    MatchOver, // Showing the match winner before going back to the lobby
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchState {
+    pub phase: MatchPhase,
+    pub phase_timer: f32, // Time remaining in timed phases
+    pub round: u32, // Current round number, 0 in the lobby
+    pub rounds_to_win: u32,
+    pub round_wins: HashMap<i32, u32>, // Rounds won by each player ID
+    pub round_winner: Option<i32>, // Winner of the last round, None for a draw
+    pub match_winner: Option<i32>,
+}
+
+impl Default for MatchState {
+    fn default() -> Self {
+        Self {
+            phase: MatchPhase::Lobby,
+            phase_timer: 0.0,
+            round: 0,
+            rounds_to_win: ROUNDS_TO_WIN,
+            round_wins: HashMap::new(),
+            round_winner: None,
+            match_winner: None,
         }
     }
 }
 
+// Values match the LIGHT_* defines of the lighting shaders
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightType {
+    Directional,
+    Point,
+    Spot,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct LightSource {
+    pub light_type: LightType,
     pub position: Vector3,
-    pub target: Vector3,
+    pub target: Vector3, // Directional and spot lights shine from the position towards the target
     pub color: Color,
     pub enabled: bool,
+    pub radius: f32, // Distance at which point and spot lights fade out
+    pub cone_angle: f32, // Half angle of a spot light's cone in degrees
 }
 
 impl Default for LightSource {
     fn default() -> Self {
         Self {
+            light_type: LightType::Directional,
             position: Vector3::new(25.0, 10.0, 0.0), // Side of the map, at medium height
             target: Vector3::new(12.5, 0.0, 12.5),  // Center of the map
             color: Color { r: 255, g: 220, b: 180, a: 255 }, // Warm white light
             enabled: true,
+            radius: DEFAULT_LIGHT_RADIUS,
+            cone_angle: DEFAULT_SPOT_CONE_ANGLE,
         }
     }
 }
@@ -118,12 +309,39 @@ impl Default for EditorCameraState {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditorTool {
+    Single, // One voxel per click, Ctrl+drag keeps removing
+    Box, // Drag to fill or erase a box
+    HollowBox, // Drag to fill or erase only the sides of a box, for walls around an area
+    Line, // Drag to fill or erase a straight line
+    FloodFill, // Click to fill the connected empty space or erase the connected voxels of the same type
+}
+
+// A single voxel change made in the editor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoxelEdit {
+    pub position: (i32, i32, i32),
+    pub old_type: VoxelType,
+    pub new_type: VoxelType,
+}
+
 #[derive(Debug, Clone)]
 pub struct EditorState {
     pub active: bool,
     pub camera: EditorCameraState,
     pub hovered_voxel: Option<(i32, i32, i32)>,
     pub build_mode: bool,
+    pub selected_block: VoxelType, // Block placed by the build tools
+    pub tool: EditorTool,
+    pub drag_start: Option<(i32, i32, i32)>, // Cell where the drag of a shape tool began
+    pub map_path: String, // File used by the save/load hotkeys
+    pub status_message: Option<String>, // Feedback shown after editor actions
+    pub status_timer: f32, // Time remaining before the status message is hidden
+    pub current_stroke: Vec<VoxelEdit>, // Edits made since the mouse button went down
+    pub undo_stack: VecDeque<Vec<VoxelEdit>>, // Finished strokes, most recent last, the oldest drop off the front
+    pub redo_stack: Vec<Vec<VoxelEdit>>, // Undone strokes, most recently undone last
+    pub selected_light: Option<usize>, // Index into GameState::lights moved by the light keys
 }
 
 impl Default for EditorState {
@@ -133,35 +351,114 @@ impl Default for EditorState {
             active: false,
             hovered_voxel: None,
             build_mode: true, // Start in build mode
+            selected_block: VoxelType::GROUND,
+            tool: EditorTool::Single,
+            drag_start: None,
+            map_path: DEFAULT_MAP_PATH.to_string(),
+            status_message: None,
+            status_timer: 0.0,
+            current_stroke: Vec::new(),
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            selected_light: None,
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    Shared, // One camera framing every player
+    Split, // One pane per player
+    Dynamic, // Splits when players spread apart, merges back when they come together
+}
+
+// Camera of one split-screen pane
+#[derive(Debug, Clone, Copy)]
+pub struct PlayerCamera {
+    pub player_id: i32,
+    pub camera: Camera3D,
+    pub previous_camera: Camera3D, // Camera before the last simulation tick, used for render interpolation
+}
+
 #[derive(Debug, Clone)]
 pub struct CameraState {
-    pub camera: Camera3D,
+    pub camera: Camera3D, // Shared camera, also used by the editor
+    pub previous_camera: Camera3D, // Camera before the last simulation tick, used for render interpolation
     pub offset: Vector3,
     pub height: f32,
     pub angle: f32,
+    pub mode: CameraMode,
+    pub split: bool, // Whether the screen is currently split into the player cameras
+    pub player_cameras: Vec<PlayerCamera>, // At most MAX_SPLIT_PANES, by player ID, kept up to date while split
 }
 
 impl Default for CameraState {
     fn default() -> Self {
+        let camera = Camera3D::perspective(
+            Vector3::new(25.0, 25.0, 25.0),
+            Vector3::new(12.5, 0.0, 12.5),
+            Vector3::new(0.0, 1.0, 0.0),
+            60.0,
+        );
         Self {
-            camera: Camera3D::perspective(
-                Vector3::new(25.0, 25.0, 25.0),
-                Vector3::new(12.5, 0.0, 12.5),
-                Vector3::new(0.0, 1.0, 0.0),
-                60.0,
-            ),
+            camera,
+            previous_camera: camera,
             offset: Vector3::new(0.0, 0.0, 15.0),
             height: 25.0,
             angle: 45.0,
+            mode: CameraMode::Shared,
+            split: false,
+            player_cameras: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BotDifficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+// Controller of one bot player, its decisions reach the logic as a PlayerInputFrame like a gamepad's
+#[derive(Debug, Clone, Copy)]
+pub struct BotState {
+    pub difficulty: BotDifficulty,
+    pub think_timer: f32, // Time until the next decision, longer on lower difficulties
+    pub input: PlayerInputFrame, // Last decision, its movement is held until the next one
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BotSettings {
+    pub fill_lobby: bool, // Add bots to the lobby until it has `lobby_size` players
+    pub lobby_size: usize,
+    pub difficulty: BotDifficulty, // Difficulty of bots added to fill the lobby
+}
+
+impl Default for BotSettings {
+    fn default() -> Self {
+        Self {
+            fill_lobby: false,
+            lobby_size: BOT_LOBBY_SIZE,
+            difficulty: BotDifficulty::Normal,
         }
     }
 }
 
+// Random choices of the simulation, seeded so a replay makes the same ones
 #[derive(Debug, Clone)]
+pub struct SimulationRng {
+    pub seed: u64,
+    pub rng: StdRng,
+}
+
+impl Default for SimulationRng {
+    fn default() -> Self {
+        Self { seed: 0, rng: StdRng::seed_from_u64(0) }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct GameState {
     pub players: HashMap<i32, PlayerState>,
     pub world: World,
@@ -169,25 +466,213 @@ pub struct GameState {
     pub next_player_id: i32,
     pub camera_state: CameraState,
     pub editor: EditorState,
-    pub shaders: HashMap<ShaderType, ffi::Shader>,
-    pub light_source: LightSource,
+    pub lights: Vec<LightSource>, // At most MAX_LIGHTS, the first enabled directional light casts shadows
+    pub blocks: BlockRegistry,
+    pub arena: Option<ArenaSettings>, // Settings of the generated arena, None for hand-built maps
+    pub match_state: MatchState,
+    pub voxel_damage: HashMap<(i32, i32, i32), f32>, // Damage taken so far by destructible voxels
+    pub round_world: Option<World>, // World as it was when the round started, broken voxels come back afterwards
+    pub events: Vec<GameEvent>, // Raised during the last update
+    pub bots: HashMap<i32, BotState>, // Bot controllers by player ID
+    pub bot_settings: BotSettings,
+    pub navigation: NavGrid, // Brought up to date with the world after every update
+    pub rng: SimulationRng,
+}
+
+// A recorded session: what the simulation started from and the input of every tick after that
+#[derive(Debug, Clone, Default)]
+pub struct Replay {
+    pub seed: u64,
+    pub blocks: BlockRegistry,
+    pub world: World,
+    pub lights: Vec<LightSource>,
+    pub bot_settings: BotSettings,
+    pub bots: Vec<(i32, BotDifficulty)>, // Bots added before the first tick by player ID, in ID order
+    pub ticks: Vec<InputFrame>, // Only the devices and keys that change the simulation are kept
+}
+
+// Replay being watched, the simulation itself is the regular GameState
+#[derive(Debug, Clone, Default)]
+pub struct ReplayPlayback {
+    pub replay: Replay,
+    pub tick: usize, // Ticks of the replay simulated so far
+    pub paused: bool,
+    pub speed: f32, // Simulated seconds per real second
+    pub accumulator: f32, // Simulated time not yet run as a tick
+    pub checkpoints: Vec<GameState>, // State every REPLAY_CHECKPOINT_INTERVAL ticks, seeking back starts from one
+    pub camera: EditorCameraState, // Free camera, independent of the players
 }
 
-impl Default for GameState {
+// GPU meshes of one render chunk, empty when the chunk has no faces of that kind
+#[derive(Debug, Clone, Default)]
+pub struct ChunkMeshes {
+    pub version: u64, // Chunk version the meshes were built from
+    pub opaque: Vec<ffi::Mesh>,
+    pub translucent: Vec<ffi::Mesh>,
+}
+
+// Render textures of the post-processing chain, all the size of the screen
+#[derive(Debug, Clone, Copy)]
+pub struct PostTargets {
+    pub scene: ffi::RenderTexture, // The 3D pass, with a depth texture effects can sample
+    pub ping_pong: [ffi::RenderTexture; 2], // Effects alternate between these, the last one draws to the screen
+}
+
+// GPU resources owned by the renderer, kept out of GameState so the simulation runs without a window
+#[derive(Debug, Clone, Default)]
+pub struct RenderState {
+    pub shaders: HashMap<ShaderType, ManagedShader>,
+    pub voxel_material: Option<ffi::Material>, // Default material using the lighting shader
+    pub chunk_meshes: Vec<ChunkMeshes>, // Indexed like World::chunk_versions
+    pub shadow_map: Option<ffi::RenderTexture>, // Depth-only framebuffer rendered from the light source
+    pub post_effects: Vec<(PostEffect, ManagedShader)>, // Enabled effects in the order they are applied
+    pub post_targets: Option<PostTargets>, // Created on first use and when the screen size changes
+    pub shader_check_time: f64, // Time of the last check for changed shader files
+    pub replay_status: Option<String>, // Position of the replay being watched, replaces the game controls help
+}
+
+// Sounds played for game events
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SoundEffect {
+    Join,
+    Dash,
+    Bump,
+    Break,
+    Place,
+    Remove,
+    Elimination,
+    RoundStart,
+}
+
+// Music played depending on the match phase
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Playlist {
+    Lobby,
+    Match,
+}
+
+// Sound file for an effect, paths are relative to resources/audio
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoundDefinition {
+    pub effect: SoundEffect,
+    pub path: String,
+    pub volume: f32, // Evens out files recorded at different levels
+}
+
+// Contents of the audio file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AudioConfig {
+    pub sounds: Vec<SoundDefinition>,
+    pub playlists: HashMap<Playlist, Vec<String>>, // Tracks played in order and then from the start again
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioSettings {
+    pub master_volume: f32,
+    pub effects_volume: f32,
+    pub music_volume: f32,
+    pub muted: bool,
+}
+
+impl Default for AudioSettings {
     fn default() -> Self {
         Self {
-            players: HashMap::new(),
-            world: World::default(),
-            player_inputs: HashMap::new(),
-            next_player_id: 0,
-            camera_state: CameraState::default(),
-            editor: EditorState::default(),
-            shaders: HashMap::new(),
-            light_source: LightSource::default(),
+            master_volume: MASTER_VOLUME,
+            effects_volume: EFFECTS_VOLUME,
+            music_volume: MUSIC_VOLUME,
+            muted: false,
         }
     }
 }
 
+// Audio device resources, kept out of GameState like the renderer's so the simulation runs without sound
+#[derive(Debug, Clone, Default)]
+pub struct AudioState {
+    pub device_ready: bool, // False when no audio device could be opened, nothing plays then
+    pub settings: AudioSettings,
+    pub sounds: HashMap<SoundEffect, (ffi::Sound, f32)>, // Loaded sounds with their volume from the audio file
+    pub playlists: HashMap<Playlist, Vec<String>>,
+    pub music: Option<ffi::Music>, // Track streaming now
+    pub playlist: Option<Playlist>, // Playlist of the track
+    pub track: usize, // Index of the track in its playlist
+}
+
+// Something a key, mouse button or gamepad control can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    // Player actions, bound separately for each keyboard player and the gamepads
+    MoveX,
+    MoveY,
+    Dash,
+    Join,
+    // Game actions, from the keyboard or any gamepad
+    ToggleEditor,
+    CycleCameraMode,
+    ToggleBotFill,
+    CycleBotDifficulty,
+    ToggleMute,
+    // Editor and replay actions, keyboard and mouse only
+    EditorPlace,
+    EditorToggleBuildMode,
+    EditorRotate, // Also turns the replay camera
+    EditorContinuousRemove,
+    EditorSaveMap,
+    EditorLoadMap,
+    EditorNextSeed,
+    EditorNextPreset,
+    EditorToggleSpawnPoint,
+    EditorToolSingle,
+    EditorToolBox,
+    EditorToolHollowBox,
+    EditorToolLine,
+    EditorToolFloodFill,
+    EditorPalette,
+    EditorUndo,
+    EditorRedo,
+    EditorToggleLight,
+    EditorSelectNextLight,
+    EditorLightX,
+    EditorLightY,
+    EditorLightZ,
+    EditorCycleLightType,
+    EditorCheckArena,
+    ReplayTogglePause,
+    ReplaySeek,
+    ReplaySpeed,
+    ReplayRestart,
+}
+
+// Devices with their own bindings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ControlDevice {
+    Keyboard, // Keyboard and mouse of the first player, also drives the editor and replays
+    SecondKeyboard, // Player sharing the keyboard, only exists while one of its actions is bound
+    Gamepad, // Every connected gamepad
+}
+
+// Key or button read by a binding
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Control {
+    Key(KeyboardKey),
+    Mouse(MouseButton),
+    GamepadButton(GamepadButton),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Binding {
+    Single(Control), // Button actions
+    WithCtrl(Control), // Button actions, only while either Ctrl key is held
+    Pair(Control, Control), // Axis actions, the negative then the positive direction
+    Axis { axis: GamepadAxis, inverted: bool }, // Axis actions, analog stick or trigger
+}
+
+// Bindings of every device from the controls file, any binding of an action triggers it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Controls {
+    pub bindings: HashMap<(ControlDevice, Action), Vec<Binding>>,
+    pub deadzones: [f32; 4], // Per gamepad, axis movement up to it counts as centered
+}
+
 #[derive(Debug)]
 #[derive(Clone)]
 pub struct PlayerInput {
@@ -196,4 +681,95 @@ pub struct PlayerInput {
 }
 
 // Define a type alias for player inputs
-pub type PlayerInputs = HashMap<i32, PlayerInput>;
\ No newline at end of file
+pub type PlayerInputs = HashMap<i32, PlayerInput>;
+// Input of one device (keyboard or gamepad) captured for a single frame
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PlayerInputFrame {
+    pub movement: Vector2, // Movement direction, x is right and y is down the screen
+    pub dash_pressed: bool,
+    pub join_pressed: bool,
+}
+
+// Replay playback keys and free camera controls captured for a single frame
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReplayInputFrame {
+    pub toggle_pause: bool, // Space
+    pub seek_step: i32, // Right / Left skip forward / back by REPLAY_SEEK_TICKS
+    pub speed_step: i32, // Up / Down double / halve the playback speed
+    pub restart: bool, // Home goes back to the start
+    pub pan: Vector2, // WASD moves the camera, x is right and y is down the screen
+    pub rotate_down: bool, // Middle mouse button held, the camera turns with the mouse
+    pub mouse_delta: Vector2,
+    pub zoom: f32, // Mouse wheel
+}
+
+// Editor mouse and hotkeys captured for a single frame
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EditorInputFrame {
+    pub mouse_ray: Ray, // Ray from the camera through the mouse cursor
+    pub mouse_delta: Vector2,
+    pub mouse_wheel: f32,
+    pub place_pressed: bool, // Left mouse button pressed this frame
+    pub place_down: bool, // Left mouse button held
+    pub toggle_build_mode: bool,
+    pub rotate_down: bool, // Middle mouse button held
+    pub continuous_remove: bool, // Ctrl held
+    pub save_map: bool,
+    pub load_map: bool,
+    pub next_seed: bool,
+    pub next_preset: bool,
+    pub toggle_spawn_point: bool,
+    pub select_tool: Option<EditorTool>, // Number keys 1-5
+    pub palette_step: i32, // Q / E move the block selection back and forth
+    pub undo: bool, // Ctrl+Z
+    pub redo: bool, // Ctrl+Y
+    pub toggle_light: bool, // L adds a point light above the hovered cell or deletes the light there
+    pub select_next_light: bool, // Tab
+    pub light_step: (i32, i32, i32), // Keypad arrows and Page Up / Down move the selected light one voxel
+    pub cycle_light_type: bool, // T switches the selected light between directional, point and spot
+    pub check_arena: bool, // F8 reports whether every walkable area and spawn point is connected
+}
+
+// Snapshot of every input the logic reads during one update
+#[derive(Debug, Clone, Default)]
+pub struct InputFrame {
+    pub players: HashMap<i32, PlayerInputFrame>, // Keyed by input ID: 0 is the keyboard, 1-4 are connected gamepads, 5 the second keyboard player
+    pub toggle_editor: bool,
+    pub cycle_camera_mode: bool, // C switches between shared, split and dynamic cameras
+    pub toggle_bot_fill: bool, // B in the lobby switches filling empty slots with bots
+    pub cycle_bot_difficulty: bool, // V in the lobby changes the difficulty of the bots
+    pub toggle_mute: bool, // N mutes and unmutes all sound, handled by the audio layer
+    pub editor: EditorInputFrame,
+}
+
+// Server side bookkeeping for one connected client
+#[derive(Debug, Clone)]
+pub struct ClientConnection {
+    pub player_id: i32,
+    pub pending_inputs: VecDeque<(u32, PlayerInputFrame)>, // Received inputs waiting for a tick, by sequence
+    pub last_received_input: u32, // Highest input sequence received
+    pub last_applied_input: u32, // Sequence of the input used by the latest tick, acknowledged in snapshots
+    pub held_input: PlayerInputFrame, // Reused when no new input arrived in time
+    pub last_seen: f32, // Server time of the last packet from this client
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ServerState {
+    pub clients: HashMap<SocketAddr, ClientConnection>,
+    pub tick: u32,
+    pub time: f32,
+    pub world_version: u32, // Increased whenever the world or light changes
+    pub sent_world: Option<(World, Vec<LightSource>, BlockRegistry)>, // World, lights and blocks the current version refers to
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ClientState {
+    pub player_id: Option<i32>, // Assigned by the server on connect
+    pub next_input_sequence: u32,
+    pub input_history: VecDeque<(u32, PlayerInputFrame)>, // Sent inputs not yet acknowledged, replayed after snapshots
+    pub world_version: Option<u32>, // Version of the world received from the server
+    pub world_download: Option<(u32, Vec<Option<Vec<u8>>>)>, // Parts received so far of a newer world version
+    pub last_snapshot_tick: u32,
+    pub time_since_packet: f32,
+    pub time_since_request: f32, // Time since the last connect or world request
+}
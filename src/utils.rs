@@ -2,7 +2,7 @@ use raylib::prelude::*;
 use rand::Rng;
 
 // Function to generate a random color from the predefined list
-pub fn generate_random_color(excluded_colors: &[Color], list_colors: &[Color]) -> Option<Color> {
+pub fn generate_random_color(rng: &mut impl Rng, excluded_colors: &[Color], list_colors: &[Color]) -> Option<Color> {
     // Find available colors
     let available_colors: Vec<Color> = list_colors.iter()
         .filter(|&&color| !excluded_colors.contains(&color))
@@ -12,7 +12,6 @@ pub fn generate_random_color(excluded_colors: &[Color], list_colors: &[Color]) -
     if available_colors.is_empty() {
         None
     } else {
-        let mut rng = rand::thread_rng();
         let index = rng.gen_range(0..available_colors.len());
         Some(available_colors[index])
     }